@@ -0,0 +1,661 @@
+use anyhow::Result;
+use n64_recomp::{Vec3f, Vec3s};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::types::ActorData;
+
+/// Longest we go without sending a full snapshot of an actor, so peers that
+/// joined late or missed a frame can pick it back up
+pub const KEYFRAME_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Registered message ID a receiver sends to an actor's owner when it can't
+/// apply a delta, carrying the actor's network ID
+pub const KEYFRAME_REQUEST_MESSAGE_ID: &str = "ns:keyframe";
+
+/// Shortest time between keyframe requests for the same actor, so a burst of
+/// unusable deltas only asks once
+pub const KEYFRAME_REQUEST_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Registered message ID a receiver sends to an actor's owner, naming the
+/// newest frame of each of its actors the receiver has
+pub const ACTOR_ACK_MESSAGE_ID: &str = "ns:actor_ack";
+
+/// Shortest time between acks to the same owner
+pub const ACK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Peers that haven't acked for this long stopped receiving the actor, or
+/// never acked at all, and no longer hold back its baseline
+const ACK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Frames kept per actor on both ends, about a second's worth; an ack older
+/// than that can't be diffed against
+const FRAME_HISTORY: usize = 64;
+
+// Delta field bits, also the order changed values are written in:
+// position, shape rotation, upper limb rotation, the 24 joints, mask, shield
+const FIELD_POSITION: u32 = 0;
+const FIELD_SHAPE_ROTATION: u32 = 1;
+const FIELD_UPPER_LIMB_ROT: u32 = 2;
+const FIELD_FIRST_JOINT: u32 = 3;
+const FIELD_MASK: u32 = FIELD_FIRST_JOINT + 24;
+const FIELD_SHIELD: u32 = FIELD_MASK + 1;
+
+/// How one of our actors should go out this frame
+pub enum ActorUpdate {
+    Keyframe {
+        seq: u32,
+    },
+    /// Only the fields in `mask` changed since frame `baseline`
+    Delta {
+        seq: u32,
+        baseline: u32,
+        mask: u32,
+        data: Vec<u8>,
+    },
+}
+
+impl ActorUpdate {
+    pub fn seq(&self) -> u32 {
+        match self {
+            ActorUpdate::Keyframe { seq } | ActorUpdate::Delta { seq, .. } => *seq,
+        }
+    }
+}
+
+/// Recent frames of one actor by sequence number, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct FrameHistory {
+    frames: VecDeque<(u32, ActorData)>,
+}
+
+impl FrameHistory {
+    pub fn push(&mut self, seq: u32, data: ActorData) {
+        self.frames.push_back((seq, data));
+        if self.frames.len() > FRAME_HISTORY {
+            self.frames.pop_front();
+        }
+    }
+
+    pub fn get(&self, seq: u32) -> Option<&ActorData> {
+        self.position(seq).map(|index| &self.frames[index].1)
+    }
+
+    fn position(&self, seq: u32) -> Option<usize> {
+        self.frames
+            .iter()
+            .rposition(|(frame_seq, _)| *frame_seq == seq)
+    }
+
+    fn latest_seq(&self) -> Option<u32> {
+        self.frames.back().map(|(seq, _)| *seq)
+    }
+}
+
+// The frames of an actor that made it onto the wire, and which of them
+// each peer has
+struct SentActor {
+    frames: FrameHistory,
+    last_keyframe: Instant,
+    keyframe_due: bool,
+    // Newest frame each peer acked, and when
+    acks: HashMap<String, (u32, Instant)>,
+}
+
+impl SentActor {
+    // The oldest frame a peer that is still acking said it has. Unset when
+    // no one is acking, or someone is further behind than our history.
+    fn acked_baseline(&self, now: Instant) -> Option<(u32, &ActorData)> {
+        let mut oldest = None;
+        for (seq, acked_at) in self.acks.values() {
+            if now.duration_since(*acked_at) >= ACK_TIMEOUT {
+                continue;
+            }
+
+            let index = self.frames.position(*seq)?;
+            oldest = Some(oldest.map_or(index, |oldest: usize| oldest.min(index)));
+        }
+
+        let (seq, data) = &self.frames.frames[oldest?];
+        Some((*seq, data))
+    }
+}
+
+/// Tracks what we sent for each of our actors and what peers acked of it,
+/// so later frames only carry what changed since a frame every receiver has.
+/// Receivers ack with `ACTOR_ACK_MESSAGE_ID` as frames arrive; until anyone
+/// has, or once someone falls further behind than the history we keep,
+/// frames go out in full. A peer that still can't apply a delta, because it
+/// missed the baseline, asks for a keyframe with `KEYFRAME_REQUEST_MESSAGE_ID`
+/// and drops deltas until one arrives.
+pub struct DeltaEncoder {
+    sent: HashMap<String, SentActor>,
+}
+
+impl DeltaEncoder {
+    pub fn new() -> Self {
+        Self {
+            sent: HashMap::new(),
+        }
+    }
+
+    /// Decide how to send `data` for `actor_id`. Nothing is recorded until
+    /// the frame is handed to `sent`.
+    pub fn prepare(&self, actor_id: &str, data: &ActorData, now: Instant) -> ActorUpdate {
+        let Some(actor) = self.sent.get(actor_id) else {
+            return ActorUpdate::Keyframe { seq: 1 };
+        };
+
+        // Zero means "no sequence" to peers, so skip it when wrapping
+        let seq = actor
+            .frames
+            .latest_seq()
+            .unwrap_or(0)
+            .wrapping_add(1)
+            .max(1);
+
+        if actor.keyframe_due || now.duration_since(actor.last_keyframe) >= KEYFRAME_INTERVAL {
+            return ActorUpdate::Keyframe { seq };
+        }
+
+        let Some((baseline, baseline_data)) = actor.acked_baseline(now) else {
+            return ActorUpdate::Keyframe { seq };
+        };
+
+        let (mask, changes) = encode_delta(baseline_data, data);
+        ActorUpdate::Delta {
+            seq,
+            baseline,
+            mask,
+            data: changes,
+        }
+    }
+
+    /// Record a frame the transport accepted, so peers can ack it
+    pub fn sent(&mut self, actor_id: &str, update: &ActorUpdate, data: &ActorData, now: Instant) {
+        let actor = self
+            .sent
+            .entry(actor_id.to_string())
+            .or_insert_with(|| SentActor {
+                frames: FrameHistory::default(),
+                last_keyframe: now,
+                keyframe_due: false,
+                acks: HashMap::new(),
+            });
+
+        if let ActorUpdate::Keyframe { .. } = update {
+            actor.last_keyframe = now;
+            actor.keyframe_due = false;
+        }
+        actor.frames.push(update.seq(), data.clone());
+    }
+
+    /// A peer has frame `seq` of `actor_id`, so later deltas can build on it
+    pub fn acked(&mut self, peer_id: &str, actor_id: &str, seq: u32, now: Instant) {
+        if let Some(actor) = self.sent.get_mut(actor_id) {
+            actor.acks.insert(peer_id.to_string(), (seq, now));
+        }
+    }
+
+    /// Stop waiting on acks from a peer that left
+    pub fn forget_peer(&mut self, peer_id: &str) {
+        for actor in self.sent.values_mut() {
+            actor.acks.remove(peer_id);
+        }
+    }
+
+    /// Send `actor_id` in full next time, for a peer that lost its baseline
+    pub fn force_keyframe(&mut self, actor_id: &str) {
+        if let Some(actor) = self.sent.get_mut(actor_id) {
+            actor.keyframe_due = true;
+        }
+    }
+
+    /// Send every actor in full next time, for peers that have nothing to
+    /// apply deltas to
+    pub fn force_keyframes(&mut self) {
+        for actor in self.sent.values_mut() {
+            actor.keyframe_due = true;
+        }
+    }
+}
+
+/// Payload of an `ACTOR_ACK_MESSAGE_ID` message: per actor, the frame's
+/// sequence number, then the actor ID's length and bytes
+pub fn encode_acks(acks: &[(&str, u32)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (actor_id, seq) in acks {
+        // Network actor IDs fit the mod's 64 byte buffers, so this never truncates
+        let id = &actor_id.as_bytes()[..actor_id.len().min(u8::MAX as usize)];
+        data.extend_from_slice(&seq.to_le_bytes());
+        data.push(id.len() as u8);
+        data.extend_from_slice(id);
+    }
+    data
+}
+
+pub fn decode_acks(data: &[u8]) -> Result<Vec<(String, u32)>> {
+    let mut reader = DeltaReader { buf: data };
+    let mut acks = Vec::new();
+    while !reader.buf.is_empty() {
+        let seq = u32::from_le_bytes(reader.take(4)?.try_into()?);
+        let len = reader.take(1)?[0] as usize;
+        let actor_id = std::str::from_utf8(reader.take(len)?)?;
+        acks.push((actor_id.to_string(), seq));
+    }
+    Ok(acks)
+}
+
+/// The fields of `current` that differ from `baseline`, as a bitmask and
+/// their new values in bit order
+pub fn encode_delta(baseline: &ActorData, current: &ActorData) -> (u32, Vec<u8>) {
+    let mut mask = 0;
+    let mut data = Vec::new();
+
+    if !vec3f_eq(&baseline.world_position, &current.world_position) {
+        mask |= 1 << FIELD_POSITION;
+        write_vec3f(&mut data, &current.world_position);
+    }
+    if !vec3s_eq(&baseline.shape_rotation, &current.shape_rotation) {
+        mask |= 1 << FIELD_SHAPE_ROTATION;
+        write_vec3s(&mut data, &current.shape_rotation);
+    }
+    if !vec3s_eq(&baseline.upper_limb_rot, &current.upper_limb_rot) {
+        mask |= 1 << FIELD_UPPER_LIMB_ROT;
+        write_vec3s(&mut data, &current.upper_limb_rot);
+    }
+    for (index, (old, new)) in baseline
+        .joint_table
+        .iter()
+        .zip(&current.joint_table)
+        .enumerate()
+    {
+        if !vec3s_eq(old, new) {
+            mask |= 1 << (FIELD_FIRST_JOINT + index as u32);
+            write_vec3s(&mut data, new);
+        }
+    }
+    if baseline.current_mask != current.current_mask {
+        mask |= 1 << FIELD_MASK;
+        data.push(current.current_mask as u8);
+    }
+    if baseline.current_shield != current.current_shield {
+        mask |= 1 << FIELD_SHIELD;
+        data.push(current.current_shield as u8);
+    }
+
+    (mask, data)
+}
+
+/// Rebuild a frame from the one it was diffed against
+pub fn apply_delta(baseline: &ActorData, mask: u32, data: &[u8]) -> Result<ActorData> {
+    if mask >> (FIELD_SHIELD + 1) != 0 {
+        return Err(anyhow::anyhow!(
+            "Unknown fields in actor delta: {:#x}",
+            mask
+        ));
+    }
+
+    let mut reader = DeltaReader { buf: data };
+    let mut actor = baseline.clone();
+    let changed = |field: u32| mask & (1 << field) != 0;
+
+    if changed(FIELD_POSITION) {
+        actor.world_position = reader.vec3f()?;
+    }
+    if changed(FIELD_SHAPE_ROTATION) {
+        actor.shape_rotation = reader.vec3s()?;
+    }
+    if changed(FIELD_UPPER_LIMB_ROT) {
+        actor.upper_limb_rot = reader.vec3s()?;
+    }
+    for (index, joint) in actor.joint_table.iter_mut().enumerate() {
+        if changed(FIELD_FIRST_JOINT + index as u32) {
+            *joint = reader.vec3s()?;
+        }
+    }
+    if changed(FIELD_MASK) {
+        actor.current_mask = reader.take(1)?[0] as i8;
+    }
+    if changed(FIELD_SHIELD) {
+        actor.current_shield = reader.take(1)?[0] as i8;
+    }
+
+    if !reader.buf.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Actor delta has {} unused bytes",
+            reader.buf.len()
+        ));
+    }
+
+    Ok(actor)
+}
+
+// Compare bit patterns so a NaN doesn't count as changed every frame
+fn vec3f_eq(a: &Vec3f, b: &Vec3f) -> bool {
+    a.x.to_bits() == b.x.to_bits()
+        && a.y.to_bits() == b.y.to_bits()
+        && a.z.to_bits() == b.z.to_bits()
+}
+
+fn vec3s_eq(a: &Vec3s, b: &Vec3s) -> bool {
+    a.x == b.x && a.y == b.y && a.z == b.z
+}
+
+fn write_vec3f(buf: &mut Vec<u8>, value: &Vec3f) {
+    for component in [value.x, value.y, value.z] {
+        buf.extend_from_slice(&component.to_le_bytes());
+    }
+}
+
+fn write_vec3s(buf: &mut Vec<u8>, value: &Vec3s) {
+    for component in [value.x, value.y, value.z] {
+        buf.extend_from_slice(&component.to_le_bytes());
+    }
+}
+
+struct DeltaReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> DeltaReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(anyhow::anyhow!("Actor delta truncated"));
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn vec3f(&mut self) -> Result<Vec3f> {
+        let mut component = || -> Result<f32> { Ok(f32::from_le_bytes(self.take(4)?.try_into()?)) };
+        Ok(Vec3f {
+            x: component()?,
+            y: component()?,
+            z: component()?,
+        })
+    }
+
+    fn vec3s(&mut self) -> Result<Vec3s> {
+        let mut component = || -> Result<i16> { Ok(i16::from_le_bytes(self.take(2)?.try_into()?)) };
+        Ok(Vec3s {
+            x: component()?,
+            y: component()?,
+            z: component()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actor_data() -> ActorData {
+        ActorData {
+            world_position: Vec3f {
+                x: 10.0,
+                y: 20.0,
+                z: -30.5,
+            },
+            shape_rotation: Vec3s {
+                x: 0,
+                y: 0x4000,
+                z: 0,
+            },
+            upper_limb_rot: Vec3s { x: 1, y: 2, z: 3 },
+            joint_table: std::array::from_fn(|i| Vec3s {
+                x: i as i16,
+                y: -(i as i16),
+                z: 0,
+            }),
+            current_mask: 0,
+            current_shield: 1,
+        }
+    }
+
+    // Whether two frames match, by diffing them with the codec under test
+    fn same(a: &ActorData, b: &ActorData) -> bool {
+        encode_delta(a, b).0 == 0
+    }
+
+    #[test]
+    fn unchanged_frame_encodes_empty() {
+        let data = actor_data();
+        let (mask, bytes) = encode_delta(&data, &data);
+        assert_eq!(mask, 0);
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn delta_round_trips() {
+        let baseline = actor_data();
+        let mut current = actor_data();
+        current.world_position.y = 21.25;
+        current.joint_table[0].z = -7;
+        current.joint_table[23].x = i16::MIN;
+        current.current_shield = -1;
+
+        let (mask, bytes) = encode_delta(&baseline, &current);
+        assert_eq!(
+            mask,
+            1 << FIELD_POSITION
+                | 1 << FIELD_FIRST_JOINT
+                | 1 << (FIELD_FIRST_JOINT + 23)
+                | 1 << FIELD_SHIELD
+        );
+        assert_eq!(bytes.len(), 12 + 6 + 6 + 1);
+
+        let rebuilt = apply_delta(&baseline, mask, &bytes).unwrap();
+        assert!(same(&rebuilt, &current));
+    }
+
+    #[test]
+    fn every_field_round_trips() {
+        let baseline = actor_data();
+        let mut current = actor_data();
+        current.world_position.x = -1.0;
+        current.shape_rotation.y = -0x8000;
+        current.upper_limb_rot.z = 0x7FFF;
+        for joint in current.joint_table.iter_mut() {
+            joint.z = 100;
+        }
+        current.current_mask = 5;
+        current.current_shield = 0;
+
+        let (mask, bytes) = encode_delta(&baseline, &current);
+        assert_eq!(mask, (1 << (FIELD_SHIELD + 1)) - 1);
+
+        let rebuilt = apply_delta(&baseline, mask, &bytes).unwrap();
+        assert!(same(&rebuilt, &current));
+    }
+
+    #[test]
+    fn nan_position_is_not_a_change() {
+        let mut data = actor_data();
+        data.world_position.x = f32::NAN;
+        assert_eq!(encode_delta(&data, &data.clone()).0, 0);
+    }
+
+    #[test]
+    fn apply_rejects_malformed_deltas() {
+        let baseline = actor_data();
+        let mut current = actor_data();
+        current.world_position.z = 0.0;
+        let (mask, bytes) = encode_delta(&baseline, &current);
+
+        assert!(apply_delta(&baseline, mask, &bytes[..bytes.len() - 1]).is_err());
+
+        let mut padded = bytes.clone();
+        padded.push(0);
+        assert!(apply_delta(&baseline, mask, &padded).is_err());
+
+        assert!(apply_delta(&baseline, 1 << (FIELD_SHIELD + 1), &[]).is_err());
+    }
+
+    #[test]
+    fn encoder_sends_deltas_between_keyframes() {
+        let mut encoder = DeltaEncoder::new();
+        let start = Instant::now();
+        let first = actor_data();
+
+        let update = encoder.prepare("player", &first, start);
+        assert!(matches!(update, ActorUpdate::Keyframe { seq: 1 }));
+        encoder.sent("player", &update, &first, start);
+        encoder.acked("peer", "player", 1, start);
+
+        let mut second = actor_data();
+        second.current_mask = 2;
+        let update = encoder.prepare("player", &second, start + Duration::from_millis(50));
+        let ActorUpdate::Delta {
+            seq,
+            baseline,
+            mask,
+            data,
+        } = &update
+        else {
+            panic!("expected a delta");
+        };
+        assert_eq!((*seq, *baseline), (2, 1));
+        assert!(same(&apply_delta(&first, *mask, data).unwrap(), &second));
+
+        let update = encoder.prepare("player", &second, start + KEYFRAME_INTERVAL);
+        assert!(matches!(update, ActorUpdate::Keyframe { seq: 2 }));
+    }
+
+    #[test]
+    fn unsent_frames_are_not_numbered() {
+        let mut encoder = DeltaEncoder::new();
+        let now = Instant::now();
+        let first = actor_data();
+        let update = encoder.prepare("player", &first, now);
+        encoder.sent("player", &update, &first, now);
+        encoder.acked("peer", "player", 1, now);
+
+        // A frame prepared but never accepted leaves no gap for peers
+        let mut dropped = actor_data();
+        dropped.current_mask = 4;
+        let _ = encoder.prepare("player", &dropped, now);
+
+        let update = encoder.prepare("player", &first, now);
+        assert!(matches!(
+            update,
+            ActorUpdate::Delta {
+                seq: 2,
+                baseline: 1,
+                mask: 0,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn unacked_frames_go_out_in_full() {
+        let mut encoder = DeltaEncoder::new();
+        let now = Instant::now();
+        let data = actor_data();
+        for seq in 1..=3 {
+            let update = encoder.prepare("player", &data, now);
+            assert_eq!(update.seq(), seq);
+            assert!(matches!(update, ActorUpdate::Keyframe { .. }));
+            encoder.sent("player", &update, &data, now);
+        }
+    }
+
+    #[test]
+    fn deltas_build_on_the_oldest_frame_peers_acked() {
+        let mut encoder = DeltaEncoder::new();
+        let start = Instant::now();
+        let now = start + ACK_TIMEOUT / 2;
+        let mut frames = Vec::new();
+        for seq in 1..=3 {
+            let mut data = actor_data();
+            data.world_position.x = seq as f32;
+            let update = encoder.prepare("player", &data, now);
+            encoder.sent("player", &update, &data, now);
+            frames.push(data);
+        }
+
+        encoder.acked("a", "player", 3, now);
+        encoder.acked("b", "player", 2, start);
+        let current = actor_data();
+        let update = encoder.prepare("player", &current, now);
+        let ActorUpdate::Delta {
+            seq,
+            baseline,
+            mask,
+            data,
+        } = &update
+        else {
+            panic!("expected a delta");
+        };
+        assert_eq!((*seq, *baseline), (4, 2));
+        assert!(same(
+            &apply_delta(&frames[1], *mask, data).unwrap(),
+            &current
+        ));
+
+        // A peer that stopped acking stops holding the baseline back
+        let later = start + ACK_TIMEOUT;
+        encoder.acked("a", "player", 3, later);
+        let update = encoder.prepare("player", &current, later);
+        assert!(matches!(update, ActorUpdate::Delta { baseline: 3, .. }));
+
+        // And one that left doesn't count at all
+        encoder.acked("b", "player", 1, later);
+        encoder.forget_peer("b");
+        let update = encoder.prepare("player", &current, later);
+        assert!(matches!(update, ActorUpdate::Delta { baseline: 3, .. }));
+    }
+
+    #[test]
+    fn ack_older_than_history_forces_keyframe() {
+        let mut encoder = DeltaEncoder::new();
+        let now = Instant::now();
+        let data = actor_data();
+        for _ in 0..FRAME_HISTORY {
+            let update = encoder.prepare("player", &data, now);
+            encoder.sent("player", &update, &data, now);
+            encoder.acked("peer", "player", 1, now);
+        }
+
+        let update = encoder.prepare("player", &data, now);
+        assert!(matches!(update, ActorUpdate::Delta { baseline: 1, .. }));
+        encoder.sent("player", &update, &data, now);
+
+        // Frame 1 has now been pushed out of the history
+        let update = encoder.prepare("player", &data, now);
+        assert!(matches!(update, ActorUpdate::Keyframe { .. }));
+    }
+
+    #[test]
+    fn acks_round_trip() {
+        let acks = [("player", 7), ("fairy", u32::MAX)];
+        let decoded = decode_acks(&encode_acks(&acks)).unwrap();
+        assert_eq!(
+            decoded,
+            vec![("player".to_string(), 7), ("fairy".to_string(), u32::MAX)]
+        );
+
+        assert!(decode_acks(&encode_acks(&acks)[..5]).is_err());
+        assert!(decode_acks(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn forced_keyframe_replaces_next_delta() {
+        let mut encoder = DeltaEncoder::new();
+        let now = Instant::now();
+        let data = actor_data();
+        let update = encoder.prepare("player", &data, now);
+        encoder.sent("player", &update, &data, now);
+        encoder.acked("peer", "player", 1, now);
+
+        encoder.force_keyframe("player");
+        let update = encoder.prepare("player", &data, now);
+        assert!(matches!(update, ActorUpdate::Keyframe { seq: 2 }));
+        encoder.sent("player", &update, &data, now);
+
+        let update = encoder.prepare("player", &data, now);
+        assert!(matches!(update, ActorUpdate::Delta { seq: 3, .. }));
+    }
+}
@@ -0,0 +1,548 @@
+use anyhow::Result;
+use n64_recomp::{mem_bu, mem_bu_write};
+use std::collections::{BTreeMap, HashMap};
+
+/// Registered message IDs carrying flag changes are this followed by the region name
+pub const FLAG_MESSAGE_PREFIX: &str = "ns:flags/";
+
+/// Session state keys holding the host's copy of a region are this followed
+/// by the region name, `#` and the chunk index
+pub const FLAG_STATE_PREFIX: &str = "ns:flags/";
+
+/// Longest region name, so message IDs and state keys fit the mod's buffers
+pub const MAX_REGION_NAME_LEN: usize = 31;
+
+/// Largest region that can be shared
+pub const MAX_REGION_BYTES: usize = 4096;
+
+/// Most regions that can be shared at once
+const MAX_REGIONS: usize = 32;
+
+/// Bytes of a region stored per session state value
+const SNAPSHOT_CHUNK_BYTES: usize = 1024;
+
+/// Changed bytes carried per message, keeping frames well under the server's limit
+const MAX_CHANGES_PER_MESSAGE: usize = 256;
+
+/// How remote changes to a shared region are merged into ours
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Flags only ever get set; whoever sets one sets it for everyone
+    Or = 1,
+    /// Sets and clears apply in the order they arrive
+    LastWriterWins = 2,
+    /// Only the host's changes apply; everyone else's go through the host
+    HostAuthoritative = 3,
+}
+
+impl MergePolicy {
+    pub fn from_code(code: u32) -> Option<Self> {
+        let policy = match code {
+            1 => MergePolicy::Or,
+            2 => MergePolicy::LastWriterWins,
+            3 => MergePolicy::HostAuthoritative,
+            _ => return None,
+        };
+
+        Some(policy)
+    }
+}
+
+// Bits of one byte that were set and cleared
+#[derive(Debug, Clone, Copy)]
+struct ByteChange {
+    offset: u16,
+    set: u8,
+    clear: u8,
+}
+
+struct FlagRegion {
+    addr: u64,
+    size: usize,
+    policy: MergePolicy,
+    /// Guest memory as of the last sync, which changes are diffed against.
+    /// Unset until the first sync in a session.
+    baseline: Option<Vec<u8>>,
+    /// Remote changes waiting for the next sync, with who sent them
+    incoming: Vec<(String, Vec<ByteChange>)>,
+    /// Chunks of the host's copy replayed to us on joining, by index
+    snapshot: BTreeMap<usize, Vec<u8>>,
+    /// What we last stored in the session state while hosting
+    published: Option<Vec<u8>>,
+}
+
+impl FlagRegion {
+    fn reset(&mut self) {
+        self.baseline = None;
+        self.incoming.clear();
+        self.snapshot.clear();
+        self.published = None;
+    }
+
+    // Whether `sender`'s changes count, and whether we pass them on
+    fn accepts(&self, sender: &str, host_id: Option<&str>, is_host: bool) -> (bool, bool) {
+        match self.policy {
+            MergePolicy::HostAuthoritative if host_id == Some(sender) => (true, false),
+            // Requests to the host, which it relays once applied
+            MergePolicy::HostAuthoritative => (is_host, true),
+            _ => (true, false),
+        }
+    }
+
+    fn merge(&self, current: &mut u8, baseline: &mut u8, set: u8, clear: u8, relay: bool) {
+        let merged = match self.policy {
+            MergePolicy::Or => *current | set,
+            _ => (*current | set) & !clear,
+        };
+        *current = merged;
+
+        // What we relay has to show up in the next diff
+        if !relay {
+            *baseline = match self.policy {
+                MergePolicy::Or => *baseline | set,
+                _ => (*baseline | set) & !clear,
+            };
+        }
+    }
+}
+
+/// What a sync wants sent
+#[derive(Default)]
+pub struct FlagOutput {
+    /// Changes for the rest of the session, as (message ID, payload)
+    pub messages: Vec<(String, Vec<u8>)>,
+    /// The host's copy of changed regions, as (state key, value)
+    pub state: Vec<(String, Vec<u8>)>,
+}
+
+/// Save context bitfields shared across the session. Once per frame the
+/// regions are read from guest memory, remote changes merged in, and what
+/// changed locally since the last frame is sent as set and clear bits.
+pub struct FlagSync {
+    regions: HashMap<String, FlagRegion>,
+}
+
+impl FlagSync {
+    pub fn new() -> Self {
+        Self {
+            regions: HashMap::new(),
+        }
+    }
+
+    /// Share `size` bytes at `addr` under `name`, replacing any earlier region of that name
+    pub fn share(&mut self, name: &str, addr: u64, size: usize, policy: MergePolicy) -> Result<()> {
+        if name.is_empty() || name.len() > MAX_REGION_NAME_LEN || name.contains('#') {
+            return Err(anyhow::anyhow!(
+                "Flag region names must be 1 to {} bytes without '#'",
+                MAX_REGION_NAME_LEN
+            ));
+        }
+
+        if size == 0 || size > MAX_REGION_BYTES {
+            return Err(anyhow::anyhow!(
+                "Flag regions must be 1 to {} bytes",
+                MAX_REGION_BYTES
+            ));
+        }
+
+        if !self.regions.contains_key(name) && self.regions.len() >= MAX_REGIONS {
+            return Err(anyhow::anyhow!(
+                "At most {} flag regions can be shared",
+                MAX_REGIONS
+            ));
+        }
+
+        self.regions.insert(
+            name.to_string(),
+            FlagRegion {
+                addr,
+                size,
+                policy,
+                baseline: None,
+                incoming: Vec::new(),
+                snapshot: BTreeMap::new(),
+                published: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn unshare(&mut self, name: &str) -> bool {
+        self.regions.remove(name).is_some()
+    }
+
+    /// Forget everything tied to the current session, for a fresh start in the next one
+    pub fn reset_session(&mut self) {
+        for region in self.regions.values_mut() {
+            region.reset();
+        }
+    }
+
+    /// Hold changes another member sent until the next sync
+    pub fn receive(&mut self, sender: &str, name: &str, data: &[u8]) -> Result<()> {
+        let Some(region) = self.regions.get_mut(name) else {
+            log::debug!("Ignoring changes to unshared flag region {}", name);
+            return Ok(());
+        };
+
+        let changes = decode_changes(data)?;
+        if let Some(change) = changes
+            .iter()
+            .find(|change| change.offset as usize >= region.size)
+        {
+            return Err(anyhow::anyhow!(
+                "Flag change at {} is outside region {} of {} bytes",
+                change.offset,
+                name,
+                region.size
+            ));
+        }
+
+        region.incoming.push((sender.to_string(), changes));
+        Ok(())
+    }
+
+    /// Hold a chunk of the host's copy of a region, replayed by the server
+    /// when we joined, until the next sync. `key` is the session state key.
+    pub fn receive_snapshot(&mut self, key: &str, value: &[u8]) {
+        let Some((name, index)) = key
+            .strip_prefix(FLAG_STATE_PREFIX)
+            .and_then(|rest| rest.split_once('#'))
+        else {
+            return;
+        };
+
+        let (Some(region), Ok(index)) = (self.regions.get_mut(name), index.parse::<usize>()) else {
+            return;
+        };
+
+        region.snapshot.insert(index, value.to_vec());
+    }
+
+    /// Merge held remote changes into guest memory and collect what changed
+    /// locally since the last sync
+    pub unsafe fn sync(
+        &mut self,
+        rdram: *mut u8,
+        client_id: &str,
+        host_id: Option<&str>,
+    ) -> FlagOutput {
+        let is_host = host_id == Some(client_id);
+        let mut output = FlagOutput::default();
+
+        for (name, region) in self.regions.iter_mut() {
+            let original: Vec<u8> = (0..region.size)
+                .map(|i| mem_bu(rdram, region.addr + i as u64))
+                .collect();
+            let mut current = original.clone();
+
+            // Under OR everything we have set is news to the session;
+            // otherwise we wait for the host's copy before saying anything
+            let mut baseline = region
+                .baseline
+                .take()
+                .unwrap_or_else(|| match region.policy {
+                    MergePolicy::Or => vec![0; region.size],
+                    _ => original.clone(),
+                });
+
+            // The host's copy from when we joined
+            for (index, chunk) in std::mem::take(&mut region.snapshot) {
+                let start = index * SNAPSHOT_CHUNK_BYTES;
+                for (i, &byte) in chunk.iter().enumerate() {
+                    let offset = start + i;
+                    if offset >= region.size {
+                        break;
+                    }
+                    let (set, clear) = (byte, !byte);
+                    region.merge(
+                        &mut current[offset],
+                        &mut baseline[offset],
+                        set,
+                        clear,
+                        false,
+                    );
+                }
+            }
+
+            // Everyone else's changes since the last frame
+            for (sender, changes) in std::mem::take(&mut region.incoming) {
+                let (accepted, relay) = region.accepts(&sender, host_id, is_host);
+                if !accepted {
+                    continue;
+                }
+
+                for change in changes {
+                    let offset = change.offset as usize;
+                    region.merge(
+                        &mut current[offset],
+                        &mut baseline[offset],
+                        change.set,
+                        change.clear,
+                        relay,
+                    );
+                }
+            }
+
+            for (i, (&old, &new)) in original.iter().zip(&current).enumerate() {
+                if old != new {
+                    mem_bu_write(rdram, region.addr + i as u64, new);
+                }
+            }
+
+            // Ours, plus requests we relay as host
+            let changes = diff(&baseline, &current, region.policy);
+            for chunk in changes.chunks(MAX_CHANGES_PER_MESSAGE) {
+                output.messages.push((
+                    format!("{}{}", FLAG_MESSAGE_PREFIX, name),
+                    encode_changes(chunk),
+                ));
+            }
+
+            baseline = match region.policy {
+                // A flag we cleared locally was still set for everyone else
+                MergePolicy::Or => baseline.iter().zip(&current).map(|(b, c)| b | c).collect(),
+                _ => current.clone(),
+            };
+            region.baseline = Some(baseline);
+
+            // Keep the session's copy current for whoever joins next
+            if is_host {
+                let published = region.published.take().unwrap_or_default();
+                for (index, chunk) in current.chunks(SNAPSHOT_CHUNK_BYTES).enumerate() {
+                    let start = index * SNAPSHOT_CHUNK_BYTES;
+                    if published.get(start..start + chunk.len()) != Some(chunk) {
+                        output.state.push((
+                            format!("{}{}#{}", FLAG_STATE_PREFIX, name, index),
+                            chunk.to_vec(),
+                        ));
+                    }
+                }
+                region.published = Some(current);
+            } else {
+                region.published = None;
+            }
+        }
+
+        output
+    }
+}
+
+fn diff(baseline: &[u8], current: &[u8], policy: MergePolicy) -> Vec<ByteChange> {
+    baseline
+        .iter()
+        .zip(current)
+        .enumerate()
+        .filter_map(|(offset, (&old, &new))| {
+            let set = new & !old;
+            let clear = match policy {
+                MergePolicy::Or => 0,
+                _ => old & !new,
+            };
+
+            (set != 0 || clear != 0).then_some(ByteChange {
+                offset: offset as u16,
+                set,
+                clear,
+            })
+        })
+        .collect()
+}
+
+// Each change is a u16 little endian offset, then the set and clear bits
+fn encode_changes(changes: &[ByteChange]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(changes.len() * 4);
+    for change in changes {
+        buf.extend_from_slice(&change.offset.to_le_bytes());
+        buf.push(change.set);
+        buf.push(change.clear);
+    }
+    buf
+}
+
+fn decode_changes(data: &[u8]) -> Result<Vec<ByteChange>> {
+    if !data.len().is_multiple_of(4) {
+        return Err(anyhow::anyhow!(
+            "Flag changes are {} bytes, not a multiple of 4",
+            data.len()
+        ));
+    }
+
+    Ok(data
+        .chunks_exact(4)
+        .map(|change| ByteChange {
+            offset: u16::from_le_bytes([change[0], change[1]]),
+            set: change[2],
+            clear: change[3],
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where the tests' region lives in guest memory
+    const REGION_ADDR: u64 = 0xFFFF_FFFF_8000_0000;
+
+    // Guest memory backing one region at `REGION_ADDR`
+    struct Guest {
+        memory: Vec<u8>,
+    }
+
+    impl Guest {
+        fn new() -> Self {
+            Self {
+                memory: vec![0; MAX_REGION_BYTES],
+            }
+        }
+
+        fn read(&mut self, offset: usize) -> u8 {
+            unsafe { mem_bu(self.memory.as_mut_ptr(), REGION_ADDR + offset as u64) }
+        }
+
+        fn write(&mut self, offset: usize, value: u8) {
+            unsafe { mem_bu_write(self.memory.as_mut_ptr(), REGION_ADDR + offset as u64, value) }
+        }
+
+        fn sync(&mut self, flags: &mut FlagSync, client_id: &str, host_id: &str) -> FlagOutput {
+            unsafe { flags.sync(self.memory.as_mut_ptr(), client_id, Some(host_id)) }
+        }
+    }
+
+    fn shared(size: usize, policy: MergePolicy) -> FlagSync {
+        let mut flags = FlagSync::new();
+        flags.share("week", REGION_ADDR, size, policy).unwrap();
+        flags
+    }
+
+    fn change(offset: u16, set: u8, clear: u8) -> Vec<u8> {
+        encode_changes(&[ByteChange { offset, set, clear }])
+    }
+
+    // (offset, set, clear) of every change in the messages a sync sent
+    fn sent(output: &FlagOutput) -> Vec<(u16, u8, u8)> {
+        output
+            .messages
+            .iter()
+            .flat_map(|(message_id, data)| {
+                assert_eq!(message_id, "ns:flags/week");
+                decode_changes(data).unwrap()
+            })
+            .map(|change| (change.offset, change.set, change.clear))
+            .collect()
+    }
+
+    #[test]
+    fn or_only_ever_sets() {
+        let mut guest = Guest::new();
+        let mut flags = shared(4, MergePolicy::Or);
+
+        // What we already hold is news to the session
+        guest.write(0, 0b0001);
+        let output = guest.sync(&mut flags, "a", "a");
+        assert_eq!(sent(&output), vec![(0, 0b0001, 0)]);
+
+        // Remote clears are ignored, and merged sets aren't sent back
+        flags
+            .receive("b", "week", &change(0, 0b0100, 0b0001))
+            .unwrap();
+        let output = guest.sync(&mut flags, "a", "a");
+        assert_eq!(guest.read(0), 0b0101);
+        assert!(sent(&output).is_empty());
+
+        // Nor are our own clears
+        guest.write(0, 0b0100);
+        let output = guest.sync(&mut flags, "a", "a");
+        assert!(sent(&output).is_empty());
+    }
+
+    #[test]
+    fn last_writer_wins_applies_sets_and_clears() {
+        let mut guest = Guest::new();
+        let mut flags = shared(4, MergePolicy::LastWriterWins);
+
+        // Without the host's copy yet, what we hold isn't sent
+        guest.write(1, 0b0011);
+        assert!(sent(&guest.sync(&mut flags, "b", "a")).is_empty());
+
+        flags
+            .receive("c", "week", &change(1, 0b0100, 0b0001))
+            .unwrap();
+        let output = guest.sync(&mut flags, "b", "a");
+        assert_eq!(guest.read(1), 0b0110);
+        assert!(sent(&output).is_empty());
+
+        guest.write(1, 0b1000);
+        let output = guest.sync(&mut flags, "b", "a");
+        assert_eq!(sent(&output), vec![(1, 0b1000, 0b0110)]);
+    }
+
+    #[test]
+    fn host_authoritative_goes_through_the_host() {
+        // The host applies a member's request and relays it to everyone
+        let mut host = Guest::new();
+        let mut host_flags = shared(4, MergePolicy::HostAuthoritative);
+        let output = host.sync(&mut host_flags, "a", "a");
+        assert_eq!(
+            output.state,
+            vec![("ns:flags/week#0".to_string(), vec![0; 4])]
+        );
+
+        host_flags.receive("b", "week", &change(2, 0b1, 0)).unwrap();
+        let output = host.sync(&mut host_flags, "a", "a");
+        assert_eq!(host.read(2), 0b1);
+        assert_eq!(sent(&output), vec![(2, 0b1, 0)]);
+        assert_eq!(
+            output.state,
+            vec![("ns:flags/week#0".to_string(), vec![0, 0, 1, 0])]
+        );
+
+        // Everyone else only takes the host's changes
+        let mut member = Guest::new();
+        let mut member_flags = shared(4, MergePolicy::HostAuthoritative);
+        member.sync(&mut member_flags, "b", "a");
+
+        member_flags
+            .receive("c", "week", &change(3, 0b1, 0))
+            .unwrap();
+        member_flags
+            .receive("a", "week", &change(2, 0b1, 0))
+            .unwrap();
+        let output = member.sync(&mut member_flags, "b", "a");
+        assert_eq!(member.read(3), 0);
+        assert_eq!(member.read(2), 0b1);
+        assert!(sent(&output).is_empty());
+        assert!(output.state.is_empty());
+    }
+
+    #[test]
+    fn snapshot_chunks_merge_on_the_next_sync() {
+        let mut guest = Guest::new();
+        let mut flags = shared(1500, MergePolicy::LastWriterWins);
+        guest.write(0, 0xFF);
+
+        // The last chunk runs past the region's end, and chunks of regions
+        // we don't share are ignored
+        flags.receive_snapshot("ns:flags/week#1", &[0xAA; SNAPSHOT_CHUNK_BYTES]);
+        flags.receive_snapshot("ns:flags/week#0", &[0x01; SNAPSHOT_CHUNK_BYTES]);
+        flags.receive_snapshot("ns:flags/other#0", &[0x02; SNAPSHOT_CHUNK_BYTES]);
+
+        let output = guest.sync(&mut flags, "b", "a");
+        assert_eq!(guest.read(0), 0x01);
+        assert_eq!(guest.read(SNAPSHOT_CHUNK_BYTES - 1), 0x01);
+        assert_eq!(guest.read(SNAPSHOT_CHUNK_BYTES), 0xAA);
+        assert_eq!(guest.read(1499), 0xAA);
+        assert_eq!(guest.read(1500), 0);
+        assert!(sent(&output).is_empty());
+    }
+
+    #[test]
+    fn changes_outside_the_region_are_refused() {
+        let mut flags = shared(4, MergePolicy::Or);
+        assert!(flags.receive("b", "week", &change(4, 0b1, 0)).is_err());
+        assert!(flags.receive("b", "week", &[0, 0, 1]).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+use n64_recomp::{Vec3f, Vec3s};
+use std::time::{Duration, Instant};
+
+use crate::types::{ActorData, ActorSnapshot, RemoteActorData};
+
+/// Default delay remote actors are rendered behind the newest sample
+pub const DEFAULT_INTERPOLATION_DELAY: Duration = Duration::from_millis(100);
+
+/// Snapshots kept per remote actor
+const MAX_SNAPSHOTS: usize = 32;
+
+/// Snapshots older than this are dropped, as long as two remain
+const SNAPSHOT_RETENTION: Duration = Duration::from_secs(1);
+
+/// Default for how far past the newest sample positions are extrapolated
+pub const DEFAULT_MAX_EXTRAPOLATION: Duration = Duration::from_millis(250);
+
+/// When remote actors that stopped updating should be hidden and forgotten
+#[derive(Debug, Clone, Copy)]
+pub struct StalenessPolicy {
+    pub hide_after: Duration,
+    /// Never removed when unset; they still go when their owner leaves
+    pub remove_after: Option<Duration>,
+}
+
+impl Default for StalenessPolicy {
+    fn default() -> Self {
+        Self {
+            hide_after: Duration::from_secs(2),
+            remove_after: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+impl RemoteActorData {
+    pub fn new(id: &str, owner_id: &str, data: ActorData, now: Instant) -> Self {
+        let mut actor = Self {
+            id: id.to_string(),
+            owner_id: owner_id.to_string(),
+            data: data.clone(),
+            last_update: now,
+            sync_seq: 0,
+            frames: Default::default(),
+            history: Default::default(),
+        };
+        actor.record(data, now);
+        actor
+    }
+
+    /// Store a new sample as the latest data and add it to the history
+    pub fn record(&mut self, data: ActorData, now: Instant) {
+        self.data = data.clone();
+        self.last_update = now;
+
+        self.history.push_back(ActorSnapshot {
+            data,
+            received_at: now,
+        });
+
+        while self.history.len() > MAX_SNAPSHOTS
+            || (self.history.len() > 2
+                && self
+                    .history
+                    .front()
+                    .is_some_and(|s| now.duration_since(s.received_at) > SNAPSHOT_RETENTION))
+        {
+            self.history.pop_front();
+        }
+    }
+
+    /// How long ago the newest sample arrived
+    pub fn age(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last_update)
+    }
+
+    /// World velocity in units per second, from the two newest samples
+    pub fn velocity(&self) -> Option<Vec3f> {
+        let count = self.history.len();
+        if count < 2 {
+            return None;
+        }
+
+        let from = &self.history[count - 2];
+        let to = &self.history[count - 1];
+        let dt = to
+            .received_at
+            .duration_since(from.received_at)
+            .as_secs_f32();
+        if dt <= 0.0 {
+            return None;
+        }
+
+        let (a, b) = (&from.data.world_position, &to.data.world_position);
+        Some(Vec3f {
+            x: (b.x - a.x) / dt,
+            y: (b.y - a.y) / dt,
+            z: (b.z - a.z) / dt,
+        })
+    }
+
+    /// The actor's state at `time`, blended between the samples around it.
+    /// Times before the history clamp to the oldest sample; times after it
+    /// carry the newest position along its velocity for up to `max_extrapolation`.
+    pub fn sample_at(&self, time: Instant, max_extrapolation: Duration) -> ActorData {
+        let newer_index = self
+            .history
+            .iter()
+            .position(|snapshot| snapshot.received_at > time);
+
+        match newer_index {
+            // Older than everything we have
+            Some(0) => self.history[0].data.clone(),
+            Some(index) => {
+                let from = &self.history[index - 1];
+                let to = &self.history[index];
+
+                let span = to.received_at.duration_since(from.received_at);
+                let elapsed = time.duration_since(from.received_at);
+                let t = if span.is_zero() {
+                    1.0
+                } else {
+                    elapsed.as_secs_f32() / span.as_secs_f32()
+                };
+
+                lerp_actor_data(&from.data, &to.data, t)
+            }
+            // Newer than everything we have
+            None => self.extrapolate(time, max_extrapolation),
+        }
+    }
+
+    // Dead reckon the position from the newest sample; everything else holds
+    fn extrapolate(&self, time: Instant, max_extrapolation: Duration) -> ActorData {
+        let mut data = self.data.clone();
+
+        if let Some(velocity) = self.velocity() {
+            let ahead = time
+                .saturating_duration_since(self.last_update)
+                .min(max_extrapolation)
+                .as_secs_f32();
+
+            data.world_position.x += velocity.x * ahead;
+            data.world_position.y += velocity.y * ahead;
+            data.world_position.z += velocity.z * ahead;
+        }
+
+        data
+    }
+}
+
+/// Blend two samples. Rotations take the shortest way around; discrete state
+/// like the mask and shield switches over halfway through.
+pub fn lerp_actor_data(from: &ActorData, to: &ActorData, t: f32) -> ActorData {
+    let joint_table = std::array::from_fn(|index| {
+        // Joint 0 holds the root translation rather than a rotation
+        if index == 0 {
+            lerp_vec3s(&from.joint_table[0], &to.joint_table[0], t)
+        } else {
+            lerp_rotation(&from.joint_table[index], &to.joint_table[index], t)
+        }
+    });
+
+    let discrete = if t < 0.5 { from } else { to };
+
+    ActorData {
+        world_position: lerp_vec3f(&from.world_position, &to.world_position, t),
+        shape_rotation: lerp_rotation(&from.shape_rotation, &to.shape_rotation, t),
+        upper_limb_rot: lerp_rotation(&from.upper_limb_rot, &to.upper_limb_rot, t),
+        joint_table,
+        current_mask: discrete.current_mask,
+        current_shield: discrete.current_shield,
+    }
+}
+
+fn lerp_vec3f(from: &Vec3f, to: &Vec3f, t: f32) -> Vec3f {
+    Vec3f {
+        x: from.x + (to.x - from.x) * t,
+        y: from.y + (to.y - from.y) * t,
+        z: from.z + (to.z - from.z) * t,
+    }
+}
+
+fn lerp_vec3s(from: &Vec3s, to: &Vec3s, t: f32) -> Vec3s {
+    let lerp = |a: i16, b: i16| (a as f32 + (b as f32 - a as f32) * t).round() as i16;
+
+    Vec3s {
+        x: lerp(from.x, to.x),
+        y: lerp(from.y, to.y),
+        z: lerp(from.z, to.z),
+    }
+}
+
+// Binary angles wrap at 0x10000, so the wrapped difference is the short way round
+fn lerp_rotation(from: &Vec3s, to: &Vec3s, t: f32) -> Vec3s {
+    let lerp = |a: i16, b: i16| {
+        let delta = b.wrapping_sub(a) as f32;
+        a.wrapping_add((delta * t).round() as i16)
+    };
+
+    Vec3s {
+        x: lerp(from.x, to.x),
+        y: lerp(from.y, to.y),
+        z: lerp(from.z, to.z),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rotation(x: i16, y: i16, z: i16) -> Vec3s {
+        Vec3s { x, y, z }
+    }
+
+    fn at_position(x: f32) -> ActorData {
+        ActorData {
+            world_position: Vec3f { x, y: 0.0, z: 0.0 },
+            shape_rotation: rotation(0, 0, 0),
+            upper_limb_rot: rotation(0, 0, 0),
+            joint_table: std::array::from_fn(|_| rotation(0, 0, 0)),
+            current_mask: 0,
+            current_shield: 0,
+        }
+    }
+
+    #[test]
+    fn rotation_takes_the_short_way_across_the_wrap() {
+        let from = rotation(0x7FF0, -0x7FF0, 0x7FFF);
+        let to = rotation(-0x7FF0, 0x7FF0, -0x8000);
+
+        let half = lerp_rotation(&from, &to, 0.5);
+        assert_eq!((half.x, half.y), (-0x8000, -0x8000));
+
+        let quarter = lerp_rotation(&from, &to, 0.25);
+        assert_eq!((quarter.x, quarter.y), (0x7FF8, -0x7FF8));
+    }
+
+    #[test]
+    fn rotation_hits_both_ends() {
+        let from = rotation(0x7FFF, 100, -0x8000);
+        let to = rotation(-0x8000, -100, 0x7FFF);
+
+        let start = lerp_rotation(&from, &to, 0.0);
+        assert_eq!((start.x, start.y, start.z), (0x7FFF, 100, -0x8000));
+
+        let end = lerp_rotation(&from, &to, 1.0);
+        assert_eq!((end.x, end.y, end.z), (-0x8000, -100, 0x7FFF));
+    }
+
+    #[test]
+    fn root_translation_is_not_wrapped() {
+        let mut from = at_position(0.0);
+        let mut to = at_position(0.0);
+        from.joint_table[0] = rotation(0x7FF0, 0, 0);
+        to.joint_table[0] = rotation(-0x7FF0, 0, 0);
+
+        // Joint 0 is a position, so it crosses zero rather than the wrap
+        assert_eq!(lerp_actor_data(&from, &to, 0.5).joint_table[0].x, 0);
+    }
+
+    #[test]
+    fn discrete_state_switches_halfway() {
+        let from = at_position(0.0);
+        let mut to = at_position(0.0);
+        to.current_mask = 4;
+
+        assert_eq!(lerp_actor_data(&from, &to, 0.49).current_mask, 0);
+        assert_eq!(lerp_actor_data(&from, &to, 0.5).current_mask, 4);
+    }
+
+    #[test]
+    fn sample_blends_between_snapshots() {
+        let start = Instant::now();
+        let mut actor = RemoteActorData::new("player", "p1", at_position(0.0), start);
+        actor.record(at_position(10.0), start + Duration::from_millis(100));
+
+        let before = actor.sample_at(start, Duration::ZERO);
+        assert_eq!(before.world_position.x, 0.0);
+
+        let between = actor.sample_at(start + Duration::from_millis(25), Duration::ZERO);
+        assert!((between.world_position.x - 2.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn extrapolation_is_capped() {
+        let start = Instant::now();
+        let mut actor = RemoteActorData::new("player", "p1", at_position(0.0), start);
+        actor.record(at_position(10.0), start + Duration::from_millis(100));
+
+        // 100 units per second, carried on for at most 50ms
+        let max = Duration::from_millis(50);
+        let ahead = actor.sample_at(start + Duration::from_millis(120), max);
+        assert!((ahead.world_position.x - 12.0).abs() < 1e-3);
+
+        let far = actor.sample_at(start + Duration::from_secs(5), max);
+        assert!((far.world_position.x - 15.0).abs() < 1e-3);
+
+        let held = actor.sample_at(start + Duration::from_secs(5), Duration::ZERO);
+        assert_eq!(held.world_position.x, 10.0);
+    }
+}
@@ -0,0 +1,692 @@
+use anyhow::Result;
+use n64_recomp::N64MemoryIO;
+use serde::{Deserialize, Serialize};
+
+/// Registered message ID carrying inventory changes
+pub const INVENTORY_MESSAGE_ID: &str = "ns:inventory";
+
+/// Session state key holding the host's inventory
+pub const INVENTORY_STATE_KEY: &str = "ns:inventory";
+
+/// Value of an empty item or mask slot
+const ITEM_NONE: u8 = 0xFF;
+
+/// Quest item bits counting heart pieces, synced as part of the hearts
+const HEART_PIECE_BITS: u32 = 0xF000_0000;
+const HEART_PIECE_SHIFT: u32 = 28;
+
+/// Health capacity of one heart
+const HEALTH_PER_HEART: i32 = 0x10;
+
+/// Fewest and most heart pieces a player can hold, counting full hearts as 4
+const MIN_HEART_PIECES: i32 = 3 * 4;
+const MAX_HEART_PIECES: i32 = 20 * 4;
+
+/// Shift and width of each upgrade in `upgrades`: quiver, bomb bag, strength,
+/// scale, wallet, deku stick and deku nut capacity
+const UPGRADE_FIELDS: [(u32, u32); 7] = [(0, 3), (3, 3), (6, 3), (9, 3), (12, 2), (14, 3), (17, 3)];
+
+/// Wallet upgrade's field in `UPGRADE_FIELDS`
+const WALLET_FIELD: usize = 4;
+
+/// Rupees each wallet upgrade holds
+const WALLET_CAPACITIES: [i32; 4] = [99, 200, 500, 500];
+
+/// The inventory as the mod copies it out of the save context
+#[repr(C)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, N64MemoryIO)]
+pub struct InventoryState {
+    pub items: [u8; 24],
+    pub masks: [u8; 24],
+    pub upgrades: u32,
+    pub quest_items: u32,
+    pub rupees: i16,
+    pub health_capacity: i16,
+}
+
+impl InventoryState {
+    fn upgrade(&self, field: usize) -> u32 {
+        let (shift, width) = UPGRADE_FIELDS[field];
+        (self.upgrades >> shift) & ((1 << width) - 1)
+    }
+
+    fn rupee_capacity(&self) -> i32 {
+        WALLET_CAPACITIES[self.upgrade(WALLET_FIELD) as usize]
+    }
+
+    // Full hearts count as 4 pieces
+    fn heart_pieces(&self) -> i32 {
+        (self.health_capacity as i32 / HEALTH_PER_HEART) * 4
+            + ((self.quest_items & HEART_PIECE_BITS) >> HEART_PIECE_SHIFT) as i32
+    }
+
+    fn set_heart_pieces(&mut self, pieces: i32) {
+        self.health_capacity = ((pieces / 4) * HEALTH_PER_HEART) as i16;
+        self.quest_items =
+            (self.quest_items & !HEART_PIECE_BITS) | (((pieces % 4) as u32) << HEART_PIECE_SHIFT);
+    }
+}
+
+/// Parts of the inventory that can be synced separately
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InventoryCategory {
+    Items = 1,
+    Masks = 2,
+    Upgrades = 3,
+    Quest = 4,
+    Rupees = 5,
+    Hearts = 6,
+}
+
+impl InventoryCategory {
+    pub fn from_code(code: u32) -> Option<Self> {
+        let category = match code {
+            1 => InventoryCategory::Items,
+            2 => InventoryCategory::Masks,
+            3 => InventoryCategory::Upgrades,
+            4 => InventoryCategory::Quest,
+            5 => InventoryCategory::Rupees,
+            6 => InventoryCategory::Hearts,
+            _ => return None,
+        };
+
+        Some(category)
+    }
+}
+
+/// How a category is synced
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InventoryMode {
+    /// Not synced
+    Private = 0,
+    /// What anyone gets, everyone gets; spending and using up stay local
+    Mirrored = 1,
+    /// One inventory for the whole session; gains and losses both apply
+    Shared = 2,
+}
+
+impl InventoryMode {
+    pub fn from_code(code: u32) -> Option<Self> {
+        let mode = match code {
+            0 => InventoryMode::Private,
+            1 => InventoryMode::Mirrored,
+            2 => InventoryMode::Shared,
+            _ => return None,
+        };
+
+        Some(mode)
+    }
+}
+
+/// What changed in one player's inventory during a frame
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct InventoryDelta {
+    /// (slot, item) pairs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    items: Vec<(u8, u8)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    masks: Vec<(u8, u8)>,
+    /// The sender's upgrades, which are merged by taking the best of each
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    upgrades: Option<u32>,
+    #[serde(default)]
+    quest_set: u32,
+    #[serde(default)]
+    quest_clear: u32,
+    #[serde(default)]
+    rupees: i32,
+    #[serde(default)]
+    heart_pieces: i32,
+}
+
+impl InventoryDelta {
+    fn is_empty(&self) -> bool {
+        self.items.is_empty()
+            && self.masks.is_empty()
+            && self.upgrades.is_none()
+            && self.quest_set == 0
+            && self.quest_clear == 0
+            && self.rupees == 0
+            && self.heart_pieces == 0
+    }
+}
+
+/// What a sync wants sent
+#[derive(Default)]
+pub struct InventoryOutput {
+    /// Our changes for the rest of the session
+    pub message: Option<Vec<u8>>,
+    /// Our inventory for the session state, when hosting and it changed
+    pub state: Option<Vec<u8>>,
+}
+
+/// The player's inventory synced across the session. Once per frame the mod
+/// hands over its inventory, remote changes are merged in, and what changed
+/// locally since the last frame is sent to everyone else.
+pub struct InventorySync {
+    items: InventoryMode,
+    masks: InventoryMode,
+    upgrades: InventoryMode,
+    quest: InventoryMode,
+    rupees: InventoryMode,
+    hearts: InventoryMode,
+    /// The inventory as of the last sync, which changes are diffed against.
+    /// Unset until the first sync in a session.
+    baseline: Option<InventoryState>,
+    /// Remote changes waiting for the next sync
+    incoming: Vec<InventoryDelta>,
+    /// The host's inventory replayed to us on joining
+    snapshot: Option<InventoryState>,
+    /// What we last stored in the session state while hosting
+    published: Option<InventoryState>,
+}
+
+impl InventorySync {
+    pub fn new() -> Self {
+        Self {
+            items: InventoryMode::Private,
+            masks: InventoryMode::Private,
+            upgrades: InventoryMode::Private,
+            quest: InventoryMode::Private,
+            rupees: InventoryMode::Private,
+            hearts: InventoryMode::Private,
+            baseline: None,
+            incoming: Vec::new(),
+            snapshot: None,
+            published: None,
+        }
+    }
+
+    pub fn set_mode(&mut self, category: InventoryCategory, mode: InventoryMode) {
+        let slot = match category {
+            InventoryCategory::Items => &mut self.items,
+            InventoryCategory::Masks => &mut self.masks,
+            InventoryCategory::Upgrades => &mut self.upgrades,
+            InventoryCategory::Quest => &mut self.quest,
+            InventoryCategory::Rupees => &mut self.rupees,
+            InventoryCategory::Hearts => &mut self.hearts,
+        };
+        *slot = mode;
+    }
+
+    fn is_enabled(&self) -> bool {
+        [
+            self.items,
+            self.masks,
+            self.upgrades,
+            self.quest,
+            self.rupees,
+            self.hearts,
+        ]
+        .iter()
+        .any(|mode| *mode != InventoryMode::Private)
+    }
+
+    /// Forget everything tied to the current session, for a fresh start in the next one
+    pub fn reset_session(&mut self) {
+        self.baseline = None;
+        self.incoming.clear();
+        self.snapshot = None;
+        self.published = None;
+    }
+
+    /// Hold changes another member sent until the next sync
+    pub fn receive(&mut self, data: &[u8]) -> Result<()> {
+        let delta: InventoryDelta = serde_json::from_slice(data)?;
+        if delta
+            .items
+            .iter()
+            .chain(&delta.masks)
+            .any(|(slot, _)| *slot >= 24)
+        {
+            return Err(anyhow::anyhow!("Inventory change to a slot past 24"));
+        }
+
+        self.incoming.push(delta);
+        Ok(())
+    }
+
+    /// Hold the host's inventory, replayed by the server when we joined,
+    /// until the next sync
+    pub fn receive_snapshot(&mut self, value: &[u8]) -> Result<()> {
+        self.snapshot = Some(serde_json::from_slice(value)?);
+        Ok(())
+    }
+
+    /// Merge held remote changes into `state` and collect what changed
+    /// locally since the last sync. Returns whether `state` was changed.
+    pub fn sync(&mut self, state: &mut InventoryState, is_host: bool) -> (bool, InventoryOutput) {
+        let mut output = InventoryOutput::default();
+        if !self.is_enabled() {
+            return (false, output);
+        }
+
+        let original = state.clone();
+        let mut baseline = self
+            .baseline
+            .take()
+            .unwrap_or_else(|| self.initial_baseline(&original));
+
+        // The host's inventory from when we joined
+        if let Some(snapshot) = self.snapshot.take() {
+            self.merge_snapshot(state, &mut baseline, &snapshot);
+        }
+
+        // Everyone else's changes since the last frame
+        for delta in std::mem::take(&mut self.incoming) {
+            self.merge(state, &mut baseline, &delta);
+        }
+
+        let delta = self.diff(&baseline, state);
+        if !delta.is_empty() {
+            match serde_json::to_vec(&delta) {
+                Ok(data) => output.message = Some(data),
+                Err(e) => log::error!("Failed to encode inventory changes: {}", e),
+            }
+        }
+        self.baseline = Some(state.clone());
+
+        // Keep the session's copy current for whoever joins next
+        if is_host {
+            if self.published.as_ref() != Some(state) {
+                match serde_json::to_vec(state) {
+                    Ok(data) => output.state = Some(data),
+                    Err(e) => log::error!("Failed to encode inventory: {}", e),
+                }
+                self.published = Some(state.clone());
+            }
+        } else {
+            self.published = None;
+        }
+
+        (*state != original, output)
+    }
+
+    // What the first sync of a session diffs against. Under mirroring
+    // everything we already hold is news to the session; gains in shared
+    // categories wait for the host's copy, and amounts only ever send changes.
+    fn initial_baseline(&self, state: &InventoryState) -> InventoryState {
+        let mut baseline = state.clone();
+
+        if self.items == InventoryMode::Mirrored {
+            baseline.items = [ITEM_NONE; 24];
+        }
+        if self.masks == InventoryMode::Mirrored {
+            baseline.masks = [ITEM_NONE; 24];
+        }
+        if self.upgrades == InventoryMode::Mirrored {
+            baseline.upgrades = 0;
+        }
+        if self.quest == InventoryMode::Mirrored {
+            baseline.quest_items &= HEART_PIECE_BITS;
+        }
+
+        baseline
+    }
+
+    // Bring the host's inventory in. Shared categories take the host's copy,
+    // mirrored ones keep the best of both.
+    fn merge_snapshot(
+        &self,
+        state: &mut InventoryState,
+        baseline: &mut InventoryState,
+        snapshot: &InventoryState,
+    ) {
+        let items = |mode, current: &mut [u8; 24], baseline: &mut [u8; 24], host: &[u8; 24]| {
+            for slot in 0..24 {
+                let take = match mode {
+                    InventoryMode::Shared => true,
+                    InventoryMode::Mirrored => current[slot] == ITEM_NONE,
+                    InventoryMode::Private => false,
+                };
+                if take {
+                    current[slot] = host[slot];
+                    baseline[slot] = host[slot];
+                }
+            }
+        };
+        items(
+            self.items,
+            &mut state.items,
+            &mut baseline.items,
+            &snapshot.items,
+        );
+        items(
+            self.masks,
+            &mut state.masks,
+            &mut baseline.masks,
+            &snapshot.masks,
+        );
+
+        if self.upgrades != InventoryMode::Private {
+            state.upgrades = best_upgrades(state.upgrades, snapshot.upgrades);
+            baseline.upgrades = best_upgrades(baseline.upgrades, snapshot.upgrades);
+        }
+
+        let host_quest = snapshot.quest_items & !HEART_PIECE_BITS;
+        match self.quest {
+            InventoryMode::Shared => {
+                state.quest_items = (state.quest_items & HEART_PIECE_BITS) | host_quest;
+                baseline.quest_items = (baseline.quest_items & HEART_PIECE_BITS) | host_quest;
+            }
+            InventoryMode::Mirrored => {
+                state.quest_items |= host_quest;
+                baseline.quest_items |= host_quest;
+            }
+            InventoryMode::Private => {}
+        }
+
+        if self.rupees == InventoryMode::Shared {
+            let rupees = (snapshot.rupees as i32).clamp(0, state.rupee_capacity());
+            baseline.rupees += (rupees - state.rupees as i32) as i16;
+            state.rupees = rupees as i16;
+        }
+
+        let pieces = match self.hearts {
+            InventoryMode::Shared => snapshot.heart_pieces(),
+            InventoryMode::Mirrored => state.heart_pieces().max(snapshot.heart_pieces()),
+            InventoryMode::Private => state.heart_pieces(),
+        };
+        let gained = pieces - state.heart_pieces();
+        state.set_heart_pieces(pieces);
+        baseline.set_heart_pieces(baseline.heart_pieces() + gained);
+    }
+
+    // Apply another player's changes to both our inventory and the baseline,
+    // so they aren't sent back out as ours
+    fn merge(
+        &self,
+        state: &mut InventoryState,
+        baseline: &mut InventoryState,
+        delta: &InventoryDelta,
+    ) {
+        let items =
+            |mode, current: &mut [u8; 24], baseline: &mut [u8; 24], changes: &[(u8, u8)]| {
+                for &(slot, item) in changes {
+                    let slot = slot as usize;
+                    let take = match mode {
+                        InventoryMode::Shared => true,
+                        // Item gets only, never over something we hold
+                        InventoryMode::Mirrored => item != ITEM_NONE && current[slot] == ITEM_NONE,
+                        InventoryMode::Private => false,
+                    };
+                    if take {
+                        current[slot] = item;
+                        baseline[slot] = item;
+                    }
+                }
+            };
+        items(
+            self.items,
+            &mut state.items,
+            &mut baseline.items,
+            &delta.items,
+        );
+        items(
+            self.masks,
+            &mut state.masks,
+            &mut baseline.masks,
+            &delta.masks,
+        );
+
+        if let (Some(upgrades), true) = (delta.upgrades, self.upgrades != InventoryMode::Private) {
+            state.upgrades = best_upgrades(state.upgrades, upgrades);
+            baseline.upgrades = best_upgrades(baseline.upgrades, upgrades);
+        }
+
+        let quest_set = delta.quest_set & !HEART_PIECE_BITS;
+        let quest_clear = match self.quest {
+            InventoryMode::Shared => delta.quest_clear & !HEART_PIECE_BITS,
+            _ => 0,
+        };
+        if self.quest != InventoryMode::Private {
+            state.quest_items = (state.quest_items | quest_set) & !quest_clear;
+            baseline.quest_items = (baseline.quest_items | quest_set) & !quest_clear;
+        }
+
+        // Amounts add up, within what the wallet holds
+        if accepts_amount(self.rupees, delta.rupees) {
+            let rupees = (state.rupees as i32 + delta.rupees).clamp(0, state.rupee_capacity());
+            baseline.rupees += (rupees - state.rupees as i32) as i16;
+            state.rupees = rupees as i16;
+        }
+
+        if accepts_amount(self.hearts, delta.heart_pieces) {
+            let pieces = (state.heart_pieces() + delta.heart_pieces)
+                .clamp(MIN_HEART_PIECES, MAX_HEART_PIECES);
+            let gained = pieces - state.heart_pieces();
+            state.set_heart_pieces(pieces);
+            baseline.set_heart_pieces(baseline.heart_pieces() + gained);
+        }
+    }
+
+    fn diff(&self, baseline: &InventoryState, state: &InventoryState) -> InventoryDelta {
+        let items = |mode, baseline: &[u8; 24], current: &[u8; 24]| -> Vec<(u8, u8)> {
+            baseline
+                .iter()
+                .zip(current)
+                .enumerate()
+                .filter(|(_, (old, new))| old != new)
+                .filter(|(_, (_, &new))| match mode {
+                    InventoryMode::Shared => true,
+                    InventoryMode::Mirrored => new != ITEM_NONE,
+                    InventoryMode::Private => false,
+                })
+                .map(|(slot, (_, &new))| (slot as u8, new))
+                .collect()
+        };
+
+        let mut delta = InventoryDelta {
+            items: items(self.items, &baseline.items, &state.items),
+            masks: items(self.masks, &baseline.masks, &state.masks),
+            ..Default::default()
+        };
+
+        if self.upgrades != InventoryMode::Private && state.upgrades != baseline.upgrades {
+            delta.upgrades = Some(state.upgrades);
+        }
+
+        if self.quest != InventoryMode::Private {
+            delta.quest_set = state.quest_items & !baseline.quest_items & !HEART_PIECE_BITS;
+        }
+        if self.quest == InventoryMode::Shared {
+            delta.quest_clear = baseline.quest_items & !state.quest_items & !HEART_PIECE_BITS;
+        }
+
+        let rupees = state.rupees as i32 - baseline.rupees as i32;
+        if accepts_amount(self.rupees, rupees) {
+            delta.rupees = rupees;
+        }
+
+        let heart_pieces = state.heart_pieces() - baseline.heart_pieces();
+        if accepts_amount(self.hearts, heart_pieces) {
+            delta.heart_pieces = heart_pieces;
+        }
+
+        delta
+    }
+}
+
+// Whether a change to an amount is synced: any under sharing, gains under mirroring
+fn accepts_amount(mode: InventoryMode, change: i32) -> bool {
+    match mode {
+        InventoryMode::Shared => change != 0,
+        InventoryMode::Mirrored => change > 0,
+        InventoryMode::Private => false,
+    }
+}
+
+// The better of each upgrade in `a` and `b`
+fn best_upgrades(a: u32, b: u32) -> u32 {
+    UPGRADE_FIELDS.iter().fold(a, |upgrades, &(shift, width)| {
+        let mask = ((1 << width) - 1) << shift;
+        let best = (a & mask).max(b & mask);
+        (upgrades & !mask) | best
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inventory() -> InventoryState {
+        InventoryState {
+            items: [ITEM_NONE; 24],
+            masks: [ITEM_NONE; 24],
+            upgrades: 0,
+            quest_items: 0,
+            rupees: 50,
+            health_capacity: 3 * HEALTH_PER_HEART as i16,
+        }
+    }
+
+    fn syncing(category: InventoryCategory, mode: InventoryMode) -> InventorySync {
+        let mut sync = InventorySync::new();
+        sync.set_mode(category, mode);
+        sync
+    }
+
+    fn receive(sync: &mut InventorySync, delta: InventoryDelta) {
+        sync.receive(&serde_json::to_vec(&delta).unwrap()).unwrap();
+    }
+
+    fn sent(output: &InventoryOutput) -> Option<InventoryDelta> {
+        output
+            .message
+            .as_ref()
+            .map(|data| serde_json::from_slice(data).unwrap())
+    }
+
+    fn upgrades(levels: &[(usize, u32)]) -> u32 {
+        levels.iter().fold(0, |upgrades, &(field, level)| {
+            upgrades | (level << UPGRADE_FIELDS[field].0)
+        })
+    }
+
+    #[test]
+    fn best_upgrades_takes_each_field_separately() {
+        let a = upgrades(&[(0, 2), (WALLET_FIELD, 1), (2, 1)]);
+        let b = upgrades(&[(0, 1), (WALLET_FIELD, 2), (3, 3)]);
+        assert_eq!(
+            best_upgrades(a, b),
+            upgrades(&[(0, 2), (WALLET_FIELD, 2), (2, 1), (3, 3)])
+        );
+        assert_eq!(best_upgrades(a, b), best_upgrades(b, a));
+    }
+
+    #[test]
+    fn rupees_stay_within_the_wallet() {
+        let mut sync = syncing(InventoryCategory::Rupees, InventoryMode::Shared);
+        let mut state = inventory();
+        state.rupees = 90;
+        sync.sync(&mut state, false);
+
+        receive(
+            &mut sync,
+            InventoryDelta {
+                rupees: 20,
+                ..Default::default()
+            },
+        );
+        let (changed, output) = sync.sync(&mut state, false);
+        assert!(changed);
+        assert_eq!(state.rupees, 99);
+        assert!(sent(&output).is_none());
+
+        receive(
+            &mut sync,
+            InventoryDelta {
+                rupees: -200,
+                ..Default::default()
+            },
+        );
+        sync.sync(&mut state, false);
+        assert_eq!(state.rupees, 0);
+
+        // A bigger wallet holds more
+        state.upgrades = upgrades(&[(WALLET_FIELD, 1)]);
+        receive(
+            &mut sync,
+            InventoryDelta {
+                rupees: 150,
+                ..Default::default()
+            },
+        );
+        sync.sync(&mut state, false);
+        assert_eq!(state.rupees, 150);
+    }
+
+    #[test]
+    fn heart_pieces_carry_into_hearts() {
+        let mut sync = syncing(InventoryCategory::Hearts, InventoryMode::Shared);
+        let mut state = inventory();
+        state.set_heart_pieces(3 * 4 + 3);
+        sync.sync(&mut state, false);
+
+        receive(
+            &mut sync,
+            InventoryDelta {
+                heart_pieces: 1,
+                ..Default::default()
+            },
+        );
+        sync.sync(&mut state, false);
+        assert_eq!(state.health_capacity, 4 * HEALTH_PER_HEART as i16);
+        assert_eq!(state.quest_items & HEART_PIECE_BITS, 0);
+
+        receive(
+            &mut sync,
+            InventoryDelta {
+                heart_pieces: -1,
+                ..Default::default()
+            },
+        );
+        sync.sync(&mut state, false);
+        assert_eq!(state.heart_pieces(), 3 * 4 + 3);
+        assert_eq!(state.health_capacity, 3 * HEALTH_PER_HEART as i16);
+
+        // Completing a heart ourselves is sent as the one piece it took
+        state.health_capacity = 4 * HEALTH_PER_HEART as i16;
+        state.quest_items &= !HEART_PIECE_BITS;
+        let (_, output) = sync.sync(&mut state, false);
+        assert_eq!(sent(&output).unwrap().heart_pieces, 1);
+    }
+
+    #[test]
+    fn mirrored_items_only_fill_empty_slots() {
+        let mut sync = syncing(InventoryCategory::Items, InventoryMode::Mirrored);
+        let mut state = inventory();
+        state.items[0] = 5;
+
+        // What we hold is news to the session
+        let (_, output) = sync.sync(&mut state, false);
+        assert_eq!(sent(&output).unwrap().items, vec![(0, 5)]);
+
+        receive(
+            &mut sync,
+            InventoryDelta {
+                items: vec![(0, 7), (1, 9), (2, ITEM_NONE)],
+                ..Default::default()
+            },
+        );
+        let (_, output) = sync.sync(&mut state, false);
+        assert_eq!(&state.items[..3], &[5, 9, ITEM_NONE]);
+        assert!(sent(&output).is_none());
+
+        // Using an item up stays local
+        state.items[1] = ITEM_NONE;
+        let (_, output) = sync.sync(&mut state, false);
+        assert!(sent(&output).is_none());
+
+        // The host's copy only fills empty slots too
+        let mut host = inventory();
+        host.items[0] = 8;
+        host.items[3] = 4;
+        sync.receive_snapshot(&serde_json::to_vec(&host).unwrap())
+            .unwrap();
+        sync.sync(&mut state, false);
+        assert_eq!(&state.items[..4], &[5, ITEM_NONE, ITEM_NONE, 4]);
+    }
+}
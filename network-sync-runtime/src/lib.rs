@@ -1,14 +1,39 @@
+mod delta;
+mod flags;
+mod interpolation;
+mod inventory;
 mod messages;
 mod network;
+mod reliable;
+mod scheduler;
+mod schema;
+mod time_sync;
+mod transport;
 mod types;
 mod utils;
 
 use env_logger::Builder;
+use flags::MergePolicy;
+use interpolation::StalenessPolicy;
+use inventory::{InventoryCategory, InventoryMode, InventoryState};
 use n64_recomp::{mem_bu, mem_bu_write, N64MemoryIO, RecompContext};
 use network::get_network_sync;
+use reliable::DeliveryStatus;
+use schema::{validate_schema_name, FieldType};
 use std::panic;
-use types::ActorData;
-use utils::{execute_safely, with_network_sync, with_network_sync_mut};
+use std::time::Duration;
+use time_sync::TimeState;
+use types::{
+    ActorData, ActorPayload, ConnectResult, ConnectionState, JoinOptions, JoinResult,
+    PlayerPresence, SessionDetails,
+};
+use utils::{
+    execute_safely, is_queue_full, owner_arg, with_network_sync, with_network_sync_mut,
+    SEND_FAILED, SEND_QUEUED, SEND_QUEUE_FULL,
+};
+
+/// How long `NetworkSyncConnect` waits for the server to accept this build
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
 
 // C - API
 
@@ -43,21 +68,56 @@ pub extern "C" fn NetworkSyncConnect(rdram: *mut u8, ctx: *mut RecompContext) {
         let host = unsafe { ctx.get_arg_string(rdram, 0) };
         log::info!("Connecting to server: {}", host);
 
-        let result = with_network_sync_mut(
+        let waiter = with_network_sync_mut(
             |module| match module.connect(&host) {
-                Ok(_) => {
-                    log::info!("Successfully connected to {}", host);
-                    1i32
-                }
+                Ok(_) => Some(module.wait_for_handshake()),
                 Err(e) => {
                     log::error!("Failed to connect to {}: {}", host, e);
-                    0i32
+                    None
+                }
+            },
+            None,
+        );
+
+        // Wait for the server to accept this build without holding the module lock
+        let result = match waiter.map(|waiter| waiter.recv_timeout(HANDSHAKE_TIMEOUT)) {
+            Some(Ok(ConnectResult::Connected)) => {
+                log::info!("Successfully connected to {}", host);
+                ConnectResult::Connected
+            }
+            Some(Ok(result)) => result,
+            Some(Err(_)) => {
+                log::error!("Timed out waiting for {} to welcome us", host);
+                let _ = with_network_sync_mut(|module| module.disconnect(), Ok(()));
+                ConnectResult::Failed
+            }
+            None => ConnectResult::Failed,
+        };
+
+        ctx.set_return(result.code());
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncGetConnectRejectReason(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetConnectRejectReason", |ctx| {
+        let buffer = ctx.get_arg_u64(0);
+        let max_len = ctx.get_arg_u32(1) as usize;
+
+        let success = with_network_sync(
+            |module| match &module.connect_reject_reason {
+                Some(reason) => {
+                    unsafe {
+                        let _ = ctx.write_string_to_mem(rdram, buffer, reason, max_len);
+                    }
+                    1i32
                 }
+                None => 0i32,
             },
             0i32,
         );
 
-        ctx.set_return(result);
+        ctx.set_return(success);
     });
 }
 
@@ -84,6 +144,35 @@ pub extern "C" fn NetworkSyncDisconnect(_rdram: *mut u8, ctx: *mut RecompContext
     });
 }
 
+#[no_mangle]
+pub extern "C" fn NetworkSyncSetReconnectPolicy(_rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSetReconnectPolicy", |ctx| {
+        let max_attempts = ctx.get_arg_u32(0);
+        let initial_delay_ms = ctx.get_arg_u32(1);
+        let max_delay_ms = ctx.get_arg_u32(2);
+
+        log::info!(
+            "Reconnect policy: {} attempts, {}ms initial delay, {}ms max delay",
+            max_attempts,
+            initial_delay_ms,
+            max_delay_ms
+        );
+
+        let result = with_network_sync_mut(
+            |module| {
+                let policy = &mut module.reconnect_policy;
+                policy.max_attempts = max_attempts;
+                policy.initial_delay = Duration::from_millis(initial_delay_ms as u64);
+                policy.max_delay = Duration::from_millis(max_delay_ms.max(initial_delay_ms) as u64);
+                1i32
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
 #[no_mangle]
 pub extern "C" fn NetworkSyncGetClientId(rdram: *mut u8, ctx: *mut RecompContext) {
     execute_safely(ctx, "NetworkSyncGetClientId", |ctx| {
@@ -113,27 +202,102 @@ pub extern "C" fn NetworkSyncGetClientId(rdram: *mut u8, ctx: *mut RecompContext
     });
 }
 
+#[no_mangle]
+pub extern "C" fn NetworkSyncGetConnectionState(_rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetConnectionState", |ctx| {
+        let state = with_network_sync(
+            |module| module.connection_state(),
+            ConnectionState::Disconnected,
+        );
+        ctx.set_return(state as u32);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncGetDroppedMessageCount(_rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetDroppedMessageCount", |ctx| {
+        let count = with_network_sync(|module| module.dropped_messages, 0);
+        ctx.set_return(count);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncPollEvent(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncPollEvent", |ctx| {
+        let id_buffer = ctx.get_arg_u64(0);
+        let id_buffer_size = ctx.get_arg_u32(1) as usize;
+
+        let event = with_network_sync_mut(|module| module.poll_event(), None);
+
+        // Write the id the event is about, or an empty string if none
+        let code = match event {
+            Some(event) => {
+                unsafe {
+                    ctx.write_string_to_mem(rdram, id_buffer, event.subject(), id_buffer_size);
+                }
+                event.code()
+            }
+            None => {
+                if id_buffer_size > 0 {
+                    unsafe {
+                        mem_bu_write(rdram, id_buffer, 0);
+                    }
+                }
+                0
+            }
+        };
+
+        ctx.set_return(code);
+    });
+}
+
 #[no_mangle]
 pub extern "C" fn NetworkSyncJoinSession(rdram: *mut u8, ctx: *mut RecompContext) {
     execute_safely(ctx, "NetworkSyncJoinSession", |ctx| {
         let session_id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let password = unsafe { ctx.get_arg_string(rdram, 1) };
+        let max_players = ctx.get_arg_u32(2);
+        let timeout_ms = ctx.get_arg_u32(3);
+
+        // Empty password and 0 players mean no password and no limit
+        let options = JoinOptions {
+            password: (!password.is_empty()).then_some(password),
+            max_players: (max_players > 0).then_some(max_players),
+        };
 
         log::info!("Joining session {}", session_id);
 
-        let result = with_network_sync_mut(
-            |module| match module.join_session(&session_id) {
+        let (status, waiter) = with_network_sync_mut(
+            |module| match module.join_session(&session_id, options) {
                 Ok(_) => {
-                    log::info!("Successfully joined session {}", session_id);
-                    1i32
+                    log::info!("Queued join request for session {}", session_id);
+                    let waiter = (timeout_ms > 0).then(|| module.wait_for_join(&session_id));
+                    (SEND_QUEUED, waiter)
+                }
+                Err(e) if is_queue_full(&e) => {
+                    log::warn!("Outbound queue full, could not join {}", session_id);
+                    (SEND_QUEUE_FULL, None)
                 }
                 Err(e) => {
                     log::error!("Failed to join session {}: {}", session_id, e);
-                    0i32
+                    (SEND_FAILED, None)
                 }
             },
-            0i32,
+            (SEND_FAILED, None),
         );
 
+        // Wait for the server's answer without holding the module lock
+        let result = match waiter {
+            Some(waiter) => match waiter.recv_timeout(Duration::from_millis(timeout_ms as u64)) {
+                Ok(result) => result.code(),
+                Err(_) => {
+                    log::warn!("Timed out waiting to join session {}", session_id);
+                    JoinResult::TimedOut.code()
+                }
+            },
+            None => status,
+        };
+
         ctx.set_return(result);
     });
 }
@@ -146,15 +310,19 @@ pub extern "C" fn NetworkSyncLeaveSession(_rdram: *mut u8, ctx: *mut RecompConte
         let result = with_network_sync_mut(
             |module| match module.leave_session() {
                 Ok(_) => {
-                    log::info!("Successfully left session");
-                    1i32
+                    log::info!("Queued request to leave session");
+                    SEND_QUEUED
+                }
+                Err(e) if is_queue_full(&e) => {
+                    log::warn!("Outbound queue full, could not leave session");
+                    SEND_QUEUE_FULL
                 }
                 Err(e) => {
                     log::error!("Failed to leave session: {}", e);
-                    0i32
+                    SEND_FAILED
                 }
             },
-            0i32,
+            SEND_FAILED,
         );
 
         ctx.set_return(result);
@@ -162,19 +330,45 @@ pub extern "C" fn NetworkSyncLeaveSession(_rdram: *mut u8, ctx: *mut RecompConte
 }
 
 #[no_mangle]
-pub extern "C" fn NetworkSyncEmitActorData(rdram: *mut u8, ctx: *mut RecompContext) {
-    execute_safely(ctx, "NetworkSyncEmitActorData", |ctx| {
-        let addr = ctx.get_arg_u64(0);
-        let player_data = unsafe { ActorData::read_from_mem(ctx, rdram, addr) };
+pub extern "C" fn NetworkSyncBanMember(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncBanMember", |ctx| {
+        let member_id = unsafe { ctx.get_arg_string(rdram, 0) };
 
         let result = with_network_sync_mut(
-            |module| match module.send_actor_sync(&player_data) {
-                Ok(_) => 1i32,
+            |module| match module.ban_member(&member_id) {
+                Ok(_) => SEND_QUEUED,
+                Err(e) if is_queue_full(&e) => {
+                    log::warn!("Outbound queue full, could not ban {}", member_id);
+                    SEND_QUEUE_FULL
+                }
                 Err(e) => {
-                    log::error!("Failed to send actor sync: {}", e);
-                    0i32
+                    log::error!("Failed to ban {}: {}", member_id, e);
+                    SEND_FAILED
                 }
             },
+            SEND_FAILED,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncSetSessionDetails(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSetSessionDetails", |ctx| {
+        let display_name = unsafe { ctx.get_arg_string(rdram, 0) };
+        let game_version = unsafe { ctx.get_arg_string(rdram, 1) };
+        let unlisted = ctx.get_arg_u32(2) != 0;
+
+        let result = with_network_sync_mut(
+            |module| {
+                module.session_details = SessionDetails {
+                    display_name: (!display_name.is_empty()).then_some(display_name),
+                    game_version: (!game_version.is_empty()).then_some(game_version),
+                    unlisted,
+                };
+                1i32
+            },
             0i32,
         );
 
@@ -183,19 +377,44 @@ pub extern "C" fn NetworkSyncEmitActorData(rdram: *mut u8, ctx: *mut RecompConte
 }
 
 #[no_mangle]
-pub extern "C" fn NetworkSyncGetRemoteActorIDs(rdram: *mut u8, ctx: *mut RecompContext) {
-    execute_safely(ctx, "NetworkSyncGetRemoteActorIDs", |ctx| {
-        let max_players = ctx.get_arg_u32(0);
-        let ids_buffer = ctx.get_arg_u64(1); // Get the virtual address
+pub extern "C" fn NetworkSyncRequestSessionList(_rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncRequestSessionList", |ctx| {
+        let result = with_network_sync_mut(
+            |module| match module.request_session_list() {
+                Ok(_) => SEND_QUEUED,
+                Err(e) if is_queue_full(&e) => {
+                    log::warn!("Outbound queue full, could not request session list");
+                    SEND_QUEUE_FULL
+                }
+                Err(e) => {
+                    log::error!("Failed to request session list: {}", e);
+                    SEND_FAILED
+                }
+            },
+            SEND_FAILED,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncListSessions(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncListSessions", |ctx| {
+        let max_sessions = ctx.get_arg_u32(0);
+        let ids_buffer = ctx.get_arg_u64(1);
         let id_buffer_size = ctx.get_arg_u32(2);
 
         let count = with_network_sync(
             |module| {
                 let mut count = 0;
 
-                if max_players > 0 {
-                    let actor_ids: Vec<&String> = module.remote_actors.keys().collect();
-                    let str_refs: Vec<&str> = actor_ids.iter().map(|s| s.as_str()).collect();
+                if max_sessions > 0 {
+                    let str_refs: Vec<&str> = module
+                        .session_list
+                        .iter()
+                        .map(|listing| listing.session_id.as_str())
+                        .collect();
 
                     unsafe {
                         count = ctx.write_string_array_to_mem(
@@ -203,7 +422,7 @@ pub extern "C" fn NetworkSyncGetRemoteActorIDs(rdram: *mut u8, ctx: *mut RecompC
                             ids_buffer,
                             &str_refs,
                             id_buffer_size as usize,
-                            max_players as usize,
+                            max_sessions as usize,
                         ) as i32;
                     }
                 }
@@ -218,23 +437,26 @@ pub extern "C" fn NetworkSyncGetRemoteActorIDs(rdram: *mut u8, ctx: *mut RecompC
 }
 
 #[no_mangle]
-pub extern "C" fn NetworkSyncGetRemoteActorData(rdram: *mut u8, ctx: *mut RecompContext) {
-    execute_safely(ctx, "NetworkSyncGetRemoteActorData", |ctx| {
-        let actor_id = unsafe { ctx.get_arg_string(rdram, 0) };
-        let data_buffer_addr = ctx.get_arg_u64(1);
+pub extern "C" fn NetworkSyncGetSessionDisplayName(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetSessionDisplayName", |ctx| {
+        let session_id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let name_buf = ctx.get_arg_u64(1);
+        let max_len = ctx.get_arg_u32(2) as usize;
 
         let success = with_network_sync(
-            |module| {
-                if let Some(remote_player) = module.remote_actors.get(&actor_id) {
+            |module| match module.find_session_listing(&session_id) {
+                Some(listing) => {
                     unsafe {
-                        remote_player
-                            .data
-                            .write_to_mem(ctx, rdram, data_buffer_addr);
+                        let _ = ctx.write_string_to_mem(
+                            rdram,
+                            name_buf,
+                            &listing.display_name,
+                            max_len,
+                        );
                     }
                     1i32
-                } else {
-                    0i32
                 }
+                None => 0i32,
             },
             0i32,
         );
@@ -244,25 +466,262 @@ pub extern "C" fn NetworkSyncGetRemoteActorData(rdram: *mut u8, ctx: *mut Recomp
 }
 
 #[no_mangle]
-pub extern "C" fn NetworkSyncEmitMessage(rdram: *mut u8, ctx: *mut RecompContext) {
-    execute_safely(ctx, "NetworkSyncEmitMessage", |ctx| {
-        let message_id = unsafe { ctx.get_arg_string(rdram, 0) };
+pub extern "C" fn NetworkSyncGetSessionGameVersion(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetSessionGameVersion", |ctx| {
+        let session_id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let version_buf = ctx.get_arg_u64(1);
+        let max_len = ctx.get_arg_u32(2) as usize;
+
+        let success = with_network_sync(
+            |module| match module.find_session_listing(&session_id) {
+                Some(listing) => {
+                    let version = listing.game_version.as_deref().unwrap_or_default();
+                    unsafe {
+                        let _ = ctx.write_string_to_mem(rdram, version_buf, version, max_len);
+                    }
+                    1i32
+                }
+                None => 0i32,
+            },
+            0i32,
+        );
+
+        ctx.set_return(success);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncGetSessionMemberCount(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetSessionMemberCount", |ctx| {
+        let session_id = unsafe { ctx.get_arg_string(rdram, 0) };
+
+        let members = with_network_sync(
+            |module| {
+                module
+                    .find_session_listing(&session_id)
+                    .map_or(0, |listing| listing.members)
+            },
+            0u32,
+        );
+
+        ctx.set_return(members);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncGetSessionCapacity(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetSessionCapacity", |ctx| {
+        let session_id = unsafe { ctx.get_arg_string(rdram, 0) };
+
+        // 0 when the session has no player limit
+        let capacity = with_network_sync(
+            |module| {
+                module
+                    .find_session_listing(&session_id)
+                    .and_then(|listing| listing.max_players)
+                    .unwrap_or(0)
+            },
+            0u32,
+        );
+
+        ctx.set_return(capacity);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncSetSessionState(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSetSessionState", |ctx| {
+        let key = unsafe { ctx.get_arg_string(rdram, 0) };
         let data_size = ctx.get_arg_u32(1) as usize;
         let data_ptr = ctx.get_arg_u64(2);
 
-        // Read the data from the provided pointer
-        let mut data = Vec::with_capacity(data_size);
+        let mut value = Vec::with_capacity(data_size);
         unsafe {
             for i in 0..data_size {
-                data.push(mem_bu(rdram, data_ptr + i as u64));
+                value.push(mem_bu(rdram, data_ptr + i as u64));
             }
         }
 
         let result = with_network_sync_mut(
-            |module| match module.send_message(&message_id, data) {
+            |module| match module.set_session_state(&key, value) {
+                Ok(_) => SEND_QUEUED,
+                Err(e) if is_queue_full(&e) => {
+                    log::warn!("Outbound queue full, dropping session state {}", key);
+                    SEND_QUEUE_FULL
+                }
+                Err(e) => {
+                    log::error!("Failed to set session state {}: {}", key, e);
+                    SEND_FAILED
+                }
+            },
+            SEND_FAILED,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncGetSessionState(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetSessionState", |ctx| {
+        let key = unsafe { ctx.get_arg_string(rdram, 0) };
+        let buffer = ctx.get_arg_u64(1);
+        let buffer_size = ctx.get_arg_u32(2) as usize;
+
+        // Full size of the value, even when only part of it fits; 0 if unset
+        let size = with_network_sync(
+            |module| match module.session_state.get(&key) {
+                Some(value) => {
+                    unsafe {
+                        for (i, &byte) in value.iter().take(buffer_size).enumerate() {
+                            mem_bu_write(rdram, buffer + i as u64, byte);
+                        }
+                    }
+                    value.len() as u32
+                }
+                None => 0,
+            },
+            0u32,
+        );
+
+        ctx.set_return(size);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncSetPresence(_rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSetPresence", |ctx| {
+        let presence = PlayerPresence {
+            scene: ctx.get_arg_u32(0),
+            room: ctx.get_arg_u32(1) as i32,
+            entrance: ctx.get_arg_u32(2),
+        };
+
+        let result = with_network_sync_mut(
+            |module| match module.set_presence(presence) {
+                Ok(_) => 1,
+                Err(e) => {
+                    log::error!("Failed to send presence: {}", e);
+                    0
+                }
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncSetAdjacentScenes(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSetAdjacentScenes", |ctx| {
+        let scene = ctx.get_arg_u32(0);
+        let scenes_ptr = ctx.get_arg_u64(1);
+        let count = ctx.get_arg_u32(2) as u64;
+
+        // Scene IDs are big endian u16s in guest memory
+        let adjacent: Vec<u32> = (0..count)
+            .map(|i| unsafe {
+                let addr = scenes_ptr + i * 2;
+                ((mem_bu(rdram, addr) as u32) << 8) | mem_bu(rdram, addr + 1) as u32
+            })
+            .collect();
+
+        let result = with_network_sync_mut(
+            |module| match module.set_adjacent_scenes(scene, adjacent) {
+                Ok(_) => 1,
+                Err(e) => {
+                    log::error!("Failed to set scenes adjacent to {}: {}", scene, e);
+                    0
+                }
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncGetPlayerPresence(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetPlayerPresence", |ctx| {
+        let player_id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let buffer = ctx.get_arg_u64(1);
+
+        let presence = with_network_sync(|module| module.presences.get(&player_id).copied(), None);
+
+        let result = match presence {
+            Some(presence) => {
+                unsafe { presence.write_to_mem(ctx, rdram, buffer) };
+                1i32
+            }
+            None => 0i32,
+        };
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncShareFlags(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncShareFlags", |ctx| {
+        let name = unsafe { ctx.get_arg_string(rdram, 0) };
+        let addr = ctx.get_arg_u64(1);
+        let size = ctx.get_arg_u32(2) as usize;
+        let policy_code = ctx.get_arg_u32(3);
+
+        let Some(policy) = MergePolicy::from_code(policy_code) else {
+            log::error!(
+                "Unknown merge policy {} for flag region '{}'",
+                policy_code,
+                name
+            );
+            ctx.set_return(0i32);
+            return;
+        };
+
+        let result = with_network_sync_mut(
+            |module| match module.flags.share(&name, addr, size, policy) {
+                Ok(_) => {
+                    log::info!(
+                        "Sharing {} bytes of flags as '{}' ({:?})",
+                        size,
+                        name,
+                        policy
+                    );
+                    1
+                }
+                Err(e) => {
+                    log::error!("Failed to share flag region '{}': {}", name, e);
+                    0
+                }
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncUnshareFlags(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncUnshareFlags", |ctx| {
+        let name = unsafe { ctx.get_arg_string(rdram, 0) };
+
+        let result = with_network_sync_mut(|module| module.flags.unshare(&name) as i32, 0i32);
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncSyncFlags(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSyncFlags", |ctx| {
+        let result = with_network_sync_mut(
+            |module| match unsafe { module.sync_flags(rdram) } {
                 Ok(_) => 1i32,
                 Err(e) => {
-                    log::error!("Failed to send message {}: {}", message_id, e);
+                    log::error!("Failed to sync flags: {}", e);
                     0i32
                 }
             },
@@ -273,6 +732,715 @@ pub extern "C" fn NetworkSyncEmitMessage(rdram: *mut u8, ctx: *mut RecompContext
     });
 }
 
+#[no_mangle]
+pub extern "C" fn NetworkSyncSetInventoryMode(_rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSetInventoryMode", |ctx| {
+        let category_code = ctx.get_arg_u32(0);
+        let mode_code = ctx.get_arg_u32(1);
+
+        let (Some(category), Some(mode)) = (
+            InventoryCategory::from_code(category_code),
+            InventoryMode::from_code(mode_code),
+        ) else {
+            log::error!(
+                "Unknown inventory category {} or mode {}",
+                category_code,
+                mode_code
+            );
+            ctx.set_return(0i32);
+            return;
+        };
+
+        let result = with_network_sync_mut(
+            |module| {
+                module.inventory.set_mode(category, mode);
+                log::info!("Inventory {:?} set to {:?}", category, mode);
+                1
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncSyncInventory(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSyncInventory", |ctx| {
+        let addr = ctx.get_arg_u64(0);
+        let mut state = unsafe { InventoryState::read_from_mem(ctx, rdram, addr) };
+
+        let changed = with_network_sync_mut(
+            |module| match module.sync_inventory(&mut state) {
+                Ok(changed) => changed,
+                Err(e) => {
+                    log::error!("Failed to sync inventory: {}", e);
+                    false
+                }
+            },
+            false,
+        );
+
+        if changed {
+            unsafe { state.write_to_mem(ctx, rdram, addr) };
+        }
+
+        ctx.set_return(changed as i32);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncSetTimeSync(_rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSetTimeSync", |ctx| {
+        let enabled = ctx.get_arg_u32(0) != 0;
+
+        let result = with_network_sync_mut(
+            |module| {
+                module.time.set_enabled(enabled);
+                log::info!("Time sync enabled: {}", enabled);
+                1
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncSyncTime(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSyncTime", |ctx| {
+        let addr = ctx.get_arg_u64(0);
+        let mut state = unsafe { TimeState::read_from_mem(ctx, rdram, addr) };
+
+        let changed = with_network_sync_mut(
+            |module| match module.sync_time(&mut state) {
+                Ok(changed) => changed,
+                Err(e) => {
+                    log::error!("Failed to sync time: {}", e);
+                    false
+                }
+            },
+            false,
+        );
+
+        if changed {
+            unsafe { state.write_to_mem(ctx, rdram, addr) };
+        }
+
+        ctx.set_return(changed as i32);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncProposeTimeTransition(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncProposeTimeTransition", |ctx| {
+        let kind = ctx.get_arg_u32(0);
+        let id_buffer = ctx.get_arg_u64(1);
+        let id_buffer_size = ctx.get_arg_u32(2) as usize;
+
+        let result = with_network_sync_mut(
+            |module| match module.propose_time_transition(kind) {
+                Ok(id) => {
+                    unsafe {
+                        let _ = ctx.write_string_to_mem(rdram, id_buffer, &id, id_buffer_size);
+                    }
+                    log::info!("Proposed time transition {} of kind {}", id, kind);
+                    1
+                }
+                Err(e) => {
+                    log::error!("Failed to propose time transition: {}", e);
+                    0
+                }
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncRespondTimeTransition(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncRespondTimeTransition", |ctx| {
+        let id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let accept = ctx.get_arg_u32(1) != 0;
+
+        let result = with_network_sync_mut(
+            |module| match module.respond_time_transition(&id, accept) {
+                Ok(_) => 1,
+                Err(e) => {
+                    log::error!("Failed to vote on time transition {}: {}", id, e);
+                    0
+                }
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncGetTimeTransitionKind(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetTimeTransitionKind", |ctx| {
+        let id = unsafe { ctx.get_arg_string(rdram, 0) };
+
+        let kind = with_network_sync(|module| module.time.transition_kind(&id).unwrap_or(0), 0u32);
+
+        ctx.set_return(kind);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncEmitActorData(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncEmitActorData", |ctx| {
+        let actor_id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let addr = ctx.get_arg_u64(1);
+        let player_data = unsafe { ActorData::read_from_mem(ctx, rdram, addr) };
+
+        let result = with_network_sync_mut(
+            |module| match module.queue_actor_update(&actor_id, ActorPayload::Player(player_data)) {
+                Ok(_) => SEND_QUEUED,
+                Err(e) if is_queue_full(&e) => {
+                    log::debug!("Outbound queue full, dropping actor sync for {}", actor_id);
+                    SEND_QUEUE_FULL
+                }
+                Err(e) => {
+                    log::error!("Failed to send actor sync: {}", e);
+                    SEND_FAILED
+                }
+            },
+            SEND_FAILED,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncRegisterSchema(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncRegisterSchema", |ctx| {
+        let schema_name = unsafe { ctx.get_arg_string(rdram, 0) };
+
+        if let Err(e) = validate_schema_name(&schema_name) {
+            log::error!("Failed to register schema '{}': {}", schema_name, e);
+            ctx.set_return(0i32);
+            return;
+        }
+
+        // Registering again starts the schema over
+        let result = with_network_sync_mut(
+            |module| {
+                module
+                    .schemas
+                    .insert(schema_name.clone(), Default::default());
+                1i32
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncAddSchemaField(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncAddSchemaField", |ctx| {
+        let schema_name = unsafe { ctx.get_arg_string(rdram, 0) };
+        let offset = ctx.get_arg_u32(1);
+        let type_code = ctx.get_arg_u32(2);
+        let count = ctx.get_arg_u32(3);
+
+        let Some(field_type) = FieldType::from_code(type_code) else {
+            log::error!(
+                "Unknown field type {} for schema '{}'",
+                type_code,
+                schema_name
+            );
+            ctx.set_return(0i32);
+            return;
+        };
+
+        let result = with_network_sync_mut(
+            |module| {
+                let Some(schema) = module.schemas.get_mut(&schema_name) else {
+                    log::error!("Schema '{}' is not registered", schema_name);
+                    return 0;
+                };
+
+                match schema.add_field(offset, field_type, count) {
+                    Ok(_) => 1,
+                    Err(e) => {
+                        log::error!("Failed to add field to schema '{}': {}", schema_name, e);
+                        0
+                    }
+                }
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncEmitActorState(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncEmitActorState", |ctx| {
+        let actor_id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let schema_name = unsafe { ctx.get_arg_string(rdram, 1) };
+        let actor_addr = ctx.get_arg_u64(2);
+
+        let result = with_network_sync_mut(
+            |module| {
+                let Some(schema) = module.schemas.get(&schema_name) else {
+                    log::error!("Schema '{}' is not registered", schema_name);
+                    return SEND_FAILED;
+                };
+
+                let payload = ActorPayload::Schema {
+                    schema: schema_name.clone(),
+                    data: unsafe { schema.read(rdram, actor_addr) },
+                };
+
+                match module.queue_actor_update(&actor_id, payload) {
+                    Ok(_) => SEND_QUEUED,
+                    Err(e) if is_queue_full(&e) => {
+                        log::debug!("Outbound queue full, dropping actor state for {}", actor_id);
+                        SEND_QUEUE_FULL
+                    }
+                    Err(e) => {
+                        log::error!("Failed to send actor state: {}", e);
+                        SEND_FAILED
+                    }
+                }
+            },
+            SEND_FAILED,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncGetRemoteActorState(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetRemoteActorState", |ctx| {
+        let owner_id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let actor_id = unsafe { ctx.get_arg_string(rdram, 1) };
+        let schema_name = unsafe { ctx.get_arg_string(rdram, 2) };
+        let actor_addr = ctx.get_arg_u64(3);
+
+        let success = with_network_sync_mut(
+            |module| {
+                // Actors that went quiet for too long are dropped first
+                module.remove_stale_actors();
+
+                let Some(state) = module.find_remote_state(owner_arg(&owner_id), &actor_id) else {
+                    return 0;
+                };
+
+                // Both sides have to agree on the layout for the bytes to mean anything
+                if state.schema != schema_name {
+                    log::debug!(
+                        "Actor {} is synced as '{}', not '{}'",
+                        actor_id,
+                        state.schema,
+                        schema_name
+                    );
+                    return 0;
+                }
+
+                let Some(schema) = module.schemas.get(&schema_name) else {
+                    log::error!("Schema '{}' is not registered", schema_name);
+                    return 0;
+                };
+
+                match unsafe { schema.write(rdram, actor_addr, &state.data) } {
+                    Ok(_) => 1,
+                    Err(e) => {
+                        log::warn!(
+                            "Schema '{}' doesn't match actor {}: {}",
+                            schema_name,
+                            actor_id,
+                            e
+                        );
+                        0
+                    }
+                }
+            },
+            0i32,
+        );
+
+        ctx.set_return(success);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncSetSendRate(_rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSetSendRate", |ctx| {
+        let hz = ctx.get_arg_u32(0);
+
+        let result = with_network_sync_mut(
+            |module| {
+                module.scheduler.set_send_rate(hz);
+                1i32
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncSetActorSendRate(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSetActorSendRate", |ctx| {
+        let actor_id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let hz = ctx.get_arg_u32(1);
+
+        let result = with_network_sync_mut(
+            |module| {
+                module.scheduler.set_actor_rate(&actor_id, hz);
+                1i32
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncGetRemoteActorIDs(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetRemoteActorIDs", |ctx| {
+        let max_players = ctx.get_arg_u32(0);
+        let owner_ids_buffer = ctx.get_arg_u64(1); // 0 when the caller doesn't want owners
+        let ids_buffer = ctx.get_arg_u64(2); // Get the virtual address
+        let id_buffer_size = ctx.get_arg_u32(3);
+
+        let count = with_network_sync_mut(
+            |module| {
+                let mut count = 0;
+
+                // Actors that went quiet for too long are dropped before listing
+                module.remove_stale_actors();
+
+                if max_players > 0 {
+                    // Owners and ids are written in the same order, so entry i
+                    // of one buffer belongs with entry i of the other
+                    let keys = module.remote_actor_keys();
+                    let owner_refs: Vec<&str> =
+                        keys.iter().map(|(owner, _)| owner.as_str()).collect();
+                    let str_refs: Vec<&str> = keys.iter().map(|(_, id)| id.as_str()).collect();
+
+                    unsafe {
+                        if owner_ids_buffer != 0 {
+                            ctx.write_string_array_to_mem(
+                                rdram,
+                                owner_ids_buffer,
+                                &owner_refs,
+                                id_buffer_size as usize,
+                                max_players as usize,
+                            );
+                        }
+                        count = ctx.write_string_array_to_mem(
+                            rdram,
+                            ids_buffer,
+                            &str_refs,
+                            id_buffer_size as usize,
+                            max_players as usize,
+                        ) as i32;
+                    }
+                }
+
+                count
+            },
+            0i32,
+        );
+
+        ctx.set_return(count);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncGetRemoteActorData(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetRemoteActorData", |ctx| {
+        let owner_id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let actor_id = unsafe { ctx.get_arg_string(rdram, 1) };
+        let data_buffer_addr = ctx.get_arg_u64(2);
+
+        let success = with_network_sync(
+            |module| {
+                if let Some(remote_player) =
+                    module.find_remote_actor(owner_arg(&owner_id), &actor_id)
+                {
+                    unsafe {
+                        remote_player
+                            .data
+                            .write_to_mem(ctx, rdram, data_buffer_addr);
+                    }
+                    1i32
+                } else {
+                    0i32
+                }
+            },
+            0i32,
+        );
+
+        ctx.set_return(success);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncGetRemoteActorDataInterpolated(
+    rdram: *mut u8,
+    ctx: *mut RecompContext,
+) {
+    execute_safely(ctx, "NetworkSyncGetRemoteActorDataInterpolated", |ctx| {
+        let owner_id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let actor_id = unsafe { ctx.get_arg_string(rdram, 1) };
+        let data_buffer_addr = ctx.get_arg_u64(2);
+
+        let success = with_network_sync(
+            |module| {
+                if let Some(data) =
+                    module.interpolated_remote_actor(owner_arg(&owner_id), &actor_id)
+                {
+                    unsafe {
+                        data.write_to_mem(ctx, rdram, data_buffer_addr);
+                    }
+                    1i32
+                } else {
+                    0i32
+                }
+            },
+            0i32,
+        );
+
+        ctx.set_return(success);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncSetInterpolationDelay(_rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSetInterpolationDelay", |ctx| {
+        let delay_ms = ctx.get_arg_u32(0);
+
+        let result = with_network_sync_mut(
+            |module| {
+                module.interpolation_delay = Duration::from_millis(delay_ms as u64);
+                1i32
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncSetMaxExtrapolation(_rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSetMaxExtrapolation", |ctx| {
+        let max_ms = ctx.get_arg_u32(0);
+
+        let result = with_network_sync_mut(
+            |module| {
+                module.max_extrapolation = Duration::from_millis(max_ms as u64);
+                1i32
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncSetStalenessPolicy(_rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncSetStalenessPolicy", |ctx| {
+        let hide_after_ms = ctx.get_arg_u32(0);
+        let remove_after_ms = ctx.get_arg_u32(1);
+
+        let result = with_network_sync_mut(
+            |module| {
+                module.staleness_policy = StalenessPolicy {
+                    hide_after: Duration::from_millis(hide_after_ms as u64),
+                    remove_after: (remove_after_ms > 0)
+                        .then(|| Duration::from_millis(remove_after_ms as u64)),
+                };
+                1i32
+            },
+            0i32,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncGetRemoteActorAge(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetRemoteActorAge", |ctx| {
+        let owner_id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let actor_id = unsafe { ctx.get_arg_string(rdram, 1) };
+
+        // u32::MAX when we have never heard of the actor
+        let age_ms = with_network_sync(
+            |module| {
+                module
+                    .remote_actor_age(owner_arg(&owner_id), &actor_id)
+                    .map_or(u32::MAX, |age| age.as_millis().min(u32::MAX as u128) as u32)
+            },
+            u32::MAX,
+        );
+
+        ctx.set_return(age_ms);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncIsRemoteActorStale(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncIsRemoteActorStale", |ctx| {
+        let owner_id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let actor_id = unsafe { ctx.get_arg_string(rdram, 1) };
+
+        let stale = with_network_sync(
+            |module| module.is_remote_actor_stale(owner_arg(&owner_id), &actor_id) as i32,
+            0i32,
+        );
+
+        ctx.set_return(stale);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncEmitMessage(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncEmitMessage", |ctx| {
+        let message_id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let data_size = ctx.get_arg_u32(1) as usize;
+        let data_ptr = ctx.get_arg_u64(2);
+        let echo = ctx.get_arg_u32(3) != 0;
+
+        // Read the data from the provided pointer
+        let mut data = Vec::with_capacity(data_size);
+        unsafe {
+            for i in 0..data_size {
+                data.push(mem_bu(rdram, data_ptr + i as u64));
+            }
+        }
+
+        let result = with_network_sync_mut(
+            |module| match module.send_message(&message_id, data, echo, Vec::new()) {
+                Ok(_) => SEND_QUEUED,
+                Err(e) if is_queue_full(&e) => {
+                    log::warn!("Outbound queue full, dropping message {}", message_id);
+                    SEND_QUEUE_FULL
+                }
+                Err(e) => {
+                    log::error!("Failed to send message {}: {}", message_id, e);
+                    SEND_FAILED
+                }
+            },
+            SEND_FAILED,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncEmitMessageTo(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncEmitMessageTo", |ctx| {
+        // Comma separated list of client IDs
+        let targets = unsafe { ctx.get_arg_string(rdram, 0) };
+        let message_id = unsafe { ctx.get_arg_string(rdram, 1) };
+        let data_size = ctx.get_arg_u32(2) as usize;
+        let data_ptr = ctx.get_arg_u64(3);
+
+        let targets: Vec<String> = targets
+            .split(',')
+            .map(str::trim)
+            .filter(|target| !target.is_empty())
+            .map(String::from)
+            .collect();
+
+        if targets.is_empty() {
+            log::error!("No targets given for message {}", message_id);
+            ctx.set_return(SEND_FAILED);
+            return;
+        }
+
+        // Read the data from the provided pointer
+        let mut data = Vec::with_capacity(data_size);
+        unsafe {
+            for i in 0..data_size {
+                data.push(mem_bu(rdram, data_ptr + i as u64));
+            }
+        }
+
+        let result = with_network_sync_mut(
+            |module| match module.send_message(&message_id, data, false, targets) {
+                Ok(_) => SEND_QUEUED,
+                Err(e) if is_queue_full(&e) => {
+                    log::warn!("Outbound queue full, dropping message {}", message_id);
+                    SEND_QUEUE_FULL
+                }
+                Err(e) => {
+                    log::error!("Failed to send message {}: {}", message_id, e);
+                    SEND_FAILED
+                }
+            },
+            SEND_FAILED,
+        );
+
+        ctx.set_return(result);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncEmitReliableMessage(rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncEmitReliableMessage", |ctx| {
+        let message_id = unsafe { ctx.get_arg_string(rdram, 0) };
+        let data_size = ctx.get_arg_u32(1) as usize;
+        let data_ptr = ctx.get_arg_u64(2);
+
+        // Read the data from the provided pointer
+        let mut data = Vec::with_capacity(data_size);
+        unsafe {
+            for i in 0..data_size {
+                data.push(mem_bu(rdram, data_ptr + i as u64));
+            }
+        }
+
+        // Delivery handle, or 0 if the message couldn't be sent at all
+        let handle = with_network_sync_mut(
+            |module| match module.send_reliable_message(&message_id, data) {
+                Ok(handle) => handle,
+                Err(e) => {
+                    log::error!("Failed to send reliable message {}: {}", message_id, e);
+                    0
+                }
+            },
+            0u32,
+        );
+
+        ctx.set_return(handle);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn NetworkSyncGetDeliveryStatus(_rdram: *mut u8, ctx: *mut RecompContext) {
+    execute_safely(ctx, "NetworkSyncGetDeliveryStatus", |ctx| {
+        let handle = ctx.get_arg_u32(0);
+
+        let status = with_network_sync(
+            |module| module.delivery_status(handle),
+            DeliveryStatus::Unknown,
+        );
+
+        ctx.set_return(status as u32);
+    });
+}
+
 #[no_mangle]
 pub extern "C" fn NetworkSyncGetPendingMessageSize(_rdram: *mut u8, ctx: *mut RecompContext) {
     execute_safely(ctx, "NetworkSyncGetPendingMessageSize", |ctx| {
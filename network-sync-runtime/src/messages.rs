@@ -1,11 +1,47 @@
-use serde::{self, Deserialize, Serialize};
+use anyhow::Result;
+use n64_recomp::{Vec3f, Vec3s};
+use serde::{self, de::DeserializeOwned, Deserialize, Serialize};
+use std::fmt;
 
 use crate::types::ActorData;
 
+/// Codec name advertised by the server in `welcome` and selected by the client
+pub const CODEC_BINARY: &str = "binary";
+
+/// Feature advertised in `welcome` by servers that relay actor deltas
+pub const FEATURE_ACTOR_DELTA: &str = "actor_delta";
+
+/// Feature advertised in `welcome` by servers that relay batches
+pub const FEATURE_BATCH: &str = "batch";
+
+/// Feature advertised in `welcome` by servers that check our `hello`
+pub const FEATURE_HELLO: &str = "hello";
+
+/// Feature advertised in `welcome` by servers that only relay actors between
+/// players in view of each other
+pub const FEATURE_PRESENCE: &str = "presence";
+
+/// Version of the protocol this runtime speaks, sent in `hello`
+pub const PROTOCOL_VERSION: u32 = 1;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JoinSessionMessage {
     pub event_type: String,
     pub session_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_players: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game_version: Option<String>,
+    pub unlisted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListSessionsMessage {
+    pub event_type: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,6 +49,60 @@ pub struct LeaveSessionMessage {
     pub event_type: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BanMemberMessage {
+    pub event_type: String,
+    pub member_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelectCodecMessage {
+    pub event_type: String,
+    pub codec: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumeMessage {
+    pub event_type: String,
+    pub resume_token: String,
+}
+
+/// Identifies this build to the server, which refuses incompatible ones
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HelloMessage {
+    pub event_type: String,
+    pub protocol_version: u32,
+    pub runtime_version: String,
+    /// Hash of the `ActorData` layout; sessions only mix runtimes that agree
+    pub layout_hash: u32,
+}
+
+/// Stores a value in the session's state, which the server replays to joiners
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetSessionStateMessage {
+    pub event_type: String,
+    pub key: String,
+    pub value: Vec<u8>,
+}
+
+/// Tells the server where we are, so it only relays actors we can see
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetPresenceMessage {
+    pub event_type: String,
+    pub scene: u32,
+    pub room: i32,
+    pub entrance: u32,
+    /// Scenes whose actors are still worth hearing about from `scene`
+    pub adjacent_scenes: Vec<u32>,
+}
+
+/// One key of the session's state, as carried by `session_state`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionStateEntry {
+    pub key: String,
+    pub value: Vec<u8>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkMessage {
     pub event_type: String,
@@ -24,7 +114,24 @@ pub struct NetworkMessage {
 pub struct ActorSyncMessage {
     pub event_type: String,
     pub sender_id: String,
+    pub actor_id: String,
     pub data: ActorData,
+    /// Frame number deltas refer back to; 0 from older peers
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub seq: u32,
+}
+
+/// The fields of an actor that changed since frame `baseline`, encoded by
+/// `delta::encode_delta`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActorDeltaMessage {
+    pub event_type: String,
+    pub sender_id: String,
+    pub actor_id: String,
+    pub seq: u32,
+    pub baseline: u32,
+    pub mask: u32,
+    pub data: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,16 +140,154 @@ pub struct RegisteredMessage {
     pub sender_id: String,
     pub message_id: String,
     pub data: Vec<u8>,
+    /// Ask the server to send the message back to us as well
+    #[serde(default)]
+    pub echo: bool,
+    /// Client IDs to deliver to instead of the whole session
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<String>,
+    /// Reliable sequence number per (sender, recipient); 0 for unreliable messages
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub seq: u32,
 }
 
-// But implement custom deserialization for ServerMessage
+/// Confirms receipt of a reliable message, relayed to its sender only
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageAckMessage {
+    pub event_type: String,
+    pub sender_id: String,
+    pub target: String,
+    pub seq: u32,
+}
+
+/// State of an actor read through the sync schema named `schema`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActorStateMessage {
+    pub event_type: String,
+    pub sender_id: String,
+    pub actor_id: String,
+    pub schema: String,
+    pub data: Vec<u8>,
+}
+
+/// A relayed message that can travel inside a batch. Batches go to the whole
+/// session, so targeted, echoed and reliable messages are sent on their own.
+#[derive(Debug, Clone)]
+pub enum BatchEntry {
+    ActorSync(ActorSyncMessage),
+    ActorDelta(ActorDeltaMessage),
+    ActorState(ActorStateMessage),
+    Registered(RegisteredMessage),
+}
+
+impl BatchEntry {
+    /// Encode as a frame of its own, JSON text or binary
+    pub fn encode(&self, binary: bool) -> Result<Vec<u8>> {
+        let encoded = match self {
+            BatchEntry::ActorSync(msg) if binary => msg.to_binary(),
+            BatchEntry::ActorDelta(msg) if binary => msg.to_binary(),
+            BatchEntry::ActorState(msg) if binary => msg.to_binary(),
+            BatchEntry::Registered(msg) if binary => msg.to_binary(),
+            BatchEntry::ActorSync(msg) => serde_json::to_vec(msg)?,
+            BatchEntry::ActorDelta(msg) => serde_json::to_vec(msg)?,
+            BatchEntry::ActorState(msg) => serde_json::to_vec(msg)?,
+            BatchEntry::Registered(msg) => serde_json::to_vec(msg)?,
+        };
+
+        Ok(encoded)
+    }
+}
+
+/// Wrap entries encoded by `BatchEntry::encode` in a single batch frame
+pub fn encode_batch(sender_id: &str, entries: &[Vec<u8>], binary: bool) -> Result<Vec<u8>> {
+    if binary {
+        let mut writer = BinaryWriter::new(BINARY_TAG_BATCH);
+        writer.string(sender_id);
+        writer.u16(entries.len() as u16);
+        for entry in entries {
+            writer.bytes(entry);
+        }
+        return Ok(writer.finish());
+    }
+
+    // Entries are JSON already, so splice them in rather than parsing them back
+    let mut json = format!(
+        "{{\"event_type\":\"batch\",\"sender_id\":{},\"messages\":[",
+        serde_json::to_string(sender_id)?
+    )
+    .into_bytes();
+    for (index, entry) in entries.iter().enumerate() {
+        if index > 0 {
+            json.push(b',');
+        }
+        json.extend_from_slice(entry);
+    }
+    json.extend_from_slice(b"]}");
+
+    Ok(json)
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+// Deserialized through `MessageHelper`, so unknown event types don't fail
 #[derive(Deserialize)]
-#[serde(from = "MessageHelper")]
+#[serde(try_from = "MessageHelper")]
 pub enum ServerMessage {
     Welcome(NetworkMessage),
+    HelloResult(NetworkMessage),
+    ResumeResult(NetworkMessage),
+    JoinSessionResult(NetworkMessage),
+    SessionList(NetworkMessage),
     SessionMembers(NetworkMessage),
+    /// The host banned us from the session we were in
+    RemovedFromSession(NetworkMessage),
+    SessionState(NetworkMessage),
+    Presence(NetworkMessage),
     ActorSync(ActorSyncMessage),
+    ActorDelta(ActorDeltaMessage),
+    ActorState(ActorStateMessage),
     RegisteredMessage(RegisteredMessage),
+    MessageAck(MessageAckMessage),
+    /// Messages one client sent in the same tick, in order
+    Batch(Vec<ServerMessage>),
+    /// An event type this build doesn't know, from a newer server
+    Unknown {
+        event_type: String,
+        raw: serde_json::Value,
+    },
+}
+
+/// Why a text frame from the server couldn't be decoded
+#[derive(Debug)]
+pub enum DecodeError {
+    /// Not JSON, or no `event_type`
+    Syntax(serde_json::Error),
+    /// A known event type whose fields aren't what we expect
+    Malformed {
+        event_type: String,
+        source: serde_json::Error,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Syntax(e) => write!(f, "Unreadable message: {}", e),
+            DecodeError::Malformed { event_type, source } => {
+                write!(f, "Malformed {} message: {}", event_type, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecodeError::Syntax(e) | DecodeError::Malformed { source: e, .. } => Some(e),
+        }
+    }
 }
 
 // Helper struct for deserialization
@@ -54,8 +299,10 @@ struct MessageHelper {
 }
 
 // Convert from helper to actual enum
-impl From<MessageHelper> for ServerMessage {
-    fn from(helper: MessageHelper) -> Self {
+impl TryFrom<MessageHelper> for ServerMessage {
+    type Error = DecodeError;
+
+    fn try_from(helper: MessageHelper) -> Result<Self, DecodeError> {
         // Combine the event_type with the rest of the fields
         let mut json_map = serde_json::Map::new();
         json_map.insert("event_type".to_string(), helper.event_type.clone().into());
@@ -65,17 +312,576 @@ impl From<MessageHelper> for ServerMessage {
         }
 
         let json = serde_json::Value::Object(json_map);
+        let event_type = helper.event_type;
 
-        match helper.event_type.as_str() {
-            "welcome" => ServerMessage::Welcome(serde_json::from_value(json).unwrap()),
-            "session_members" => {
-                ServerMessage::SessionMembers(serde_json::from_value(json).unwrap())
+        let message = match event_type.as_str() {
+            "welcome" => ServerMessage::Welcome(parse_event(&event_type, json)?),
+            "hello_result" => ServerMessage::HelloResult(parse_event(&event_type, json)?),
+            "resume_result" => ServerMessage::ResumeResult(parse_event(&event_type, json)?),
+            "join_session_result" => {
+                ServerMessage::JoinSessionResult(parse_event(&event_type, json)?)
+            }
+            "session_list" => ServerMessage::SessionList(parse_event(&event_type, json)?),
+            "session_members" => ServerMessage::SessionMembers(parse_event(&event_type, json)?),
+            "removed_from_session" => {
+                ServerMessage::RemovedFromSession(parse_event(&event_type, json)?)
             }
-            "actor_sync" => ServerMessage::ActorSync(serde_json::from_value(json).unwrap()),
+            "session_state" => ServerMessage::SessionState(parse_event(&event_type, json)?),
+            "presence" => ServerMessage::Presence(parse_event(&event_type, json)?),
+            "actor_sync" => ServerMessage::ActorSync(parse_event(&event_type, json)?),
+            "actor_delta" => ServerMessage::ActorDelta(parse_event(&event_type, json)?),
+            "actor_state" => ServerMessage::ActorState(parse_event(&event_type, json)?),
             "registered_message" => {
-                ServerMessage::RegisteredMessage(serde_json::from_value(json).unwrap())
+                ServerMessage::RegisteredMessage(parse_event(&event_type, json)?)
             }
-            _ => panic!("Unknown message type: {}", helper.event_type),
+            "message_ack" => ServerMessage::MessageAck(parse_event(&event_type, json)?),
+            "batch" => ServerMessage::Batch(parse_event(&event_type, json["messages"].clone())?),
+            _ => ServerMessage::Unknown {
+                event_type,
+                raw: json,
+            },
+        };
+
+        Ok(message)
+    }
+}
+
+// Parse the fields of a known event type
+fn parse_event<T: DeserializeOwned>(
+    event_type: &str,
+    value: serde_json::Value,
+) -> Result<T, DecodeError> {
+    serde_json::from_value(value).map_err(|source| DecodeError::Malformed {
+        event_type: event_type.to_string(),
+        source,
+    })
+}
+
+impl ServerMessage {
+    /// Decode a text frame. Event types this build doesn't know come back as
+    /// `Unknown` rather than failing, so newer servers can add messages.
+    pub fn from_json(text: &str) -> Result<Self, DecodeError> {
+        let helper = serde_json::from_str::<MessageHelper>(text).map_err(DecodeError::Syntax)?;
+        Self::try_from(helper)
+    }
+}
+
+impl ServerMessage {
+    /// Decode a binary frame. Only actor syncs, deltas and states, registered
+    /// messages, acks and batches of them are sent in binary.
+    pub fn from_binary(bytes: &[u8]) -> Result<Self> {
+        let mut reader = BinaryReader::new(bytes);
+
+        let message = match reader.u8()? {
+            BINARY_TAG_ACTOR_SYNC => ServerMessage::ActorSync(ActorSyncMessage {
+                event_type: "actor_sync".to_string(),
+                sender_id: reader.string()?,
+                actor_id: reader.string()?,
+                data: reader.actor_data()?,
+                seq: reader.trailing_u32()?,
+            }),
+            BINARY_TAG_ACTOR_DELTA => ServerMessage::ActorDelta(ActorDeltaMessage {
+                event_type: "actor_delta".to_string(),
+                sender_id: reader.string()?,
+                actor_id: reader.string()?,
+                seq: reader.u32()?,
+                baseline: reader.u32()?,
+                mask: reader.u32()?,
+                data: reader.bytes()?,
+            }),
+            BINARY_TAG_REGISTERED_MESSAGE => ServerMessage::RegisteredMessage(RegisteredMessage {
+                event_type: "registered_message".to_string(),
+                sender_id: reader.string()?,
+                message_id: reader.string()?,
+                data: reader.bytes()?,
+                echo: reader.trailing_flag()?,
+                targets: reader.trailing_strings()?,
+                seq: reader.trailing_u32()?,
+            }),
+            BINARY_TAG_MESSAGE_ACK => ServerMessage::MessageAck(MessageAckMessage {
+                event_type: "message_ack".to_string(),
+                sender_id: reader.string()?,
+                target: reader.string()?,
+                seq: reader.u32()?,
+            }),
+            BINARY_TAG_ACTOR_STATE => ServerMessage::ActorState(ActorStateMessage {
+                event_type: "actor_state".to_string(),
+                sender_id: reader.string()?,
+                actor_id: reader.string()?,
+                schema: reader.string()?,
+                data: reader.bytes()?,
+            }),
+            BINARY_TAG_BATCH => {
+                // Each entry carries its own sender
+                let _sender_id = reader.string()?;
+                let count = reader.u16()?;
+
+                let messages = (0..count)
+                    .map(|_| match ServerMessage::from_binary(&reader.bytes()?)? {
+                        ServerMessage::Batch(_) => Err(anyhow::anyhow!("Nested batch")),
+                        message => Ok(message),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                ServerMessage::Batch(messages)
+            }
+            tag => return Err(anyhow::anyhow!("Unknown binary message tag: {}", tag)),
+        };
+
+        Ok(message)
+    }
+}
+
+// MARK: - Binary Codec
+
+// Layout: tag u8, then fields in declaration order. Strings are u16 length
+// prefixed, byte blobs u32 length prefixed, all integers little endian.
+// Actor syncs end with a u32 frame number, absent from older peers.
+// Registered messages end with an echo flag byte, a u16 counted list of
+// target client IDs and a u32 reliable sequence number, all absent from
+// older peers. Batches are a u16 counted list of complete frames, each u32
+// length prefixed.
+// Must stay in sync with network-sync-server/src/protocol.rs; both sides are
+// tested against the frames in fixtures/binary.
+const BINARY_TAG_ACTOR_SYNC: u8 = 1;
+const BINARY_TAG_REGISTERED_MESSAGE: u8 = 2;
+const BINARY_TAG_MESSAGE_ACK: u8 = 3;
+const BINARY_TAG_ACTOR_DELTA: u8 = 4;
+const BINARY_TAG_BATCH: u8 = 5;
+const BINARY_TAG_ACTOR_STATE: u8 = 6;
+
+impl ActorSyncMessage {
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut writer = BinaryWriter::new(BINARY_TAG_ACTOR_SYNC);
+        writer.string(&self.sender_id);
+        writer.string(&self.actor_id);
+        writer.actor_data(&self.data);
+        writer.u32(self.seq);
+        writer.finish()
+    }
+}
+
+impl ActorDeltaMessage {
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut writer = BinaryWriter::new(BINARY_TAG_ACTOR_DELTA);
+        writer.string(&self.sender_id);
+        writer.string(&self.actor_id);
+        writer.u32(self.seq);
+        writer.u32(self.baseline);
+        writer.u32(self.mask);
+        writer.bytes(&self.data);
+        writer.finish()
+    }
+}
+
+impl ActorStateMessage {
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut writer = BinaryWriter::new(BINARY_TAG_ACTOR_STATE);
+        writer.string(&self.sender_id);
+        writer.string(&self.actor_id);
+        writer.string(&self.schema);
+        writer.bytes(&self.data);
+        writer.finish()
+    }
+}
+
+impl RegisteredMessage {
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut writer = BinaryWriter::new(BINARY_TAG_REGISTERED_MESSAGE);
+        writer.string(&self.sender_id);
+        writer.string(&self.message_id);
+        writer.bytes(&self.data);
+        writer.flag(self.echo);
+        writer.strings(&self.targets);
+        writer.u32(self.seq);
+        writer.finish()
+    }
+}
+
+impl MessageAckMessage {
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut writer = BinaryWriter::new(BINARY_TAG_MESSAGE_ACK);
+        writer.string(&self.sender_id);
+        writer.string(&self.target);
+        writer.u32(self.seq);
+        writer.finish()
+    }
+}
+
+struct BinaryWriter {
+    buf: Vec<u8>,
+}
+
+impl BinaryWriter {
+    fn new(tag: u8) -> Self {
+        Self { buf: vec![tag] }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    fn string(&mut self, value: &str) {
+        self.buf
+            .extend_from_slice(&(value.len() as u16).to_le_bytes());
+        self.buf.extend_from_slice(value.as_bytes());
+    }
+
+    fn bytes(&mut self, value: &[u8]) {
+        self.buf
+            .extend_from_slice(&(value.len() as u32).to_le_bytes());
+        self.buf.extend_from_slice(value);
+    }
+
+    fn strings(&mut self, values: &[String]) {
+        self.buf
+            .extend_from_slice(&(values.len() as u16).to_le_bytes());
+        for value in values {
+            self.string(value);
+        }
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn flag(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    fn vec3f(&mut self, value: &Vec3f) {
+        for component in [value.x, value.y, value.z] {
+            self.buf.extend_from_slice(&component.to_le_bytes());
+        }
+    }
+
+    fn vec3s(&mut self, value: &Vec3s) {
+        for component in [value.x, value.y, value.z] {
+            self.buf.extend_from_slice(&component.to_le_bytes());
+        }
+    }
+
+    fn actor_data(&mut self, data: &ActorData) {
+        self.vec3f(&data.world_position);
+        self.vec3s(&data.shape_rotation);
+        self.vec3s(&data.upper_limb_rot);
+        for joint in &data.joint_table {
+            self.vec3s(joint);
+        }
+        self.buf.push(data.current_mask as u8);
+        self.buf.push(data.current_shield as u8);
+    }
+}
+
+struct BinaryReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> BinaryReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(anyhow::anyhow!(
+                "Binary message truncated: wanted {} bytes, {} left",
+                len,
+                self.buf.len()
+            ));
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        Ok(i16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = u16::from_le_bytes(self.take(2)?.try_into()?) as usize;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>> {
+        let len = u32::from_le_bytes(self.take(4)?.try_into()?) as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    // A flag byte that may be missing from the end of the frame
+    fn trailing_flag(&mut self) -> Result<bool> {
+        if self.buf.is_empty() {
+            return Ok(false);
+        }
+        Ok(self.u8()? != 0)
+    }
+
+    // A u32 that may be missing from the end of the frame
+    fn trailing_u32(&mut self) -> Result<u32> {
+        if self.buf.is_empty() {
+            return Ok(0);
+        }
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    // A u16 counted string list that may be missing from the end of the frame
+    fn trailing_strings(&mut self) -> Result<Vec<String>> {
+        if self.buf.is_empty() {
+            return Ok(Vec::new());
+        }
+        let count = u16::from_le_bytes(self.take(2)?.try_into()?);
+        (0..count).map(|_| self.string()).collect()
+    }
+
+    fn vec3f(&mut self) -> Result<Vec3f> {
+        Ok(Vec3f {
+            x: self.f32()?,
+            y: self.f32()?,
+            z: self.f32()?,
+        })
+    }
+
+    fn vec3s(&mut self) -> Result<Vec3s> {
+        Ok(Vec3s {
+            x: self.i16()?,
+            y: self.i16()?,
+            z: self.i16()?,
+        })
+    }
+
+    fn actor_data(&mut self) -> Result<ActorData> {
+        let world_position = self.vec3f()?;
+        let shape_rotation = self.vec3s()?;
+        let upper_limb_rot = self.vec3s()?;
+
+        let joints = (0..24).map(|_| self.vec3s()).collect::<Result<Vec<_>>>()?;
+        let joint_table = joints
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid joint table length"))?;
+
+        Ok(ActorData {
+            world_position,
+            shape_rotation,
+            upper_limb_rot,
+            joint_table,
+            current_mask: self.u8()? as i8,
+            current_shield: self.u8()? as i8,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Binary frames both the runtime and the server must agree on, kept as
+    // hex in fixtures/binary and checked against the server's codec as well
+    macro_rules! fixture {
+        ($name:literal) => {
+            parse_hex(include_str!(concat!(
+                "../../fixtures/binary/",
+                $name,
+                ".hex"
+            )))
+        };
+    }
+
+    fn parse_hex(text: &str) -> Vec<u8> {
+        let digits: Vec<u8> = text.bytes().filter(|b| b.is_ascii_hexdigit()).collect();
+        digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect()
+    }
+
+    fn actor_data() -> ActorData {
+        let joint = |i: i16| Vec3s {
+            x: i,
+            y: -i,
+            z: i16::MAX - i,
+        };
+
+        ActorData {
+            world_position: Vec3f {
+                x: 1.5,
+                y: -2.0,
+                z: 1024.25,
+            },
+            shape_rotation: Vec3s { x: 1, y: 2, z: 3 },
+            upper_limb_rot: Vec3s {
+                x: -4,
+                y: i16::MIN,
+                z: 6,
+            },
+            joint_table: std::array::from_fn(|i| joint(i as i16)),
+            current_mask: 3,
+            current_shield: -1,
+        }
+    }
+
+    fn actor_sync() -> ActorSyncMessage {
+        ActorSyncMessage {
+            event_type: "actor_sync".to_string(),
+            sender_id: "p1".to_string(),
+            actor_id: "player".to_string(),
+            data: actor_data(),
+            seq: 7,
+        }
+    }
+
+    fn actor_delta() -> ActorDeltaMessage {
+        ActorDeltaMessage {
+            event_type: "actor_delta".to_string(),
+            sender_id: "p1".to_string(),
+            actor_id: "player".to_string(),
+            seq: 8,
+            baseline: 7,
+            mask: 0b101,
+            data: vec![1, 2, 3, 4],
+        }
+    }
+
+    fn registered_message(echo: bool, targets: &[&str], seq: u32) -> RegisteredMessage {
+        RegisteredMessage {
+            event_type: "registered_message".to_string(),
+            sender_id: "p1".to_string(),
+            message_id: "chat".to_string(),
+            data: b"hi".to_vec(),
+            echo,
+            targets: targets.iter().map(|target| target.to_string()).collect(),
+            seq,
+        }
+    }
+
+    // Re-encode a decoded frame, to compare against the fixture it came from
+    fn encode(message: &ServerMessage) -> Vec<u8> {
+        match message {
+            ServerMessage::ActorSync(msg) => msg.to_binary(),
+            ServerMessage::ActorDelta(msg) => msg.to_binary(),
+            ServerMessage::ActorState(msg) => msg.to_binary(),
+            ServerMessage::RegisteredMessage(msg) => msg.to_binary(),
+            ServerMessage::MessageAck(msg) => msg.to_binary(),
+            _ => panic!("not a binary message"),
         }
     }
+
+    #[test]
+    fn binary_frames_match_fixtures() {
+        let fixtures = [
+            (actor_sync().to_binary(), fixture!("actor_sync")),
+            (actor_delta().to_binary(), fixture!("actor_delta")),
+            (
+                ActorStateMessage {
+                    event_type: "actor_state".to_string(),
+                    sender_id: "p1".to_string(),
+                    actor_id: "door".to_string(),
+                    schema: "door_state".to_string(),
+                    data: vec![9, 8, 7],
+                }
+                .to_binary(),
+                fixture!("actor_state"),
+            ),
+            (
+                registered_message(true, &["p2", "p3"], 5).to_binary(),
+                fixture!("registered_message"),
+            ),
+            (
+                MessageAckMessage {
+                    event_type: "message_ack".to_string(),
+                    sender_id: "p2".to_string(),
+                    target: "p1".to_string(),
+                    seq: 5,
+                }
+                .to_binary(),
+                fixture!("message_ack"),
+            ),
+        ];
+
+        for (encoded, fixture) in fixtures {
+            assert_eq!(encoded, fixture);
+            assert_eq!(
+                encode(&ServerMessage::from_binary(&fixture).unwrap()),
+                fixture
+            );
+        }
+    }
+
+    #[test]
+    fn binary_batch_matches_fixture() {
+        let entries = [
+            BatchEntry::ActorDelta(actor_delta()).encode(true).unwrap(),
+            BatchEntry::Registered(registered_message(false, &[], 0))
+                .encode(true)
+                .unwrap(),
+        ];
+        assert_eq!(
+            encode_batch("p1", &entries, true).unwrap(),
+            fixture!("batch")
+        );
+
+        let ServerMessage::Batch(messages) =
+            ServerMessage::from_binary(&fixture!("batch")).unwrap()
+        else {
+            panic!("expected a batch");
+        };
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[0], ServerMessage::ActorDelta(_)));
+        assert!(matches!(messages[1], ServerMessage::RegisteredMessage(_)));
+    }
+
+    #[test]
+    fn binary_decode_keeps_fields() {
+        let ServerMessage::ActorSync(msg) =
+            ServerMessage::from_binary(&fixture!("actor_sync")).unwrap()
+        else {
+            panic!("expected an actor sync");
+        };
+        assert_eq!(msg.actor_id, "player");
+        assert_eq!(msg.seq, 7);
+        assert_eq!(msg.data.world_position.z, 1024.25);
+        assert_eq!(msg.data.upper_limb_rot.y, i16::MIN);
+        assert_eq!(msg.data.joint_table[23].z, i16::MAX - 23);
+        assert_eq!(msg.data.current_shield, -1);
+    }
+
+    #[test]
+    fn binary_decode_defaults_trailing_fields() {
+        let ServerMessage::RegisteredMessage(msg) =
+            ServerMessage::from_binary(&fixture!("registered_message_v1")).unwrap()
+        else {
+            panic!("expected a registered message");
+        };
+        assert_eq!(msg.data, b"hi");
+        assert!(!msg.echo);
+        assert!(msg.targets.is_empty());
+        assert_eq!(msg.seq, 0);
+    }
+
+    #[test]
+    fn binary_decode_rejects_truncated_frames() {
+        let fixture = fixture!("actor_delta");
+        assert!(ServerMessage::from_binary(&fixture[..fixture.len() - 1]).is_err());
+        assert!(ServerMessage::from_binary(&[0xFF]).is_err());
+    }
 }
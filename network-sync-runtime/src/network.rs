@@ -1,17 +1,57 @@
 use anyhow::Result;
-use gamecore::network::NetworkModule;
-use serde_json;
 use std::collections::{HashMap, VecDeque};
 use std::panic;
-use std::sync::{Arc, Mutex, OnceLock};
-use std::time::Instant;
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use tokio::runtime::Runtime;
 
+use crate::delta::{
+    apply_delta, decode_acks, encode_acks, ActorUpdate, DeltaEncoder, ACK_INTERVAL,
+    ACTOR_ACK_MESSAGE_ID, KEYFRAME_REQUEST_INTERVAL, KEYFRAME_REQUEST_MESSAGE_ID,
+};
+use crate::flags::{FlagSync, FLAG_MESSAGE_PREFIX, FLAG_STATE_PREFIX};
+use crate::interpolation::{
+    StalenessPolicy, DEFAULT_INTERPOLATION_DELAY, DEFAULT_MAX_EXTRAPOLATION,
+};
+use crate::inventory::{InventoryState, InventorySync, INVENTORY_MESSAGE_ID, INVENTORY_STATE_KEY};
 use crate::messages::{
-    ActorSyncMessage, JoinSessionMessage, LeaveSessionMessage, RegisteredMessage,
-    ServerMessage,
+    encode_batch, ActorDeltaMessage, ActorStateMessage, ActorSyncMessage, BanMemberMessage,
+    BatchEntry, HelloMessage, JoinSessionMessage, LeaveSessionMessage, ListSessionsMessage,
+    MessageAckMessage, RegisteredMessage, ResumeMessage, SelectCodecMessage, ServerMessage,
+    SessionStateEntry, SetPresenceMessage, SetSessionStateMessage, CODEC_BINARY,
+    FEATURE_ACTOR_DELTA, FEATURE_BATCH, FEATURE_HELLO, FEATURE_PRESENCE, PROTOCOL_VERSION,
+};
+use crate::reliable::{DeliveryStatus, OutgoingReliable, ReliableChannel, RESEND_INTERVAL};
+use crate::scheduler::SendScheduler;
+use crate::schema::SyncSchema;
+use crate::time_sync::{
+    TimeState, TimeSync, PROPOSE_MESSAGE_ID, TIME_MESSAGE_PREFIX, VOTE_MESSAGE_ID,
+};
+use crate::transport::{InboundFrame, Transport};
+use crate::types::{
+    ActorData, ActorPayload, ConnectResult, ConnectionState, JoinOptions, JoinResult,
+    LifecycleEvent, PlayerPresence, RemoteActorData, RemoteActorState, SessionDetails,
+    SessionListing,
 };
-use crate::types::{ActorData, RemoteActorData};
+use crate::utils::is_queue_full;
+
+/// Oldest lifecycle events are dropped past this many if the mod never polls
+const MAX_QUEUED_EVENTS: usize = 64;
+
+/// Most messages packed into one batch
+const MAX_BATCH_ENTRIES: usize = 32;
+
+/// Batches stop growing past this many bytes, well under the server's frame limit
+const MAX_BATCH_BYTES: usize = 8192;
+
+/// Session state keys must fit the mod's 64 byte string buffers
+const MAX_STATE_KEY_LEN: usize = 63;
+
+/// Largest session state value the server stores
+const MAX_STATE_VALUE_BYTES: usize = 1024;
+
+/// Most scenes that can be declared adjacent to one scene
+const MAX_ADJACENT_SCENES: usize = 32;
 
 // Global singleton instances
 pub static NETWORK_PLAY: OnceLock<Arc<Mutex<NetworkSyncModule>>> = OnceLock::new();
@@ -29,60 +69,303 @@ pub fn get_network_sync() -> Arc<Mutex<NetworkSyncModule>> {
         .clone()
 }
 
+/// Backoff settings for the reconnect supervisor
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectPolicy {
+    /// Attempts before giving up; 0 disables reconnection
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
 /// Minimal network play module with just what we need
 pub struct NetworkSyncModule {
-    network: NetworkModule,
+    network: Transport,
     connected: bool,
+    /// Whether the server advertised binary framing in its welcome
+    binary_supported: bool,
+    /// Whether the server relays actor deltas, so we can stop sending full frames
+    delta_supported: bool,
+    /// Last frame sent for each of our actors, for delta compression
+    actor_encoder: DeltaEncoder,
+    /// Whether the server relays batches of messages as one frame
+    batch_supported: bool,
+    /// Whether the server filters actor traffic by where players are
+    presence_supported: bool,
+    /// Holds our actor updates until they're due to go out
+    pub scheduler: SendScheduler,
+    /// Session wide messages waiting for the next tick's batch
+    outbox: Vec<BatchEntry>,
+    /// Whether the send tick task is running
+    ticking: bool,
+    /// Server we were asked to connect to, cleared on an explicit disconnect
+    server_url: Option<String>,
+    /// Bumped for every socket we open and on disconnect. Frames are tagged
+    /// with the generation of the socket they arrived on, so late ones from a
+    /// replaced socket are dropped, and a reconnect supervisor whose attempt
+    /// has been superseded gives up.
+    generation: u64,
+    /// Token the server issued for resuming our connection after a drop
+    resume_token: Option<String>,
+    /// Whether the reconnect supervisor is running
+    reconnecting: bool,
+    pub reconnect_policy: ReconnectPolicy,
     pub client_id: String,
+    /// Caller blocked on the server accepting our hello
+    connect_waiter: Option<mpsc::Sender<ConnectResult>>,
+    /// Why the server last refused our hello
+    pub connect_reject_reason: Option<String>,
+    /// Session we asked to join that the server hasn't confirmed yet
+    pending_session_id: Option<String>,
+    /// Options of the last join, reused when rejoining after a reconnect
+    join_options: JoinOptions,
+    /// Caller blocked on the result of joining this session
+    join_waiter: Option<(String, mpsc::Sender<JoinResult>)>,
     current_session_id: Option<String>,
     session_members: Vec<String>,
-    pub remote_actors: HashMap<String, RemoteActorData>,
+    /// Member the server made host, as of its last member list
+    session_host: Option<String>,
+    /// Sent with every join, for sessions we end up creating
+    pub session_details: SessionDetails,
+    /// Most recent session listing from the server
+    pub session_list: Vec<SessionListing>,
+    /// Our copy of the current session's state, kept by the server
+    pub session_state: HashMap<String, Vec<u8>>,
+    /// Where we last said we are, sent again whenever we join a session
+    local_presence: Option<PlayerPresence>,
+    /// Scenes the mod declared adjacent to each scene
+    adjacent_scenes: HashMap<u32, Vec<u32>>,
+    /// Where the other members are, by client id
+    pub presences: HashMap<String, PlayerPresence>,
+    /// Save context bitfields shared with the session
+    pub flags: FlagSync,
+    /// The player's inventory, synced by category
+    pub inventory: InventorySync,
+    /// The three day clock, kept to the host's
+    pub time: TimeSync,
+    /// Remote actors keyed by (owner client id, actor network id)
+    pub remote_actors: HashMap<(String, String), RemoteActorData>,
+    /// When we last asked an actor's owner for a keyframe, keyed like
+    /// `remote_actors`, until the keyframe arrives
+    keyframe_requests: HashMap<(String, String), Instant>,
+    /// When we last told each owner which frames of its actors we have
+    actor_acks: HashMap<String, Instant>,
+    /// Remote actors synced through schemas, keyed the same way
+    pub remote_states: HashMap<(String, String), RemoteActorState>,
+    /// Sync schemas registered by mods, by name
+    pub schemas: HashMap<String, SyncSchema>,
+    /// How far behind the newest sample interpolated reads are taken
+    pub interpolation_delay: Duration,
+    /// How far past the newest sample remote positions are dead reckoned
+    pub max_extrapolation: Duration,
+    pub staleness_policy: StalenessPolicy,
     /// Queue of (message_id, data) tuples
     pub message_queue: VecDeque<(String, Vec<u8>)>,
+    /// Sequencing and acks for reliable messages
+    reliable: ReliableChannel,
+    /// Whether the reliable resend task is running
+    resending: bool,
+    /// Queue of lifecycle events for the mod to poll
+    pub event_queue: VecDeque<LifecycleEvent>,
+    /// Server messages skipped as unknown or undecodable since connecting
+    pub dropped_messages: u32,
 }
 
 impl NetworkSyncModule {
     pub fn new() -> Self {
         Self {
-            network: NetworkModule::new(),
+            network: Transport::new(),
             connected: false,
+            binary_supported: false,
+            delta_supported: false,
+            actor_encoder: DeltaEncoder::new(),
+            batch_supported: false,
+            presence_supported: false,
+            scheduler: SendScheduler::new(),
+            outbox: Vec::new(),
+            ticking: false,
+            server_url: None,
+            generation: 0,
+            resume_token: None,
+            reconnecting: false,
+            reconnect_policy: ReconnectPolicy::default(),
             client_id: "".to_string(),
+            connect_waiter: None,
+            connect_reject_reason: None,
+            pending_session_id: None,
+            join_options: JoinOptions::default(),
+            join_waiter: None,
             current_session_id: None,
             session_members: Vec::new(),
+            session_host: None,
+            session_details: SessionDetails::default(),
+            session_list: Vec::new(),
+            session_state: HashMap::new(),
+            local_presence: None,
+            adjacent_scenes: HashMap::new(),
+            presences: HashMap::new(),
+            flags: FlagSync::new(),
+            inventory: InventorySync::new(),
+            time: TimeSync::new(),
             remote_actors: HashMap::new(),
+            keyframe_requests: HashMap::new(),
+            actor_acks: HashMap::new(),
+            remote_states: HashMap::new(),
+            schemas: HashMap::new(),
+            interpolation_delay: DEFAULT_INTERPOLATION_DELAY,
+            max_extrapolation: DEFAULT_MAX_EXTRAPOLATION,
+            staleness_policy: StalenessPolicy::default(),
             message_queue: VecDeque::new(),
+            reliable: ReliableChannel::new(),
+            resending: false,
+            event_queue: VecDeque::new(),
+            dropped_messages: 0,
+        }
+    }
+
+    pub fn connection_state(&self) -> ConnectionState {
+        if self.reconnecting {
+            ConnectionState::Reconnecting
+        } else if !self.connected {
+            ConnectionState::Disconnected
+        } else if self.client_id.is_empty() {
+            ConnectionState::Connecting
+        } else if self.current_session_id.is_some() {
+            ConnectionState::InSession
+        } else {
+            ConnectionState::Connected
         }
     }
 
     pub fn connect(&mut self, url: &str) -> Result<()> {
-        // Set up the message handler before connecting
-        self.network.on_message(move |message| {
-            // Use catch_unwind to prevent thread panics
-            if let Err(e) = panic::catch_unwind(|| {
-                // Process the message
-                match process_network_message(&message) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        log::error!("Error processing message: {}", e);
-                    }
-                }
-            }) {
-                // Handle any panics that might occur
-                log::error!("Panic in message handler: {:?}", e);
-            }
-        });
+        // Drop any previous connection without triggering a reconnect, and
+        // stop a reconnect supervisor from replacing the one we open now
+        self.network.disconnect();
+        self.reconnecting = false;
+        let generation = self.next_generation();
 
         // Connect to the network using the tokio runtime
         let runtime = get_tokio_runtime();
-        runtime.block_on(async { self.network.connect(url).await })?;
+        self.network = runtime.block_on(Transport::connect(url, move |frame| {
+            handle_frame(generation, frame)
+        }))?;
 
         self.connected = true;
+        self.server_url = Some(url.to_string());
+        self.resume_token = None;
+        self.client_id.clear();
+        self.connect_reject_reason = None;
+        self.dropped_messages = 0;
+        self.reliable.reset();
+
+        self.announce()
+    }
+
+    // Invalidate the current socket, returning the generation for the next one
+    fn next_generation(&mut self) -> u64 {
+        self.generation += 1;
+        self.generation
+    }
+
+    // Get notified once the server has accepted or refused this build.
+    // Replaces any earlier waiter, whose receiver then sees a disconnect.
+    pub fn wait_for_handshake(&mut self) -> mpsc::Receiver<ConnectResult> {
+        let (sender, receiver) = mpsc::channel();
+        self.connect_waiter = Some(sender);
+        receiver
+    }
+
+    fn finish_handshake(&mut self, result: ConnectResult) {
+        if let Some(waiter) = self.connect_waiter.take() {
+            let _ = waiter.send(result);
+        }
+    }
+
+    // This member is no longer in the session, so drop every actor they owned
+    fn forget_member(&mut self, member: &str) {
+        self.remote_actors
+            .retain(|(owner_id, _), _| owner_id != member);
+        self.remote_states
+            .retain(|(owner_id, _), _| owner_id != member);
+        self.keyframe_requests
+            .retain(|(owner_id, _), _| owner_id != member);
+        self.actor_acks.remove(member);
+        self.actor_encoder.forget_peer(member);
+        self.reliable.remove_peer(member);
+        self.presences.remove(member);
+        self.queue_event(LifecycleEvent::MemberLeft(member.to_string()));
+        log::info!("Player {} has disconnected", member);
+    }
+
+    // We're out of the session without having left it, so forget everyone
+    // in it but `own_id` and everything synced with them
+    fn forget_session(&mut self, own_id: &str) {
+        let old_members = std::mem::take(&mut self.session_members);
+        for member in old_members.iter().filter(|member| *member != own_id) {
+            self.forget_member(member);
+        }
+        self.session_host = None;
+        self.session_state.clear();
+        self.flags.reset_session();
+        self.inventory.reset_session();
+        self.time.reset_session();
+        self.reliable.reset();
+    }
+
+    // Tell the server who we are, so it can turn away builds that would
+    // corrupt each other's actors
+    fn send_hello(&mut self) -> Result<()> {
+        let hello_msg = HelloMessage {
+            event_type: "hello".to_string(),
+            protocol_version: PROTOCOL_VERSION,
+            runtime_version: env!("CARGO_PKG_VERSION").to_string(),
+            layout_hash: ActorData::layout_hash(),
+        };
+
+        let json = serde_json::to_string(&hello_msg)?;
+        self.network.send_text(&json)?;
+
+        Ok(())
+    }
+
+    // First frames on every new socket
+    fn announce(&mut self) -> Result<()> {
+        // Let the server know we can receive binary frames; servers that
+        // don't understand this keep talking JSON to us
+        let select_msg = SelectCodecMessage {
+            event_type: "select_codec".to_string(),
+            codec: CODEC_BINARY.to_string(),
+        };
+
+        let json = serde_json::to_string(&select_msg)?;
+        self.network.send_text(&json)?;
+
+        // After a drop, ask for our previous connection back
+        if let Some(resume_token) = &self.resume_token {
+            let resume_msg = ResumeMessage {
+                event_type: "resume".to_string(),
+                resume_token: resume_token.clone(),
+            };
+
+            let json = serde_json::to_string(&resume_msg)?;
+            self.network.send_text(&json)?;
+        }
 
         Ok(())
     }
 
     // Join a specific game session
-    pub fn join_session(&mut self, session_id: &str) -> Result<()> {
+    pub fn join_session(&mut self, session_id: &str, options: JoinOptions) -> Result<()> {
         if !self.connected {
             return Err(anyhow::anyhow!("Not connected to server"));
         }
@@ -91,221 +374,1647 @@ impl NetworkSyncModule {
         let join_msg = JoinSessionMessage {
             event_type: "join_session".to_string(),
             session_id: session_id.to_string(),
+            password: options.password.clone(),
+            max_players: options.max_players,
+            display_name: self.session_details.display_name.clone(),
+            game_version: self.session_details.game_version.clone(),
+            unlisted: self.session_details.unlisted,
         };
 
-        // Send join request using the tokio runtime
+        // Queue the join request
         let json = serde_json::to_string(&join_msg)?;
-        let runtime = get_tokio_runtime();
-        runtime.block_on(async { self.network.send_message(&json).await })?;
+        self.network.send_text(&json)?;
 
-        // Update local state - will be confirmed by server response
-        self.current_session_id = Some(session_id.to_string());
+        // Becomes the current session once the server accepts us, which
+        // then replays its state to us
+        self.pending_session_id = Some(session_id.to_string());
+        self.session_state.clear();
+        self.presences.clear();
+        self.flags.reset_session();
+        self.inventory.reset_session();
+        self.time.reset_session();
+        self.join_options = options;
         log::info!("Sent join request for session: {}", session_id);
 
         Ok(())
     }
 
+    // Ask the server for its public sessions; they arrive as a SessionListReceived event
+    pub fn request_session_list(&mut self) -> Result<()> {
+        if !self.connected {
+            return Err(anyhow::anyhow!("Not connected to server"));
+        }
+
+        let list_msg = ListSessionsMessage {
+            event_type: "list_sessions".to_string(),
+        };
+
+        let json = serde_json::to_string(&list_msg)?;
+        self.network.send_text(&json)?;
+
+        Ok(())
+    }
+
+    // Find a session in the most recent listing
+    pub fn find_session_listing(&self, session_id: &str) -> Option<&SessionListing> {
+        self.session_list
+            .iter()
+            .find(|listing| listing.session_id == session_id)
+    }
+
+    // Get notified of the server's answer to our join of `session_id`.
+    // Replaces any earlier waiter, whose receiver then sees a disconnect.
+    pub fn wait_for_join(&mut self, session_id: &str) -> mpsc::Receiver<JoinResult> {
+        let (sender, receiver) = mpsc::channel();
+        self.join_waiter = Some((session_id.to_string(), sender));
+        receiver
+    }
+
     // Leave the current session
     pub fn leave_session(&mut self) -> Result<()> {
         if !self.connected {
             return Err(anyhow::anyhow!("Not connected to server"));
         }
 
-        if let Some(session_id) = &self.current_session_id {
+        let session_id = self
+            .current_session_id
+            .as_ref()
+            .or(self.pending_session_id.as_ref());
+
+        if let Some(session_id) = session_id {
             let leave_msg = LeaveSessionMessage {
                 event_type: "leave_session".to_string(),
             };
 
             let json = serde_json::to_string(&leave_msg)?;
 
-            // Send message using the tokio runtime
-            let runtime = get_tokio_runtime();
-            runtime.block_on(async { self.network.send_message(&json).await })?;
+            // Queue the leave request
+            self.network.send_text(&json)?;
 
-            // Update local state - will be confirmed by server response
+            // Forget the session so a reconnect doesn't rejoin it
             log::info!("Sent request to leave session: {}", session_id);
+            self.pending_session_id = None;
+            self.current_session_id = None;
+            self.session_members.clear();
+            self.session_host = None;
+            self.session_state.clear();
+            self.presences.clear();
+            self.flags.reset_session();
+            self.inventory.reset_session();
+            self.time.reset_session();
+            self.reliable.reset();
+        }
+
+        Ok(())
+    }
+
+    // Ban a member from our session, removing them if they're in it. Only
+    // the host may; the server keeps them out until the session closes.
+    pub fn ban_member(&mut self, member_id: &str) -> Result<()> {
+        if self.current_session_id.is_none() {
+            return Err(anyhow::anyhow!("Not in a session"));
+        }
+
+        if self.session_host.as_ref() != Some(&self.client_id) {
+            return Err(anyhow::anyhow!("Only the host can ban members"));
+        }
+
+        if member_id.is_empty() || member_id == self.client_id {
+            return Err(anyhow::anyhow!("Can't ban member '{}'", member_id));
+        }
+
+        let ban_msg = BanMemberMessage {
+            event_type: "ban_member".to_string(),
+            member_id: member_id.to_string(),
+        };
+
+        let json = serde_json::to_string(&ban_msg)?;
+        self.network.send_text(&json)?;
+        log::info!("Sent request to ban member: {}", member_id);
+
+        Ok(())
+    }
+
+    // Store a value in the session's state. The server keeps it for as long
+    // as the session lives and hands it to everyone who joins later.
+    pub fn set_session_state(&mut self, key: &str, value: Vec<u8>) -> Result<()> {
+        if self.current_session_id.is_none() {
+            return Err(anyhow::anyhow!("Not in a session"));
+        }
+
+        if key.is_empty() || key.len() > MAX_STATE_KEY_LEN {
+            return Err(anyhow::anyhow!(
+                "Session state keys must be 1 to {} bytes",
+                MAX_STATE_KEY_LEN
+            ));
+        }
+
+        if value.is_empty() || value.len() > MAX_STATE_VALUE_BYTES {
+            return Err(anyhow::anyhow!(
+                "Session state values must be 1 to {} bytes",
+                MAX_STATE_VALUE_BYTES
+            ));
+        }
+
+        let state_msg = SetSessionStateMessage {
+            event_type: "set_session_state".to_string(),
+            key: key.to_string(),
+            value: value.clone(),
+        };
+
+        let json = serde_json::to_string(&state_msg)?;
+        self.network.send_text(&json)?;
+
+        // The server sends it back to us too, but reads shouldn't lag our writes
+        self.session_state.insert(key.to_string(), value);
+
+        Ok(())
+    }
+
+    /// Remember where we are and tell the session, if we're in one
+    pub fn set_presence(&mut self, presence: PlayerPresence) -> Result<()> {
+        self.local_presence = Some(presence);
+        self.send_presence()
+    }
+
+    pub fn set_adjacent_scenes(&mut self, scene: u32, adjacent: Vec<u32>) -> Result<()> {
+        if adjacent.len() > MAX_ADJACENT_SCENES {
+            return Err(anyhow::anyhow!(
+                "At most {} scenes can be adjacent to one scene",
+                MAX_ADJACENT_SCENES
+            ));
+        }
+
+        self.adjacent_scenes.insert(scene, adjacent);
+        Ok(())
+    }
+
+    // Servers that don't filter actor traffic have no use for our presence
+    fn send_presence(&mut self) -> Result<()> {
+        let Some(presence) = self.local_presence else {
+            return Ok(());
+        };
+        if self.current_session_id.is_none() || !self.presence_supported {
+            return Ok(());
         }
 
+        let presence_msg = SetPresenceMessage {
+            event_type: "set_presence".to_string(),
+            scene: presence.scene,
+            room: presence.room,
+            entrance: presence.entrance,
+            adjacent_scenes: self
+                .adjacent_scenes
+                .get(&presence.scene)
+                .cloned()
+                .unwrap_or_default(),
+        };
+
+        let json = serde_json::to_string(&presence_msg)?;
+        self.network.send_text(&json)?;
+
+        // Whoever can see us now has nothing to apply deltas to
+        self.actor_encoder.force_keyframes();
+
         Ok(())
     }
 
     // Disconnect from the server
     pub fn disconnect(&mut self) -> Result<()> {
+        // Also stops a running reconnect supervisor
+        self.server_url = None;
+        self.resume_token = None;
+        self.reconnecting = false;
+        self.next_generation();
+
         if !self.connected {
             return Ok(());
         }
 
-        // Disconnect using the tokio runtime
-        let runtime = get_tokio_runtime();
-        runtime.block_on(async { self.network.disconnect().await })?;
+        // Queues a close frame; the writer task shuts the socket down
+        self.network.disconnect();
 
         self.connected = false;
+        self.binary_supported = false;
+        self.delta_supported = false;
+        self.batch_supported = false;
+        self.presence_supported = false;
+        self.client_id.clear();
+        self.pending_session_id = None;
         self.current_session_id = None;
         self.session_members.clear();
+        self.session_host = None;
+        self.session_state.clear();
+        self.presences.clear();
+        self.flags.reset_session();
+        self.inventory.reset_session();
+        self.time.reset_session();
+        self.reliable.reset();
 
         Ok(())
     }
 
-    // Sends an actor sync event
-    pub fn send_actor_sync(&mut self, player_data: &ActorData) -> Result<()> {
+    // Hold an actor update for the next send tick, or send it now if scheduling is off
+    pub fn queue_actor_update(&mut self, actor_id: &str, payload: ActorPayload) -> Result<()> {
         if !self.connected {
             return Err(anyhow::anyhow!("Not connected"));
         }
 
-        let player_update_msg = ActorSyncMessage {
-            event_type: "actor_sync".to_string(),
-            sender_id: self.client_id.clone(),
-            data: player_data.clone(),
-        };
-
-        let json = serde_json::to_string(&player_update_msg)?;
+        if self.scheduler.tick_interval().is_none() {
+            return self.send_actor_update(actor_id, &payload);
+        }
 
-        // Send player data to the server
-        let runtime = get_tokio_runtime();
-        runtime.block_on(async { self.network.send_message(&json).await })?;
+        self.scheduler.queue(actor_id, payload);
+        self.start_ticking();
 
         Ok(())
     }
 
-    // Send a message to other clients
-    pub fn send_message(&mut self, message_id: &str, data: Vec<u8>) -> Result<()> {
-        if !self.connected {
-            return Err(anyhow::anyhow!("Not connected"));
+    fn start_ticking(&mut self) {
+        if !self.ticking {
+            self.ticking = true;
+            get_tokio_runtime().spawn(send_tick_loop());
         }
+    }
 
-        if let Some(session_id) = &self.current_session_id {
-            let msg = RegisteredMessage {
-                event_type: "registered_message".to_string(),
-                sender_id: self.client_id.clone(),
-                message_id: message_id.to_string(),
-                data,
+    // Send every actor update that is due and every held message, packed into
+    // as few batches as the server will take
+    fn flush_tick(&mut self) {
+        let now = Instant::now();
+
+        let mut outgoing = Vec::new();
+        for (actor_id, payload) in self.scheduler.due(now) {
+            let (update, entry) = self.actor_entry(&actor_id, &payload, now);
+            outgoing.push((entry, Some((actor_id, update, payload))));
+        }
+        outgoing.extend(self.outbox.drain(..).map(|entry| (entry, None)));
+
+        let mut batch = Vec::new();
+        let mut batch_actors = Vec::new();
+        let mut batch_bytes = 0;
+
+        for (entry, actor) in outgoing {
+            let encoded = match entry.encode(self.binary_supported) {
+                Ok(encoded) => encoded,
+                Err(e) => {
+                    log::error!("Failed to encode outgoing message: {}", e);
+                    continue;
+                }
             };
 
-            let json = serde_json::to_string(&msg)?;
+            let batch_full = !self.batch_supported
+                || batch.len() >= MAX_BATCH_ENTRIES
+                || batch_bytes + encoded.len() > MAX_BATCH_BYTES;
+            if !batch.is_empty() && batch_full {
+                self.send_batch(
+                    std::mem::take(&mut batch),
+                    std::mem::take(&mut batch_actors),
+                    now,
+                );
+                batch_bytes = 0;
+            }
+
+            batch_bytes += encoded.len();
+            batch.push(encoded);
+            batch_actors.extend(actor);
+        }
+
+        if !batch.is_empty() {
+            self.send_batch(batch, batch_actors, now);
+        }
+    }
+
+    // Send encoded entries as one frame, bare if there's only one, then record
+    // the actor updates in it as sent
+    fn send_batch(
+        &mut self,
+        entries: Vec<Vec<u8>>,
+        actors: Vec<(String, Option<ActorUpdate>, ActorPayload)>,
+        now: Instant,
+    ) {
+        let count = entries.len();
+        let result = if count == 1 {
+            self.send_encoded(entries.into_iter().next().unwrap_or_default())
+        } else {
+            encode_batch(&self.client_id, &entries, self.binary_supported)
+                .and_then(|frame| self.send_encoded(frame))
+        };
 
-            // Send message to the server
-            let runtime = get_tokio_runtime();
-            runtime.block_on(async { self.network.send_message(&json).await })?;
+        match result {
+            Ok(_) => {
+                for (actor_id, update, payload) in actors {
+                    self.scheduler.sent(&actor_id, now);
+                    self.actor_update_sent(&actor_id, update.as_ref(), &payload, now);
+                }
+            }
+            Err(e) if is_queue_full(&e) => {
+                // Try actors again next tick unless a newer update replaces them
+                log::debug!("Outbound queue full, holding {} updates", count);
+                for (actor_id, _, payload) in actors {
+                    self.scheduler.requeue(&actor_id, payload);
+                }
+            }
+            Err(e) => log::error!("Failed to send {} updates: {}", count, e),
+        }
+    }
 
-            log::debug!("Sent message '{}' to session {}", message_id, session_id);
+    // Queue an encoded frame in the codec the server expects
+    fn send_encoded(&self, frame: Vec<u8>) -> Result<()> {
+        if self.binary_supported {
+            self.network.send_binary(frame)?;
+        } else {
+            self.network.send_text(&String::from_utf8(frame)?)?;
         }
 
         Ok(())
     }
 
-    // Get the size of the next message in the queue
-    pub fn get_pending_message_size(&self) -> u32 {
-        if let Some((_, data)) = self.message_queue.front() {
-            data.len() as u32
+    // Actors registered before we knew our client id fall back to it
+    fn network_actor_id(&self, actor_id: &str) -> String {
+        if actor_id.is_empty() {
+            self.client_id.clone()
         } else {
-            0 // No messages
+            actor_id.to_string()
         }
     }
 
-    // Get the next message from the queue
-    pub fn get_message(&mut self, buffer: &mut [u8]) -> Option<String> {
-        if let Some((message_id, data)) = self.message_queue.pop_front() {
-            if buffer.len() >= data.len() {
-                buffer[..data.len()].copy_from_slice(&data);
-                Some(message_id)
-            } else {
-                log::error!(
-                    "Buffer too small for message: {} > {}",
-                    data.len(),
-                    buffer.len()
-                );
-                None
+    // Build the frame for an actor update. Player data also reports how the
+    // delta encoder packed it.
+    fn actor_entry(
+        &self,
+        actor_id: &str,
+        payload: &ActorPayload,
+        now: Instant,
+    ) -> (Option<ActorUpdate>, BatchEntry) {
+        match payload {
+            ActorPayload::Player(data) => {
+                let (update, entry) = self.actor_sync_entry(actor_id, data, now);
+                (Some(update), entry)
+            }
+            ActorPayload::Schema { schema, data } => {
+                let entry = BatchEntry::ActorState(ActorStateMessage {
+                    event_type: "actor_state".to_string(),
+                    sender_id: self.client_id.clone(),
+                    actor_id: self.network_actor_id(actor_id),
+                    schema: schema.clone(),
+                    data: data.clone(),
+                });
+                (None, entry)
             }
-        } else {
-            None
         }
     }
 
-    // Queue a message
-    fn queue_message(&mut self, message_id: String, data: Vec<u8>) {
-        self.message_queue.push_back((message_id, data));
+    // Build an actor's next frame, in full or as a delta against the last one sent
+    fn actor_sync_entry(
+        &self,
+        actor_id: &str,
+        player_data: &ActorData,
+        now: Instant,
+    ) -> (ActorUpdate, BatchEntry) {
+        let actor_id = self.network_actor_id(actor_id);
+
+        let update = if self.delta_supported {
+            self.actor_encoder.prepare(&actor_id, player_data, now)
+        } else {
+            ActorUpdate::Keyframe { seq: 0 }
+        };
+
+        let entry = match &update {
+            ActorUpdate::Keyframe { seq } => BatchEntry::ActorSync(ActorSyncMessage {
+                event_type: "actor_sync".to_string(),
+                sender_id: self.client_id.clone(),
+                actor_id,
+                data: player_data.clone(),
+                seq: *seq,
+            }),
+            ActorUpdate::Delta {
+                seq,
+                baseline,
+                mask,
+                data,
+            } => BatchEntry::ActorDelta(ActorDeltaMessage {
+                event_type: "actor_delta".to_string(),
+                sender_id: self.client_id.clone(),
+                actor_id,
+                seq: *seq,
+                baseline: *baseline,
+                mask: *mask,
+                data: data.clone(),
+            }),
+        };
+
+        (update, entry)
     }
-}
 
-// Separate function to process messages that can safely access the global singleton
-fn process_network_message(message: &str) -> Result<()> {
-    // Check if the message is empty or just whitespace
-    if message.trim().is_empty() {
-        log::debug!("Received empty message, ignoring");
-        return Ok(());
+    // Only frames that made it into the queue can be built on
+    fn actor_update_sent(
+        &mut self,
+        actor_id: &str,
+        update: Option<&ActorUpdate>,
+        payload: &ActorPayload,
+        now: Instant,
+    ) {
+        if let (true, Some(update), ActorPayload::Player(data)) =
+            (self.delta_supported, update, payload)
+        {
+            let actor_id = self.network_actor_id(actor_id);
+            self.actor_encoder.sent(&actor_id, update, data, now);
+        }
     }
 
-    // Parse NetworkMessage
-    let server_msg = match serde_json::from_str::<ServerMessage>(message) {
-        Ok(msg) => msg,
-        Err(e) => {
-            log::debug!("Failed to parse message: {} (Error: {})", message, e);
-            return Ok(());
+    // Sends an update for one of our owned actors right away
+    pub fn send_actor_update(&mut self, actor_id: &str, payload: &ActorPayload) -> Result<()> {
+        if !self.connected {
+            return Err(anyhow::anyhow!("Not connected"));
         }
-    };
 
-    let network_sync = get_network_sync();
-    let mut module = network_sync.lock().unwrap();
+        let now = Instant::now();
+        let (update, entry) = self.actor_entry(actor_id, payload, now);
 
-    match server_msg {
-        ServerMessage::Welcome(msg) => {
-            module.client_id = msg.sender_id.clone();
-            log::info!("Connected as player ID: {}", module.client_id);
+        // Queue actor data for the server
+        self.send_encoded(entry.encode(self.binary_supported)?)?;
+        self.actor_update_sent(actor_id, update.as_ref(), payload, now);
+
+        Ok(())
+    }
+
+    // Send a message to other clients, or only to `targets` if any are given.
+    // Messages for the whole session wait for the next tick to be batched.
+    pub fn send_message(
+        &mut self,
+        message_id: &str,
+        data: Vec<u8>,
+        echo: bool,
+        targets: Vec<String>,
+    ) -> Result<()> {
+        if !self.connected {
+            return Err(anyhow::anyhow!("Not connected"));
         }
 
-        ServerMessage::SessionMembers(msg) => {
-            if let Some(session_id) = msg.data.get("session_id").and_then(|v| v.as_str()) {
-                // Update the member list
-                if let Some(members) = msg.data.get("members").and_then(|v| v.as_array()) {
-                    let session_members: Vec<String> = members
-                        .iter()
-                        .filter_map(|v| v.as_str().map(String::from))
-                        .collect();
+        if let Some(session_id) = &self.current_session_id {
+            let batchable = self.batch_supported
+                && self.scheduler.tick_interval().is_some()
+                && !echo
+                && targets.is_empty();
 
-                    // Get current members to identify disconnected players
-                    let old_members =
-                        std::mem::replace(&mut module.session_members, session_members.clone());
+            let msg = RegisteredMessage {
+                event_type: "registered_message".to_string(),
+                sender_id: self.client_id.clone(),
+                message_id: message_id.to_string(),
+                data,
+                echo,
+                targets,
+                seq: 0,
+            };
 
-                    // Find any members that were removed (disconnected)
-                    for old_member in old_members {
-                        if !session_members.contains(&old_member) {
-                            // This player is no longer in the session, remove them from remote_players
-                            module.remote_actors.remove(&old_member);
-                            log::info!("Player {} has disconnected", old_member);
-                        }
-                    }
+            log::debug!("Sending message '{}' to session {}", message_id, session_id);
 
-                    log::info!(
-                        "Session '{}' updated: {} members: {:?}",
-                        session_id,
-                        module.session_members.len(),
-                        module.session_members
-                    );
-                }
+            let entry = BatchEntry::Registered(msg);
+            if batchable {
+                self.outbox.push(entry);
+                self.start_ticking();
+            } else {
+                // Queue message for the server
+                self.send_encoded(entry.encode(self.binary_supported)?)?;
             }
         }
 
-        ServerMessage::ActorSync(msg) => {
-            if msg.sender_id != module.client_id {
-                // Only store data from other players, not ourself
-                let remote_data = RemoteActorData {
-                    id: msg.sender_id.clone(),
-                    data: msg.data.clone(),
-                    last_update: Instant::now(),
-                };
+        Ok(())
+    }
 
-                // Store the remote player data
-                module
-                    .remote_actors
-                    .insert(msg.sender_id.clone(), remote_data);
+    // Send a message to every other session member, retrying until each one
+    // acknowledges it. Returns a handle for checking its delivery status.
+    pub fn send_reliable_message(&mut self, message_id: &str, data: Vec<u8>) -> Result<u32> {
+        if !self.connected {
+            return Err(anyhow::anyhow!("Not connected"));
+        }
+
+        if self.current_session_id.is_none() {
+            return Err(anyhow::anyhow!("Not in a session"));
+        }
+
+        let recipients: Vec<String> = self
+            .session_members
+            .iter()
+            .filter(|member| **member != self.client_id)
+            .cloned()
+            .collect();
 
-                log::debug!("Received actor sync from {}", msg.sender_id);
+        let (handle, outgoing) = self.reliable.queue(message_id, data, &recipients);
+
+        // Copies that don't make it into the queue now go out with the next resend
+        for message in &outgoing {
+            if let Err(e) = self.send_reliable_copy(message) {
+                log::warn!(
+                    "Deferring reliable message '{}' to {}: {}",
+                    message.message_id,
+                    message.recipient,
+                    e
+                );
             }
         }
 
-        ServerMessage::RegisteredMessage(msg) => {
-            if msg.sender_id != module.client_id {
-                module.queue_message(msg.message_id.clone(), msg.data);
+        if self.reliable.has_pending() && !self.resending {
+            self.resending = true;
+            get_tokio_runtime().spawn(reliable_resend_loop());
+        }
+
+        log::debug!(
+            "Sent reliable message '{}' to {} members",
+            message_id,
+            recipients.len()
+        );
+
+        Ok(handle)
+    }
+
+    pub fn delivery_status(&self, handle: u32) -> DeliveryStatus {
+        self.reliable.status(handle)
+    }
+
+    fn send_reliable_copy(&self, message: &OutgoingReliable) -> Result<()> {
+        let msg = RegisteredMessage {
+            event_type: "registered_message".to_string(),
+            sender_id: self.client_id.clone(),
+            message_id: message.message_id.clone(),
+            data: message.data.clone(),
+            echo: false,
+            targets: vec![message.recipient.clone()],
+            seq: message.seq,
+        };
+
+        if self.binary_supported {
+            self.network.send_binary(msg.to_binary())?;
+        } else {
+            let json = serde_json::to_string(&msg)?;
+            self.network.send_text(&json)?;
+        }
+
+        Ok(())
+    }
+
+    // Confirm a reliable message to its sender
+    fn send_ack(&self, target: &str, seq: u32) -> Result<()> {
+        let ack_msg = MessageAckMessage {
+            event_type: "message_ack".to_string(),
+            sender_id: self.client_id.clone(),
+            target: target.to_string(),
+            seq,
+        };
+
+        if self.binary_supported {
+            self.network.send_binary(ack_msg.to_binary())?;
+        } else {
+            let json = serde_json::to_string(&ack_msg)?;
+            self.network.send_text(&json)?;
+        }
+
+        Ok(())
+    }
+
+    // Ask the owner of an actor whose delta we couldn't apply to send it in full
+    fn request_keyframe(&mut self, owner_id: &str, actor_id: &str) -> Result<()> {
+        let now = Instant::now();
+        let key = (owner_id.to_string(), actor_id.to_string());
+        if self
+            .keyframe_requests
+            .get(&key)
+            .is_some_and(|requested| now.duration_since(*requested) < KEYFRAME_REQUEST_INTERVAL)
+        {
+            return Ok(());
+        }
+
+        self.keyframe_requests.insert(key, now);
+        self.send_message(
+            KEYFRAME_REQUEST_MESSAGE_ID,
+            actor_id.as_bytes().to_vec(),
+            false,
+            vec![owner_id.to_string()],
+        )
+    }
+
+    // Tell an owner the newest frame we have of each of its actors, at most
+    // every `ACK_INTERVAL`, so its deltas build on frames we can apply
+    fn ack_actor_frames(&mut self, owner_id: &str, now: Instant) -> Result<()> {
+        if self
+            .actor_acks
+            .get(owner_id)
+            .is_some_and(|acked| now.duration_since(*acked) < ACK_INTERVAL)
+        {
+            return Ok(());
+        }
+
+        let acks: Vec<(&str, u32)> = self
+            .remote_actors
+            .values()
+            .filter(|actor| actor.owner_id == owner_id && actor.sync_seq != 0)
+            .map(|actor| (actor.id.as_str(), actor.sync_seq))
+            .collect();
+        if acks.is_empty() {
+            return Ok(());
+        }
+
+        let data = encode_acks(&acks);
+        self.actor_acks.insert(owner_id.to_string(), now);
+        self.send_message(
+            ACTOR_ACK_MESSAGE_ID,
+            data,
+            false,
+            vec![owner_id.to_string()],
+        )
+    }
+
+    // Find a remote actor by its owner and network id. Without an owner the
+    // player actor of that id wins, then the first owner syncing the id.
+    pub fn find_remote_actor(
+        &self,
+        owner_id: Option<&str>,
+        actor_id: &str,
+    ) -> Option<&RemoteActorData> {
+        find_owned(&self.remote_actors, owner_id, actor_id)
+    }
+
+    // A remote actor's state `interpolation_delay` ago, blended between samples
+    pub fn interpolated_remote_actor(
+        &self,
+        owner_id: Option<&str>,
+        actor_id: &str,
+    ) -> Option<ActorData> {
+        let render_time = Instant::now()
+            .checked_sub(self.interpolation_delay)
+            .unwrap_or_else(Instant::now);
+
+        self.find_remote_actor(owner_id, actor_id)
+            .map(|actor| actor.sample_at(render_time, self.max_extrapolation))
+    }
+
+    // Find a schema synced remote actor the same way as `find_remote_actor`
+    pub fn find_remote_state(
+        &self,
+        owner_id: Option<&str>,
+        actor_id: &str,
+    ) -> Option<&RemoteActorState> {
+        find_owned(&self.remote_states, owner_id, actor_id)
+    }
+
+    // Time since a remote actor's last update
+    pub fn remote_actor_age(&self, owner_id: Option<&str>, actor_id: &str) -> Option<Duration> {
+        let now = Instant::now();
+
+        self.find_remote_actor(owner_id, actor_id)
+            .map(|actor| actor.age(now))
+            .or_else(|| {
+                self.find_remote_state(owner_id, actor_id)
+                    .map(|state| now.saturating_duration_since(state.last_update))
+            })
+    }
+
+    // Whether a remote actor has gone quiet long enough that mods should hide it
+    pub fn is_remote_actor_stale(&self, owner_id: Option<&str>, actor_id: &str) -> bool {
+        self.remote_actor_age(owner_id, actor_id)
+            .is_some_and(|age| age >= self.staleness_policy.hide_after)
+    }
+
+    // (owner, actor id) of every remote actor, sorted so listings are stable
+    pub fn remote_actor_keys(&self) -> Vec<&(String, String)> {
+        let mut keys: Vec<_> = self.remote_actors.keys().collect();
+        keys.sort();
+        keys
+    }
+
+    // Forget remote actors that stopped updating longer ago than the policy allows
+    pub fn remove_stale_actors(&mut self) {
+        let Some(remove_after) = self.staleness_policy.remove_after else {
+            return;
+        };
+
+        let now = Instant::now();
+        self.remote_actors.retain(|_, actor| {
+            let keep = actor.age(now) < remove_after;
+            if !keep {
+                log::info!(
+                    "Removing remote actor {} of {} after {:?} without updates",
+                    actor.id,
+                    actor.owner_id,
+                    actor.age(now)
+                );
+            }
+            keep
+        });
+        self.keyframe_requests
+            .retain(|_, requested| now.duration_since(*requested) < remove_after);
+        self.remote_states.retain(|_, state| {
+            let keep = now.saturating_duration_since(state.last_update) < remove_after;
+            if !keep {
+                log::info!(
+                    "Removing remote actor {} of {} after going quiet",
+                    state.id,
+                    state.owner_id
+                );
+            }
+            keep
+        });
+    }
+
+    // Get the size of the next message in the queue
+    pub fn get_pending_message_size(&self) -> u32 {
+        if let Some((_, data)) = self.message_queue.front() {
+            data.len() as u32
+        } else {
+            0 // No messages
+        }
+    }
+
+    // Get the next message from the queue
+    pub fn get_message(&mut self, buffer: &mut [u8]) -> Option<String> {
+        if let Some((message_id, data)) = self.message_queue.pop_front() {
+            if buffer.len() >= data.len() {
+                buffer[..data.len()].copy_from_slice(&data);
+                Some(message_id)
+            } else {
+                log::error!(
+                    "Buffer too small for message: {} > {}",
+                    data.len(),
+                    buffer.len()
+                );
+                None
+            }
+        } else {
+            None
+        }
+    }
+
+    // Hand a message to the mod, unless one of our own subsystems sent it
+    fn deliver_message(&mut self, sender_id: &str, message_id: String, data: Vec<u8>) {
+        if message_id == ACTOR_ACK_MESSAGE_ID {
+            match decode_acks(&data) {
+                Ok(acks) => {
+                    let now = Instant::now();
+                    for (actor_id, seq) in acks {
+                        self.actor_encoder.acked(sender_id, &actor_id, seq, now);
+                    }
+                }
+                Err(e) => log::warn!("Dropping actor acks from {}: {}", sender_id, e),
+            }
+            return;
+        }
+
+        if message_id == KEYFRAME_REQUEST_MESSAGE_ID {
+            match String::from_utf8(data) {
+                Ok(actor_id) => self.actor_encoder.force_keyframe(&actor_id),
+                Err(e) => log::warn!("Dropping keyframe request from {}: {}", sender_id, e),
+            }
+            return;
+        }
+
+        if let Some(name) = message_id.strip_prefix(FLAG_MESSAGE_PREFIX) {
+            if let Err(e) = self.flags.receive(sender_id, name, &data) {
+                log::warn!("Dropping flag changes from {}: {}", sender_id, e);
+            }
+            return;
+        }
+
+        if message_id.starts_with(TIME_MESSAGE_PREFIX) {
+            let host_id = self.session_host.clone();
+            match self
+                .time
+                .receive(sender_id, &message_id, &data, host_id.as_deref())
+            {
+                Ok(Some(event)) => self.queue_event(event),
+                Ok(None) => {}
+                Err(e) => log::warn!("Dropping time message from {}: {}", sender_id, e),
+            }
+            return;
+        }
+
+        if message_id == INVENTORY_MESSAGE_ID {
+            if let Err(e) = self.inventory.receive(&data) {
+                log::warn!("Dropping inventory changes from {}: {}", sender_id, e);
+            }
+            return;
+        }
+
+        self.message_queue.push_back((message_id, data));
+    }
+
+    // Merge the flag changes other members sent into guest memory and send
+    // ours. Called once per frame by the mod.
+    pub unsafe fn sync_flags(&mut self, rdram: *mut u8) -> Result<()> {
+        // Diffing moves the baseline on, so wait out a reconnect rather than
+        // lose changes we couldn't send
+        if self.current_session_id.is_none() || !self.connected {
+            return Ok(());
+        }
+
+        let output = self
+            .flags
+            .sync(rdram, &self.client_id, self.session_host.as_deref());
+
+        for (message_id, data) in output.messages {
+            self.send_reliable_message(&message_id, data)?;
+        }
+
+        for (key, value) in output.state {
+            self.set_session_state(&key, value)?;
+        }
+
+        Ok(())
+    }
+
+    // Merge the inventory changes other members sent into `state` and send
+    // ours. Called once per frame by the mod; returns whether `state` changed.
+    pub fn sync_inventory(&mut self, state: &mut InventoryState) -> Result<bool> {
+        // As with flags, the baseline only moves once the changes can go out
+        if self.current_session_id.is_none() || !self.connected {
+            return Ok(false);
+        }
+
+        let is_host = self.session_host.as_ref() == Some(&self.client_id);
+        let (changed, output) = self.inventory.sync(state, is_host);
+
+        if let Some(data) = output.message {
+            self.send_reliable_message(INVENTORY_MESSAGE_ID, data)?;
+        }
+
+        if let Some(value) = output.state {
+            self.set_session_state(INVENTORY_STATE_KEY, value)?;
+        }
+
+        Ok(changed)
+    }
+
+    // Bring `state` toward the host's clock, or send ours as host, and resolve
+    // time transitions. Called once per frame by the mod; returns whether
+    // `state` changed.
+    pub fn sync_time(&mut self, state: &mut TimeState) -> Result<bool> {
+        if self.current_session_id.is_none() {
+            return Ok(false);
+        }
+
+        let host_id = self.session_host.clone();
+        let members = self.session_members.clone();
+        let (changed, output) =
+            self.time
+                .sync(state, &self.client_id, host_id.as_deref(), &members);
+
+        for event in output.events {
+            self.queue_event(event);
+        }
+
+        for (message_id, data, reliable) in output.messages {
+            if reliable {
+                self.send_reliable_message(message_id, data)?;
+            } else {
+                self.send_message(message_id, data, false, Vec::new())?;
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Ask the session to accept a time transition of `kind`, returning its ID
+    pub fn propose_time_transition(&mut self, kind: u32) -> Result<String> {
+        if self.current_session_id.is_none() {
+            return Err(anyhow::anyhow!("Not in a session"));
+        }
+
+        let client_id = self.client_id.clone();
+        let (id, data) = self.time.propose(&client_id, kind)?;
+        self.send_reliable_message(PROPOSE_MESSAGE_ID, data)?;
+        Ok(id)
+    }
+
+    pub fn respond_time_transition(&mut self, id: &str, accept: bool) -> Result<()> {
+        let client_id = self.client_id.clone();
+        let data = self.time.respond(&client_id, id, accept)?;
+        self.send_reliable_message(VOTE_MESSAGE_ID, data)?;
+        Ok(())
+    }
+
+    // Queue a lifecycle event, dropping the oldest if the mod isn't polling
+    fn queue_event(&mut self, event: LifecycleEvent) {
+        if self.event_queue.len() >= MAX_QUEUED_EVENTS {
+            self.event_queue.pop_front();
+        }
+        self.event_queue.push_back(event);
+    }
+
+    // Get the next lifecycle event from the queue
+    pub fn poll_event(&mut self) -> Option<LifecycleEvent> {
+        self.event_queue.pop_front()
+    }
+
+    fn count_dropped_message(&mut self) {
+        self.dropped_messages = self.dropped_messages.saturating_add(1);
+    }
+}
+
+// Look up an entry keyed by (owner, actor id). Without an owner, a player's
+// own actor is preferred, then the lowest owner id syncing an actor of that id.
+fn find_owned<'a, T>(
+    entries: &'a HashMap<(String, String), T>,
+    owner_id: Option<&str>,
+    actor_id: &str,
+) -> Option<&'a T> {
+    let owner = owner_id.unwrap_or(actor_id);
+    if let Some(entry) = entries.get(&(owner.to_string(), actor_id.to_string())) {
+        return Some(entry);
+    }
+    if owner_id.is_some() {
+        return None;
+    }
+
+    entries
+        .iter()
+        .filter(|((_, id), _)| id == actor_id)
+        .min_by(|((a, _), _), ((b, _), _)| a.cmp(b))
+        .map(|(_, entry)| entry)
+}
+
+// Every inbound frame is handed to this handler from the reader task of the
+// socket opened as `generation`
+fn handle_frame(generation: u64, frame: InboundFrame) {
+    // Use catch_unwind to prevent thread panics
+    if let Err(e) = panic::catch_unwind(move || {
+        let network_sync = get_network_sync();
+        let mut module = network_sync.lock().unwrap();
+
+        // Aborting a reader doesn't stop a frame it's already handing over
+        if generation != module.generation {
+            log::debug!("Dropping frame from a replaced connection");
+            return;
+        }
+
+        // Process the message
+        let result = match frame {
+            InboundFrame::Text(message) => process_network_message(&mut module, &message),
+            InboundFrame::Binary(bytes) => process_binary_message(&mut module, &bytes),
+            InboundFrame::Closed => process_connection_closed(&mut module),
+        };
+
+        if let Err(e) = result {
+            log::error!("Error processing message: {}", e);
+        }
+    }) {
+        // Handle any panics that might occur
+        log::error!("Panic in message handler: {:?}", e);
+    }
+}
+
+// The socket dropped underneath us; start reconnecting if allowed
+fn process_connection_closed(module: &mut NetworkSyncModule) -> Result<()> {
+    module.connected = false;
+    module.binary_supported = false;
+    module.delta_supported = false;
+    module.batch_supported = false;
+    module.presence_supported = false;
+    module.queue_event(LifecycleEvent::Disconnected);
+    log::warn!("Lost connection to server");
+
+    if module.reconnecting || module.server_url.is_none() {
+        return Ok(());
+    }
+
+    if module.reconnect_policy.max_attempts == 0 {
+        log::info!("Reconnection is disabled");
+        return Ok(());
+    }
+
+    module.reconnecting = true;
+    tokio::spawn(reconnect_supervisor(module.generation));
+
+    Ok(())
+}
+
+// Reconnects with exponential backoff, then resumes our previous connection.
+// `generation` is that of the socket that dropped; once the mod connects or
+// disconnects itself, the generation moves on and the supervisor gives up.
+async fn reconnect_supervisor(mut generation: u64) {
+    let network_sync = get_network_sync();
+    let policy = network_sync.lock().unwrap().reconnect_policy;
+    let mut delay = policy.initial_delay;
+
+    for attempt in 1..=policy.max_attempts {
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(policy.max_delay);
+
+        // Stop if the mod took over the connection while we were waiting
+        let url = {
+            let mut module = network_sync.lock().unwrap();
+            if module.generation != generation {
+                return;
+            }
+
+            match module.server_url.clone() {
+                Some(url) => url,
+                None => {
+                    module.reconnecting = false;
+                    return;
+                }
+            }
+        };
+
+        // Claim the next generation up front, so frames the new socket gets
+        // before we install it aren't mistaken for stale ones
+        generation = network_sync.lock().unwrap().next_generation();
+
+        log::info!(
+            "Reconnecting to {} (attempt {}/{})",
+            url,
+            attempt,
+            policy.max_attempts
+        );
+
+        let handler = move |frame| handle_frame(generation, frame);
+        match Transport::connect(&url, handler).await {
+            Ok(mut transport) => {
+                let mut module = network_sync.lock().unwrap();
+                if module.generation != generation {
+                    transport.disconnect();
+                    return;
+                }
+
+                module.reconnecting = false;
+                module.network.disconnect();
+                module.network = transport;
+                module.connected = true;
+
+                if let Err(e) = module.announce() {
+                    log::error!("Failed to resume connection: {}", e);
+                }
+
+                log::info!("Reconnected to {}", url);
+                return;
+            }
+            Err(e) => {
+                log::warn!("Reconnect attempt {} failed: {}", attempt, e);
+            }
+        }
+    }
+
+    log::error!(
+        "Giving up on reconnecting after {} attempts",
+        policy.max_attempts
+    );
+
+    let mut module = network_sync.lock().unwrap();
+    if module.generation != generation {
+        return;
+    }
+
+    module.reconnecting = false;
+    module.resume_token = None;
+    module.client_id.clear();
+    module.pending_session_id = None;
+    module.current_session_id = None;
+    module.session_members.clear();
+    module.session_host = None;
+    module.session_state.clear();
+    module.presences.clear();
+    module.flags.reset_session();
+    module.inventory.reset_session();
+    module.time.reset_session();
+    module.remote_actors.clear();
+    module.keyframe_requests.clear();
+    module.actor_acks.clear();
+    module.remote_states.clear();
+    module.reliable.reset();
+    module.queue_event(LifecycleEvent::ReconnectFailed);
+}
+
+// Flushes held actor updates and messages every tick for as long as we're connected
+async fn send_tick_loop() {
+    let network_sync = get_network_sync();
+
+    loop {
+        let interval = network_sync.lock().unwrap().scheduler.tick_interval();
+        if let Some(interval) = interval {
+            tokio::time::sleep(interval).await;
+        }
+
+        let mut module = network_sync.lock().unwrap();
+        if module.connected {
+            module.flush_tick();
+        }
+
+        // Stop once disconnected or scheduling was turned off
+        if !module.connected || interval.is_none() {
+            // Anything still held is stale by the time we could send it
+            module.scheduler.clear_pending();
+            module.outbox.clear();
+            module.ticking = false;
+            return;
+        }
+    }
+}
+
+// Resends reliable messages whose ack is overdue, until none are left
+async fn reliable_resend_loop() {
+    let network_sync = get_network_sync();
+
+    loop {
+        tokio::time::sleep(RESEND_INTERVAL / 4).await;
+
+        let mut module = network_sync.lock().unwrap();
+        if !module.reliable.has_pending() {
+            module.resending = false;
+            return;
+        }
+
+        // Don't burn attempts while there's no one to send to
+        if !module.connected || module.reconnecting || module.client_id.is_empty() {
+            continue;
+        }
+
+        for message in module.reliable.due_for_resend(Instant::now()) {
+            if let Err(e) = module.send_reliable_copy(&message) {
+                log::warn!(
+                    "Failed to resend reliable message '{}' to {}: {}",
+                    message.message_id,
+                    message.recipient,
+                    e
+                );
+            }
+        }
+    }
+}
+
+// Separate function to process messages that can safely access the global singleton
+fn process_network_message(module: &mut NetworkSyncModule, message: &str) -> Result<()> {
+    // Check if the message is empty or just whitespace
+    if message.trim().is_empty() {
+        log::debug!("Received empty message, ignoring");
+        return Ok(());
+    }
+
+    let server_msg = match ServerMessage::from_json(message) {
+        Ok(msg) => msg,
+        Err(e) => {
+            log::warn!("Dropping message from server: {}", e);
+            log::debug!("Dropped message: {}", message);
+            module.count_dropped_message();
+            return Ok(());
+        }
+    };
+
+    handle_server_message(module, server_msg)
+}
+
+// Binary frames carry the same messages in a compact layout
+fn process_binary_message(module: &mut NetworkSyncModule, bytes: &[u8]) -> Result<()> {
+    let server_msg = match ServerMessage::from_binary(bytes) {
+        Ok(msg) => msg,
+        Err(e) => {
+            log::warn!("Dropping binary message from server: {}", e);
+            module.count_dropped_message();
+            return Ok(());
+        }
+    };
+
+    handle_server_message(module, server_msg)
+}
+
+fn handle_server_message(module: &mut NetworkSyncModule, server_msg: ServerMessage) -> Result<()> {
+    // Batched messages are handled one by one, in the order they were sent
+    let server_msg = match server_msg {
+        ServerMessage::Batch(messages) => {
+            for message in messages {
+                if let Err(e) = handle_server_message(module, message) {
+                    log::error!("Error processing batched message: {}", e);
+                }
+            }
+            return Ok(());
+        }
+        message => message,
+    };
+
+    match server_msg {
+        ServerMessage::Welcome(msg) => {
+            // While resuming, the token and our final id come from the resume result
+            // instead, and until then we keep the old id to tell ourselves apart
+            if module.resume_token.is_none() {
+                module.client_id = msg.sender_id.clone();
+                module.resume_token = msg
+                    .data
+                    .get("resume_token")
+                    .and_then(|v| v.as_str())
+                    .map(String::from);
+
+                let client_id = module.client_id.clone();
+                module.queue_event(LifecycleEvent::Connected(client_id));
+            }
+
+            module.binary_supported = msg
+                .data
+                .get("codecs")
+                .and_then(|v| v.as_array())
+                .is_some_and(|codecs| codecs.iter().any(|c| c.as_str() == Some(CODEC_BINARY)));
+            let has_feature = |name: &str| {
+                msg.data
+                    .get("features")
+                    .and_then(|v| v.as_array())
+                    .is_some_and(|features| features.iter().any(|f| f.as_str() == Some(name)))
+            };
+            module.delta_supported = has_feature(FEATURE_ACTOR_DELTA);
+            module.batch_supported = has_feature(FEATURE_BATCH);
+            module.presence_supported = has_feature(FEATURE_PRESENCE);
+
+            // Servers that don't check builds have accepted us already
+            if has_feature(FEATURE_HELLO) {
+                module.send_hello()?;
+            } else {
+                module.finish_handshake(ConnectResult::Connected);
+            }
+
+            // Whoever sees us on this connection has nothing to apply deltas to
+            module.actor_encoder.force_keyframes();
+
+            log::info!(
+                "Connected as player ID: {} (binary framing: {}, actor deltas: {}, batching: {})",
+                msg.sender_id,
+                module.binary_supported,
+                module.delta_supported,
+                module.batch_supported
+            );
+        }
+
+        ServerMessage::HelloResult(msg) => {
+            let accepted = msg
+                .data
+                .get("accepted")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+
+            if accepted {
+                module.finish_handshake(ConnectResult::Connected);
+            } else {
+                let reason = msg
+                    .data
+                    .get("reason")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("unknown")
+                    .to_string();
+                log::error!(
+                    "Server refused runtime {} (protocol {}): {}",
+                    env!("CARGO_PKG_VERSION"),
+                    PROTOCOL_VERSION,
+                    reason
+                );
+
+                // Retrying won't change the answer, so don't reconnect
+                module.connect_reject_reason = Some(reason.clone());
+                module.finish_handshake(ConnectResult::Incompatible(reason));
+                module.disconnect()?;
+                module.queue_event(LifecycleEvent::Disconnected);
+            }
+        }
+
+        ServerMessage::ResumeResult(msg) => {
+            let previous_id = std::mem::replace(&mut module.client_id, msg.sender_id.clone());
+            module.resume_token = msg
+                .data
+                .get("resume_token")
+                .and_then(|v| v.as_str())
+                .map(String::from);
+
+            let resumed = msg
+                .data
+                .get("resumed")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+
+            let client_id = module.client_id.clone();
+            module.queue_event(LifecycleEvent::Connected(client_id));
+
+            if resumed {
+                module.current_session_id = msg
+                    .data
+                    .get("session_id")
+                    .and_then(|v| v.as_str())
+                    .map(String::from);
+                log::info!("Resumed connection as player ID: {}", module.client_id);
+            } else {
+                // The server no longer knows us, so nobody we knew of is with us anymore
+                module.forget_session(&previous_id);
+
+                // Rejoin as a new member, including a join the drop cut short
+                let session_id = module
+                    .current_session_id
+                    .take()
+                    .or_else(|| module.pending_session_id.take());
+                if let Some(session_id) = session_id {
+                    log::info!("Could not resume, rejoining session {}", session_id);
+                    let options = module.join_options.clone();
+                    module.join_session(&session_id, options)?;
+                }
+            }
+        }
+
+        ServerMessage::JoinSessionResult(msg) => {
+            let session_id = msg
+                .data
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+            let reason = msg
+                .data
+                .get("result")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default();
+            let result = JoinResult::from_reason(reason);
+
+            if module.pending_session_id.as_deref() == Some(session_id.as_str()) {
+                module.pending_session_id = None;
+
+                if result == JoinResult::Ok {
+                    module.current_session_id = Some(session_id.clone());
+                    module.queue_event(LifecycleEvent::Joined(session_id.clone()));
+                    log::info!("Joined session {}", session_id);
+                    module.send_presence()?;
+                } else {
+                    module.queue_event(LifecycleEvent::JoinRejected(session_id.clone()));
+                    log::warn!("Server refused to join session {}: {}", session_id, reason);
+                }
+            }
+
+            if let Some((waiting_for, waiter)) = module.join_waiter.take() {
+                if waiting_for == session_id {
+                    let _ = waiter.send(result);
+                } else {
+                    module.join_waiter = Some((waiting_for, waiter));
+                }
+            }
+        }
+
+        ServerMessage::SessionList(msg) => {
+            let sessions = msg.data.get("sessions").cloned().unwrap_or_default();
+            module.session_list = serde_json::from_value(sessions)?;
+            module.queue_event(LifecycleEvent::SessionListReceived);
+            log::info!("Received listing of {} sessions", module.session_list.len());
+        }
+
+        ServerMessage::SessionMembers(msg) => {
+            if let Some(session_id) = msg.data.get("session_id").and_then(|v| v.as_str()) {
+                // Update the member list
+                if let Some(members) = msg.data.get("members").and_then(|v| v.as_array()) {
+                    let session_members: Vec<String> = members
+                        .iter()
+                        .filter_map(|v| v.as_str().map(String::from))
+                        .collect();
+
+                    // Servers without join results confirm by listing us
+                    if module.pending_session_id.as_deref() == Some(session_id)
+                        && session_members.contains(&module.client_id)
+                    {
+                        module.pending_session_id = None;
+                        module.current_session_id = Some(session_id.to_string());
+                        module.queue_event(LifecycleEvent::Joined(session_id.to_string()));
+                        log::info!("Joined session {}", session_id);
+                        module.send_presence()?;
+                    }
+
+                    // Older servers don't name the host; they hand hosting to
+                    // the oldest member, which they list first
+                    module.session_host = msg
+                        .data
+                        .get("host_id")
+                        .and_then(|v| v.as_str())
+                        .map(String::from)
+                        .or_else(|| session_members.first().cloned());
+
+                    // Get current members to identify disconnected players
+                    let old_members =
+                        std::mem::replace(&mut module.session_members, session_members.clone());
+
+                    // Find any members that were removed (disconnected)
+                    for old_member in &old_members {
+                        if !session_members.contains(old_member) {
+                            module.forget_member(old_member);
+                        }
+                    }
+
+                    // And any that are new to us
+                    for member in &session_members {
+                        if !old_members.contains(member) && member != &module.client_id {
+                            // Late joiners need full frames before deltas mean anything
+                            module.actor_encoder.force_keyframes();
+                            module.queue_event(LifecycleEvent::MemberJoined(member.clone()));
+                        }
+                    }
+
+                    log::info!(
+                        "Session '{}' updated: {} members: {:?}",
+                        session_id,
+                        module.session_members.len(),
+                        module.session_members
+                    );
+                }
+            }
+        }
+
+        ServerMessage::RemovedFromSession(msg) => {
+            let session_id = msg
+                .data
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+
+            if module.current_session_id.as_deref() == Some(session_id.as_str()) {
+                let reason = msg
+                    .data
+                    .get("reason")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("unknown");
+                log::warn!("Removed from session {}: {}", session_id, reason);
+
+                // Don't rejoin it on a reconnect either
+                module.current_session_id = None;
+                let client_id = module.client_id.clone();
+                module.forget_session(&client_id);
+                module.queue_event(LifecycleEvent::RemovedFromSession(session_id));
+            }
+        }
+
+        // Sets by any member, ours included, in the order the server stored
+        // them, or the whole state when we join
+        ServerMessage::SessionState(msg) => {
+            let entries = msg.data.get("entries").cloned().unwrap_or_default();
+            let entries: Vec<SessionStateEntry> = serde_json::from_value(entries)?;
+            let replay = msg
+                .data
+                .get("replay")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+
+            for entry in entries {
+                // Only the copy we join with; after that changes arrive as they happen
+                if replay && entry.key.starts_with(FLAG_STATE_PREFIX) {
+                    module.flags.receive_snapshot(&entry.key, &entry.value);
+                }
+                if replay && entry.key == INVENTORY_STATE_KEY {
+                    if let Err(e) = module.inventory.receive_snapshot(&entry.value) {
+                        log::warn!("Ignoring the host's inventory: {}", e);
+                    }
+                }
+
+                if module.session_state.get(&entry.key) != Some(&entry.value) {
+                    module.queue_event(LifecycleEvent::SessionStateChanged(entry.key.clone()));
+                    module.session_state.insert(entry.key, entry.value);
+                }
+            }
+        }
+
+        ServerMessage::Presence(msg) => {
+            if msg.sender_id != module.client_id {
+                let field = |name: &str| msg.data.get(name).and_then(|v| v.as_i64());
+                let presence = PlayerPresence {
+                    scene: field("scene").unwrap_or_default() as u32,
+                    room: field("room").unwrap_or(-1) as i32,
+                    entrance: field("entrance").unwrap_or_default() as u32,
+                };
+
+                let previous = module.presences.insert(msg.sender_id.clone(), presence);
+                if previous != Some(presence) {
+                    // They may have just come into view, and need full frames
+                    module.actor_encoder.force_keyframes();
+                    module.queue_event(LifecycleEvent::PresenceChanged(msg.sender_id.clone()));
+                    log::debug!(
+                        "Player {} is in scene {} room {}",
+                        msg.sender_id,
+                        presence.scene,
+                        presence.room
+                    );
+                }
+            }
+        }
+
+        ServerMessage::ActorSync(msg) => {
+            if msg.sender_id != module.client_id {
+                // Only store data from other players, not ourself
+                let now = Instant::now();
+                let key = (msg.sender_id.clone(), msg.actor_id.clone());
+
+                // Add the sample to the remote actor's history
+                let remote_data = module
+                    .remote_actors
+                    .entry(key.clone())
+                    .and_modify(|remote_data| remote_data.record(msg.data.clone(), now))
+                    .or_insert_with(|| {
+                        RemoteActorData::new(&msg.actor_id, &msg.sender_id, msg.data.clone(), now)
+                    });
+                remote_data.sync_seq = msg.seq;
+                module.keyframe_requests.remove(&key);
+
+                // Unnumbered frames come from owners that don't send deltas
+                if msg.seq != 0 {
+                    remote_data.frames.push(msg.seq, msg.data);
+                    if let Err(e) = module.ack_actor_frames(&msg.sender_id, now) {
+                        log::warn!("Failed to ack actor frames of {}: {}", msg.sender_id, e);
+                    }
+                }
+
+                log::debug!(
+                    "Received actor sync for {} from {}",
+                    msg.actor_id,
+                    msg.sender_id
+                );
+            }
+        }
+
+        ServerMessage::ActorState(msg) => {
+            if msg.sender_id != module.client_id {
+                let key = (msg.sender_id.clone(), msg.actor_id.clone());
+
+                module.remote_states.insert(
+                    key,
+                    RemoteActorState {
+                        id: msg.actor_id,
+                        owner_id: msg.sender_id,
+                        schema: msg.schema,
+                        data: msg.data,
+                        last_update: Instant::now(),
+                    },
+                );
+            }
+        }
+
+        ServerMessage::ActorDelta(msg) => {
+            if msg.sender_id != module.client_id {
+                let key = (msg.sender_id.clone(), msg.actor_id.clone());
+
+                // Without the frame it was diffed against, ask for a keyframe
+                let now = Instant::now();
+                let baseline = module
+                    .remote_actors
+                    .get(&key)
+                    .and_then(|remote_data| remote_data.frames.get(msg.baseline));
+                match baseline {
+                    Some(baseline) => {
+                        let data = apply_delta(baseline, msg.mask, &msg.data)?;
+                        if let Some(remote_data) = module.remote_actors.get_mut(&key) {
+                            remote_data.frames.push(msg.seq, data.clone());
+                            remote_data.record(data, now);
+                            remote_data.sync_seq = msg.seq;
+                        }
+                        if let Err(e) = module.ack_actor_frames(&msg.sender_id, now) {
+                            log::warn!("Failed to ack actor frames of {}: {}", msg.sender_id, e);
+                        }
+                    }
+                    None => {
+                        log::debug!(
+                            "Dropping delta {} for {} from {}, waiting for a keyframe",
+                            msg.seq,
+                            msg.actor_id,
+                            msg.sender_id
+                        );
+                        module.request_keyframe(&msg.sender_id, &msg.actor_id)?;
+                    }
+                }
+            }
+        }
+
+        ServerMessage::RegisteredMessage(msg) if msg.seq > 0 => {
+            // Ack every copy, since the sender may have missed an earlier ack
+            if let Err(e) = module.send_ack(&msg.sender_id, msg.seq) {
+                log::warn!(
+                    "Failed to ack reliable message from {}: {}",
+                    msg.sender_id,
+                    e
+                );
+            }
+
+            let ready = module
+                .reliable
+                .receive(&msg.sender_id, msg.seq, msg.message_id, msg.data);
+            for (message_id, data) in ready {
+                log::debug!(
+                    "Received reliable message '{}' from {}",
+                    message_id,
+                    msg.sender_id
+                );
+                module.deliver_message(&msg.sender_id, message_id, data);
+            }
+        }
+
+        ServerMessage::MessageAck(msg) => {
+            module.reliable.acknowledge(&msg.sender_id, msg.seq);
+        }
+
+        // Unpacked at the top of handle_server_message
+        ServerMessage::Batch(_) => {}
+
+        // Sent by a newer server; nothing here depends on it
+        ServerMessage::Unknown { event_type, raw } => {
+            log::debug!("Skipping unknown {} message: {}", event_type, raw);
+            module.count_dropped_message();
+        }
+
+        ServerMessage::RegisteredMessage(msg) => {
+            // The server only sends our own messages back when we asked it to
+            if msg.sender_id != module.client_id || msg.echo {
+                module.deliver_message(&msg.sender_id, msg.message_id.clone(), msg.data);
                 log::debug!(
                     "Received message '{}' from {}",
                     msg.message_id,
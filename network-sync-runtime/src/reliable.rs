@@ -0,0 +1,425 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// How long to wait for an ack before sending a reliable message again
+pub const RESEND_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Sends before a copy is given up on and its delivery reported as failed
+const MAX_SEND_ATTEMPTS: u32 = 10;
+
+/// Finished delivery statuses kept around for the mod to query
+const MAX_FINISHED_DELIVERIES: usize = 256;
+
+/// Out of order messages held per sender before we stop waiting for a gap
+const MAX_HELD_MESSAGES: usize = 64;
+
+/// Delivery status of a reliable message, as handed to the guest
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// No such handle, or it finished too long ago to remember
+    Unknown = 0,
+    Pending = 1,
+    /// Every recipient acknowledged the message
+    Delivered = 2,
+    /// A recipient left or never acknowledged it
+    Failed = 3,
+}
+
+/// A copy of a reliable message bound for one recipient
+#[derive(Debug, Clone)]
+pub struct OutgoingReliable {
+    pub recipient: String,
+    pub seq: u32,
+    pub message_id: String,
+    pub data: Vec<u8>,
+}
+
+struct PendingCopy {
+    handle: u32,
+    message: OutgoingReliable,
+    last_sent: Instant,
+    attempts: u32,
+}
+
+struct Delivery {
+    remaining: usize,
+    status: DeliveryStatus,
+}
+
+// Messages from one sender, delivered in sequence order
+struct Inbox {
+    next_seq: u32,
+    held: BTreeMap<u32, (String, Vec<u8>)>,
+}
+
+/// Bookkeeping for reliable, ordered registered messages. Each recipient gets
+/// its own copy with a per (sender, recipient) sequence number, so targeted
+/// sends never leave gaps in someone else's stream. Sending is left to the
+/// caller; this only decides what needs sending.
+pub struct ReliableChannel {
+    next_handle: u32,
+    next_seq: HashMap<String, u32>,
+    pending: HashMap<(String, u32), PendingCopy>,
+    deliveries: HashMap<u32, Delivery>,
+    finished: VecDeque<u32>,
+    inboxes: HashMap<String, Inbox>,
+}
+
+impl ReliableChannel {
+    pub fn new() -> Self {
+        Self {
+            next_handle: 1,
+            next_seq: HashMap::new(),
+            pending: HashMap::new(),
+            deliveries: HashMap::new(),
+            finished: VecDeque::new(),
+            inboxes: HashMap::new(),
+        }
+    }
+
+    /// Track a new message to `recipients`, returning its delivery handle and
+    /// the copies to send right away
+    pub fn queue(
+        &mut self,
+        message_id: &str,
+        data: Vec<u8>,
+        recipients: &[String],
+    ) -> (u32, Vec<OutgoingReliable>) {
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1).max(1);
+
+        self.deliveries.insert(
+            handle,
+            Delivery {
+                remaining: recipients.len(),
+                status: DeliveryStatus::Pending,
+            },
+        );
+
+        // Nobody to wait on
+        if recipients.is_empty() {
+            self.finish(handle, DeliveryStatus::Delivered);
+            return (handle, Vec::new());
+        }
+
+        let now = Instant::now();
+        let mut outgoing = Vec::with_capacity(recipients.len());
+
+        for recipient in recipients {
+            let seq = self.next_seq.entry(recipient.clone()).or_insert(0);
+            *seq += 1;
+
+            let message = OutgoingReliable {
+                recipient: recipient.clone(),
+                seq: *seq,
+                message_id: message_id.to_string(),
+                data: data.clone(),
+            };
+
+            self.pending.insert(
+                (recipient.clone(), *seq),
+                PendingCopy {
+                    handle,
+                    message: message.clone(),
+                    last_sent: now,
+                    attempts: 1,
+                },
+            );
+            outgoing.push(message);
+        }
+
+        (handle, outgoing)
+    }
+
+    /// A recipient confirmed it received message `seq` from us
+    pub fn acknowledge(&mut self, recipient: &str, seq: u32) {
+        let Some(copy) = self.pending.remove(&(recipient.to_string(), seq)) else {
+            // Duplicate ack for a resent copy
+            return;
+        };
+
+        if let Some(delivery) = self.deliveries.get_mut(&copy.handle) {
+            delivery.remaining = delivery.remaining.saturating_sub(1);
+            if delivery.remaining == 0 && delivery.status == DeliveryStatus::Pending {
+                self.finish(copy.handle, DeliveryStatus::Delivered);
+            }
+        }
+    }
+
+    /// Copies whose ack is overdue, to be sent again. Copies out of attempts
+    /// are dropped and their delivery marked failed.
+    pub fn due_for_resend(&mut self, now: Instant) -> Vec<OutgoingReliable> {
+        let mut resend = Vec::new();
+        let mut failed = Vec::new();
+
+        for (key, copy) in self.pending.iter_mut() {
+            if now.duration_since(copy.last_sent) < RESEND_INTERVAL {
+                continue;
+            }
+
+            if copy.attempts >= MAX_SEND_ATTEMPTS {
+                failed.push(key.clone());
+                continue;
+            }
+
+            copy.attempts += 1;
+            copy.last_sent = now;
+            resend.push(copy.message.clone());
+        }
+
+        for key in failed {
+            if let Some(copy) = self.pending.remove(&key) {
+                log::warn!(
+                    "Gave up delivering '{}' to {}",
+                    copy.message.message_id,
+                    copy.message.recipient
+                );
+                self.finish(copy.handle, DeliveryStatus::Failed);
+            }
+        }
+
+        resend
+    }
+
+    /// Forget a client that left, failing anything still bound for it
+    pub fn remove_peer(&mut self, peer: &str) {
+        let keys: Vec<_> = self
+            .pending
+            .keys()
+            .filter(|(recipient, _)| recipient == peer)
+            .cloned()
+            .collect();
+
+        for key in keys {
+            if let Some(copy) = self.pending.remove(&key) {
+                self.finish(copy.handle, DeliveryStatus::Failed);
+            }
+        }
+
+        self.next_seq.remove(peer);
+        self.inboxes.remove(peer);
+    }
+
+    /// Fail everything in flight and start every stream over, for when our
+    /// client id changes or we leave the session
+    pub fn reset(&mut self) {
+        let handles: Vec<u32> = self.pending.values().map(|copy| copy.handle).collect();
+        for handle in handles {
+            self.finish(handle, DeliveryStatus::Failed);
+        }
+
+        self.pending.clear();
+        self.next_seq.clear();
+        self.inboxes.clear();
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn status(&self, handle: u32) -> DeliveryStatus {
+        self.deliveries
+            .get(&handle)
+            .map_or(DeliveryStatus::Unknown, |delivery| delivery.status)
+    }
+
+    /// Accept reliable message `seq` from `sender`, returning the
+    /// (message_id, data) pairs that are now deliverable in order
+    pub fn receive(
+        &mut self,
+        sender: &str,
+        seq: u32,
+        message_id: String,
+        data: Vec<u8>,
+    ) -> Vec<(String, Vec<u8>)> {
+        let inbox = self.inboxes.entry(sender.to_string()).or_insert(Inbox {
+            next_seq: 1,
+            held: BTreeMap::new(),
+        });
+
+        // Already delivered; the sender missed our ack
+        if seq < inbox.next_seq {
+            return Vec::new();
+        }
+
+        inbox.held.insert(seq, (message_id, data));
+
+        // The sender gave up on whatever we're missing, so stop waiting for it
+        if inbox.held.len() > MAX_HELD_MESSAGES {
+            if let Some(&oldest) = inbox.held.keys().next() {
+                log::warn!(
+                    "Skipping reliable messages {}..{} from {}",
+                    inbox.next_seq,
+                    oldest,
+                    sender
+                );
+                inbox.next_seq = oldest;
+            }
+        }
+
+        let mut ready = Vec::new();
+        while let Some(message) = inbox.held.remove(&inbox.next_seq) {
+            ready.push(message);
+            inbox.next_seq += 1;
+        }
+
+        ready
+    }
+
+    fn finish(&mut self, handle: u32, status: DeliveryStatus) {
+        let Some(delivery) = self.deliveries.get_mut(&handle) else {
+            return;
+        };
+
+        // A failure sticks even if other recipients ack later
+        if delivery.status != DeliveryStatus::Pending {
+            return;
+        }
+        delivery.status = status;
+
+        self.finished.push_back(handle);
+        if self.finished.len() > MAX_FINISHED_DELIVERIES {
+            if let Some(oldest) = self.finished.pop_front() {
+                self.deliveries.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receive(channel: &mut ReliableChannel, seq: u32) -> Vec<u32> {
+        channel
+            .receive("p1", seq, format!("m{}", seq), vec![seq as u8])
+            .into_iter()
+            .map(|(id, _)| id[1..].parse().unwrap())
+            .collect()
+    }
+
+    fn peers(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn receive_delivers_in_order() {
+        let mut channel = ReliableChannel::new();
+        assert_eq!(receive(&mut channel, 1), vec![1]);
+        assert_eq!(receive(&mut channel, 2), vec![2]);
+    }
+
+    #[test]
+    fn receive_holds_out_of_order_messages() {
+        let mut channel = ReliableChannel::new();
+        assert!(receive(&mut channel, 3).is_empty());
+        assert!(receive(&mut channel, 2).is_empty());
+        assert_eq!(receive(&mut channel, 1), vec![1, 2, 3]);
+        assert_eq!(receive(&mut channel, 4), vec![4]);
+    }
+
+    #[test]
+    fn receive_drops_duplicates() {
+        let mut channel = ReliableChannel::new();
+        assert_eq!(receive(&mut channel, 1), vec![1]);
+        assert!(receive(&mut channel, 1).is_empty());
+
+        // A duplicate of a held message is still delivered only once
+        assert!(receive(&mut channel, 3).is_empty());
+        assert!(receive(&mut channel, 3).is_empty());
+        assert_eq!(receive(&mut channel, 2), vec![2, 3]);
+        assert!(receive(&mut channel, 2).is_empty());
+    }
+
+    #[test]
+    fn receive_keeps_senders_apart() {
+        let mut channel = ReliableChannel::new();
+        assert_eq!(receive(&mut channel, 1), vec![1]);
+        let from_p2 = channel.receive("p2", 2, "m2".to_string(), Vec::new());
+        assert!(from_p2.is_empty());
+    }
+
+    #[test]
+    fn receive_skips_gap_once_too_many_are_held() {
+        let mut channel = ReliableChannel::new();
+        let last = MAX_HELD_MESSAGES as u32 + 2;
+        for seq in 2..last {
+            assert!(receive(&mut channel, seq).is_empty());
+        }
+
+        // Message 1 never arrived; the next one gives up on it
+        assert_eq!(receive(&mut channel, last), (2..=last).collect::<Vec<_>>());
+        assert!(receive(&mut channel, 1).is_empty());
+    }
+
+    #[test]
+    fn delivery_waits_for_every_recipient() {
+        let mut channel = ReliableChannel::new();
+        let (handle, outgoing) = channel.queue("chat", b"hi".to_vec(), &peers(&["p2", "p3"]));
+        assert_eq!(outgoing.len(), 2);
+        assert!(outgoing.iter().all(|copy| copy.seq == 1));
+
+        channel.acknowledge("p2", 1);
+        assert_eq!(channel.status(handle), DeliveryStatus::Pending);
+        channel.acknowledge("p2", 1);
+        assert_eq!(channel.status(handle), DeliveryStatus::Pending);
+        channel.acknowledge("p3", 1);
+        assert_eq!(channel.status(handle), DeliveryStatus::Delivered);
+        assert!(!channel.has_pending());
+    }
+
+    #[test]
+    fn sequences_are_per_recipient() {
+        let mut channel = ReliableChannel::new();
+        channel.queue("a", Vec::new(), &peers(&["p2"]));
+        let (_, outgoing) = channel.queue("b", Vec::new(), &peers(&["p2", "p3"]));
+
+        let seqs: HashMap<_, _> = outgoing
+            .iter()
+            .map(|copy| (copy.recipient.as_str(), copy.seq))
+            .collect();
+        assert_eq!(seqs["p2"], 2);
+        assert_eq!(seqs["p3"], 1);
+    }
+
+    #[test]
+    fn no_recipients_is_delivered() {
+        let mut channel = ReliableChannel::new();
+        let (handle, outgoing) = channel.queue("chat", Vec::new(), &[]);
+        assert!(outgoing.is_empty());
+        assert_eq!(channel.status(handle), DeliveryStatus::Delivered);
+    }
+
+    #[test]
+    fn unacknowledged_copies_are_resent_then_failed() {
+        let mut channel = ReliableChannel::new();
+        let (handle, _) = channel.queue("chat", Vec::new(), &peers(&["p2"]));
+        let mut now = Instant::now();
+
+        assert!(channel.due_for_resend(now).is_empty());
+        for _ in 1..MAX_SEND_ATTEMPTS {
+            now += RESEND_INTERVAL;
+            assert_eq!(channel.due_for_resend(now).len(), 1);
+        }
+
+        now += RESEND_INTERVAL;
+        assert!(channel.due_for_resend(now).is_empty());
+        assert_eq!(channel.status(handle), DeliveryStatus::Failed);
+        assert!(!channel.has_pending());
+    }
+
+    #[test]
+    fn leaving_peer_fails_its_deliveries() {
+        let mut channel = ReliableChannel::new();
+        let (handle, _) = channel.queue("chat", Vec::new(), &peers(&["p2", "p3"]));
+
+        channel.remove_peer("p2");
+        channel.acknowledge("p3", 1);
+        assert_eq!(channel.status(handle), DeliveryStatus::Failed);
+
+        // The peer starts from 1 again if it comes back
+        let (_, outgoing) = channel.queue("chat", Vec::new(), &peers(&["p2"]));
+        assert_eq!(outgoing[0].seq, 1);
+    }
+}
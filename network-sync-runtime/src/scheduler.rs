@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::types::ActorPayload;
+
+/// Default rate actor updates are flushed to the server, in Hz
+pub const DEFAULT_SEND_RATE: u32 = 20;
+
+struct ScheduledActor {
+    /// Newest data not yet sent; later updates in the same tick replace it
+    pending: Option<ActorPayload>,
+    /// Per actor send interval, when slower than the tick
+    interval: Option<Duration>,
+    last_sent: Option<Instant>,
+}
+
+/// Decides when our actors go out. Updates are held until the next tick and
+/// only the newest per actor is sent, at the global rate or the actor's own
+/// slower rate.
+pub struct SendScheduler {
+    /// Time between ticks; unset sends every update straight away
+    tick_interval: Option<Duration>,
+    actors: HashMap<String, ScheduledActor>,
+}
+
+impl SendScheduler {
+    pub fn new() -> Self {
+        Self {
+            tick_interval: rate_to_interval(DEFAULT_SEND_RATE),
+            actors: HashMap::new(),
+        }
+    }
+
+    pub fn tick_interval(&self) -> Option<Duration> {
+        self.tick_interval
+    }
+
+    /// Set the global send rate in Hz; 0 turns scheduling off
+    pub fn set_send_rate(&mut self, hz: u32) {
+        self.tick_interval = rate_to_interval(hz);
+    }
+
+    /// Set how often one actor is sent, in Hz; 0 goes back to the global rate
+    pub fn set_actor_rate(&mut self, actor_id: &str, hz: u32) {
+        self.entry(actor_id).interval = rate_to_interval(hz);
+    }
+
+    /// Hold `data` until the actor is next due, replacing anything still held
+    pub fn queue(&mut self, actor_id: &str, data: ActorPayload) {
+        self.entry(actor_id).pending = Some(data);
+    }
+
+    /// Take the held updates of every actor whose interval has elapsed
+    pub fn due(&mut self, now: Instant) -> Vec<(String, ActorPayload)> {
+        let mut due = Vec::new();
+
+        for (actor_id, actor) in self.actors.iter_mut() {
+            let ready = match (actor.interval, actor.last_sent) {
+                (Some(interval), Some(last_sent)) => now.duration_since(last_sent) >= interval,
+                _ => true,
+            };
+
+            if ready {
+                if let Some(data) = actor.pending.take() {
+                    due.push((actor_id.clone(), data));
+                }
+            }
+        }
+
+        due
+    }
+
+    /// Record that an actor's update went out
+    pub fn sent(&mut self, actor_id: &str, now: Instant) {
+        self.entry(actor_id).last_sent = Some(now);
+    }
+
+    /// Put back an update that couldn't be sent, unless a newer one arrived
+    pub fn requeue(&mut self, actor_id: &str, data: ActorPayload) {
+        self.entry(actor_id).pending.get_or_insert(data);
+    }
+
+    /// Drop everything held, keeping per actor rates
+    pub fn clear_pending(&mut self) {
+        for actor in self.actors.values_mut() {
+            actor.pending = None;
+        }
+    }
+
+    fn entry(&mut self, actor_id: &str) -> &mut ScheduledActor {
+        self.actors
+            .entry(actor_id.to_string())
+            .or_insert(ScheduledActor {
+                pending: None,
+                interval: None,
+                last_sent: None,
+            })
+    }
+}
+
+fn rate_to_interval(hz: u32) -> Option<Duration> {
+    (hz > 0).then(|| Duration::from_secs(1) / hz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(byte: u8) -> ActorPayload {
+        ActorPayload::Schema {
+            schema: "door".to_string(),
+            data: vec![byte],
+        }
+    }
+
+    // (actor id, payload byte) of everything due, sorted
+    fn due(scheduler: &mut SendScheduler, now: Instant) -> Vec<(String, u8)> {
+        let mut due: Vec<_> = scheduler
+            .due(now)
+            .into_iter()
+            .map(|(actor_id, payload)| match payload {
+                ActorPayload::Schema { data, .. } => (actor_id, data[0]),
+                ActorPayload::Player(_) => panic!("unexpected player payload"),
+            })
+            .collect();
+        due.sort();
+        due
+    }
+
+    #[test]
+    fn rates_convert_to_intervals() {
+        let mut scheduler = SendScheduler::new();
+        assert_eq!(scheduler.tick_interval(), Some(Duration::from_millis(50)));
+
+        scheduler.set_send_rate(0);
+        assert_eq!(scheduler.tick_interval(), None);
+    }
+
+    #[test]
+    fn only_the_newest_update_is_sent() {
+        let mut scheduler = SendScheduler::new();
+        let now = Instant::now();
+        scheduler.queue("a", payload(1));
+        scheduler.queue("a", payload(2));
+        scheduler.queue("b", payload(3));
+
+        assert_eq!(
+            due(&mut scheduler, now),
+            vec![("a".to_string(), 2), ("b".to_string(), 3)]
+        );
+        assert!(due(&mut scheduler, now).is_empty());
+    }
+
+    #[test]
+    fn actor_rate_holds_updates_until_due() {
+        let mut scheduler = SendScheduler::new();
+        let start = Instant::now();
+        scheduler.set_actor_rate("a", 2);
+
+        scheduler.queue("a", payload(1));
+        assert_eq!(due(&mut scheduler, start), vec![("a".to_string(), 1)]);
+        scheduler.sent("a", start);
+
+        scheduler.queue("a", payload(2));
+        assert!(due(&mut scheduler, start + Duration::from_millis(499)).is_empty());
+        assert_eq!(
+            due(&mut scheduler, start + Duration::from_millis(500)),
+            vec![("a".to_string(), 2)]
+        );
+
+        // Back to the global rate, which leaves timing to the tick
+        scheduler.sent("a", start + Duration::from_millis(500));
+        scheduler.set_actor_rate("a", 0);
+        scheduler.queue("a", payload(3));
+        assert_eq!(
+            due(&mut scheduler, start + Duration::from_millis(501)),
+            vec![("a".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn requeue_keeps_newer_updates() {
+        let mut scheduler = SendScheduler::new();
+        let now = Instant::now();
+
+        scheduler.requeue("a", payload(1));
+        assert_eq!(due(&mut scheduler, now), vec![("a".to_string(), 1)]);
+
+        scheduler.queue("a", payload(2));
+        scheduler.requeue("a", payload(1));
+        assert_eq!(due(&mut scheduler, now), vec![("a".to_string(), 2)]);
+    }
+
+    #[test]
+    fn clear_pending_keeps_rates() {
+        let mut scheduler = SendScheduler::new();
+        let start = Instant::now();
+        scheduler.set_actor_rate("a", 1);
+        scheduler.sent("a", start);
+        scheduler.queue("a", payload(1));
+
+        scheduler.clear_pending();
+        assert!(due(&mut scheduler, start + Duration::from_secs(1)).is_empty());
+
+        scheduler.queue("a", payload(2));
+        assert!(due(&mut scheduler, start + Duration::from_millis(10)).is_empty());
+    }
+}
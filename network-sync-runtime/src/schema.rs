@@ -0,0 +1,228 @@
+use anyhow::Result;
+use n64_recomp::{mem_bu, mem_bu_write};
+
+/// Longest schema name, so it fits the mod's 32 byte buffers
+pub const MAX_SCHEMA_NAME_LEN: usize = 31;
+
+/// Largest actor state a schema can describe
+pub const MAX_STATE_BYTES: usize = 1024;
+
+/// Most fields a single schema can have
+const MAX_SCHEMA_FIELDS: usize = 64;
+
+/// Type of a schema field, as handed over by the guest
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    U8 = 1,
+    S8 = 2,
+    U16 = 3,
+    S16 = 4,
+    U32 = 5,
+    S32 = 6,
+    F32 = 7,
+    Vec3f = 8,
+    Vec3s = 9,
+}
+
+impl FieldType {
+    pub fn from_code(code: u32) -> Option<Self> {
+        let field_type = match code {
+            1 => FieldType::U8,
+            2 => FieldType::S8,
+            3 => FieldType::U16,
+            4 => FieldType::S16,
+            5 => FieldType::U32,
+            6 => FieldType::S32,
+            7 => FieldType::F32,
+            8 => FieldType::Vec3f,
+            9 => FieldType::Vec3s,
+            _ => return None,
+        };
+
+        Some(field_type)
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            FieldType::U8 | FieldType::S8 => 1,
+            FieldType::U16 | FieldType::S16 => 2,
+            FieldType::U32 | FieldType::S32 | FieldType::F32 => 4,
+            FieldType::Vec3f => 12,
+            FieldType::Vec3s => 6,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SchemaField {
+    offset: u32,
+    field_type: FieldType,
+    count: u32,
+}
+
+impl SchemaField {
+    fn size(&self) -> usize {
+        self.field_type.size() * self.count as usize
+    }
+}
+
+/// Which parts of an actor a mod wants synced: fields at offsets from the
+/// actor's address. States are the fields' bytes back to back, in guest byte
+/// order, so only peers with the same schema can make sense of them.
+#[derive(Debug, Clone, Default)]
+pub struct SyncSchema {
+    fields: Vec<SchemaField>,
+}
+
+impl SyncSchema {
+    /// Append `count` consecutive values of `field_type` at `offset`
+    pub fn add_field(&mut self, offset: u32, field_type: FieldType, count: u32) -> Result<()> {
+        if count == 0 {
+            return Err(anyhow::anyhow!("Schema fields need a count of at least 1"));
+        }
+
+        if self.fields.len() >= MAX_SCHEMA_FIELDS {
+            return Err(anyhow::anyhow!(
+                "Schemas can have at most {} fields",
+                MAX_SCHEMA_FIELDS
+            ));
+        }
+
+        let field = SchemaField {
+            offset,
+            field_type,
+            count,
+        };
+        if self.state_size() + field.size() > MAX_STATE_BYTES {
+            return Err(anyhow::anyhow!(
+                "Schema state would exceed {} bytes",
+                MAX_STATE_BYTES
+            ));
+        }
+
+        self.fields.push(field);
+        Ok(())
+    }
+
+    /// Size of the states this schema reads and writes
+    pub fn state_size(&self) -> usize {
+        self.fields.iter().map(SchemaField::size).sum()
+    }
+
+    /// Read the schema's fields from the actor at `addr`
+    pub unsafe fn read(&self, rdram: *mut u8, addr: u64) -> Vec<u8> {
+        let mut state = Vec::with_capacity(self.state_size());
+
+        for field in &self.fields {
+            let start = addr + field.offset as u64;
+            for i in 0..field.size() {
+                state.push(mem_bu(rdram, start + i as u64));
+            }
+        }
+
+        state
+    }
+
+    /// Write a state read with the same schema into the actor at `addr`
+    pub unsafe fn write(&self, rdram: *mut u8, addr: u64, state: &[u8]) -> Result<()> {
+        if state.len() != self.state_size() {
+            return Err(anyhow::anyhow!(
+                "State is {} bytes but the schema expects {}",
+                state.len(),
+                self.state_size()
+            ));
+        }
+
+        let mut bytes = state.iter();
+        for field in &self.fields {
+            let start = addr + field.offset as u64;
+            for (i, byte) in bytes.by_ref().take(field.size()).enumerate() {
+                mem_bu_write(rdram, start + i as u64, *byte);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn validate_schema_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > MAX_SCHEMA_NAME_LEN {
+        return Err(anyhow::anyhow!(
+            "Schema names must be 1 to {} bytes",
+            MAX_SCHEMA_NAME_LEN
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two actors' worth of guest memory, one after the other
+    const ACTOR_A: u64 = 0xFFFF_FFFF_8000_0000;
+    const ACTOR_B: u64 = ACTOR_A + 0x100;
+
+    fn schema() -> SyncSchema {
+        let mut schema = SyncSchema::default();
+        schema.add_field(0x24, FieldType::Vec3f, 1).unwrap();
+        schema.add_field(0x10, FieldType::S16, 2).unwrap();
+        schema.add_field(0x03, FieldType::U8, 1).unwrap();
+        schema
+    }
+
+    #[test]
+    fn state_round_trips_between_actors() {
+        let mut memory = vec![0u8; 0x200];
+        let rdram = memory.as_mut_ptr();
+        let schema = schema();
+
+        unsafe {
+            for i in 0..0x100 {
+                mem_bu_write(rdram, ACTOR_A + i, i as u8);
+            }
+
+            let state = schema.read(rdram, ACTOR_A);
+            assert_eq!(state.len(), 12 + 4 + 1);
+            assert_eq!(&state[..3], &[0x24, 0x25, 0x26]);
+
+            schema.write(rdram, ACTOR_B, &state).unwrap();
+            assert_eq!(schema.read(rdram, ACTOR_B), state);
+
+            // Bytes outside the schema's fields are left alone
+            assert_eq!(mem_bu(rdram, ACTOR_B + 0x02), 0);
+            assert_eq!(mem_bu(rdram, ACTOR_B + 0x14), 0);
+            assert_eq!(mem_bu(rdram, ACTOR_B + 0x30), 0);
+        }
+    }
+
+    #[test]
+    fn write_refuses_states_of_another_size() {
+        let mut memory = vec![0u8; 0x200];
+        let rdram = memory.as_mut_ptr();
+        let schema = schema();
+
+        unsafe {
+            assert!(schema.write(rdram, ACTOR_B, &[1; 16]).is_err());
+            assert!(schema.write(rdram, ACTOR_B, &[1; 18]).is_err());
+            assert!(schema.read(rdram, ACTOR_B).iter().all(|byte| *byte == 0));
+        }
+    }
+
+    #[test]
+    fn fields_are_limited() {
+        let mut schema = SyncSchema::default();
+        assert!(schema.add_field(0, FieldType::U8, 0).is_err());
+        assert!(schema
+            .add_field(0, FieldType::U32, (MAX_STATE_BYTES / 4 + 1) as u32)
+            .is_err());
+
+        for offset in 0..MAX_SCHEMA_FIELDS as u32 {
+            schema.add_field(offset, FieldType::U8, 1).unwrap();
+        }
+        assert!(schema.add_field(0, FieldType::U8, 1).is_err());
+        assert_eq!(schema.state_size(), MAX_SCHEMA_FIELDS);
+    }
+}
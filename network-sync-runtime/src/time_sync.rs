@@ -0,0 +1,535 @@
+use anyhow::Result;
+use n64_recomp::N64MemoryIO;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::types::LifecycleEvent;
+
+/// Registered message IDs used by time sync start with this
+pub const TIME_MESSAGE_PREFIX: &str = "ns:time/";
+
+const CLOCK_MESSAGE_ID: &str = "ns:time/clock";
+pub const PROPOSE_MESSAGE_ID: &str = "ns:time/propose";
+pub const VOTE_MESSAGE_ID: &str = "ns:time/vote";
+const RESULT_MESSAGE_ID: &str = "ns:time/result";
+
+/// How often the host sends its clock
+const CLOCK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time of day a new day starts at, 6:00
+const DAY_START: u16 = 0x4000;
+
+/// Time units in a day
+const DAY_LENGTH: i64 = 0x10000;
+
+/// Clocks further apart than this, about an hour and a half, jump to the host's
+const SNAP_THRESHOLD: i64 = 0x1000;
+
+/// Share of the remaining difference made up each frame
+const CONVERGE_DIVISOR: i64 = 8;
+
+/// Members that haven't voted on a transition by then are counted as accepting
+const VOTE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Transitions remembered at once, so kinds can still be read once resolved
+const MAX_TRANSITIONS: usize = 16;
+
+/// Largest transition ID, so it fits the mod's 64 byte string buffers
+const MAX_TRANSITION_ID_LEN: usize = 63;
+
+/// The clock as the mod copies it out of the save context
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, N64MemoryIO)]
+pub struct TimeState {
+    pub day: i32,
+    pub time: u32,
+    pub time_speed_offset: i32,
+}
+
+impl TimeState {
+    // Time since dawn of day 0, which unlike `time` doesn't wrap at midnight
+    fn linear(&self) -> i64 {
+        self.day as i64 * DAY_LENGTH + (self.time as u16).wrapping_sub(DAY_START) as i64
+    }
+
+    fn set_linear(&mut self, linear: i64) {
+        self.day = linear.div_euclid(DAY_LENGTH) as i32;
+        self.time = (linear.rem_euclid(DAY_LENGTH) as u16).wrapping_add(DAY_START) as u32;
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ProposeMessage {
+    id: String,
+    kind: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VoteMessage {
+    id: String,
+    accept: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ResultMessage {
+    id: String,
+    committed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TransitionState {
+    Voting,
+    Committed,
+    Cancelled,
+}
+
+/// A session wide change of time, like the Song of Time, that every member
+/// gets to accept or veto
+struct Transition {
+    kind: u32,
+    proposer: String,
+    proposed_at: Instant,
+    /// Votes by client id; the proposer's is implied
+    votes: HashMap<String, bool>,
+    state: TransitionState,
+}
+
+/// What a sync wants sent, as (message ID, payload, reliable)
+#[derive(Default)]
+pub struct TimeOutput {
+    pub messages: Vec<(&'static str, Vec<u8>, bool)>,
+    /// Transitions we resolved as host, which our own messages won't tell us about
+    pub events: Vec<LifecycleEvent>,
+}
+
+/// The three day clock, kept to the host's. The host sends its clock once a
+/// second and everyone else makes up the difference over the following frames.
+pub struct TimeSync {
+    enabled: bool,
+    last_clock_sent: Option<Instant>,
+    /// The host's latest clock, compared against ours on the next sync
+    host_clock: Option<TimeState>,
+    /// Difference to the host's clock still to be made up
+    remaining: i64,
+    transitions: HashMap<String, Transition>,
+    /// Numbers our transition IDs
+    next_transition: u32,
+}
+
+impl TimeSync {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            last_clock_sent: None,
+            host_clock: None,
+            remaining: 0,
+            transitions: HashMap::new(),
+            next_transition: 1,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.host_clock = None;
+            self.remaining = 0;
+        }
+    }
+
+    /// Forget everything tied to the current session, for a fresh start in the next one
+    pub fn reset_session(&mut self) {
+        self.last_clock_sent = None;
+        self.host_clock = None;
+        self.remaining = 0;
+        self.transitions.clear();
+    }
+
+    pub fn transition_kind(&self, id: &str) -> Option<u32> {
+        self.transitions.get(id).map(|transition| transition.kind)
+    }
+
+    /// Handle a time message another member sent, returning the event it
+    /// raises for the mod, if any
+    pub fn receive(
+        &mut self,
+        sender: &str,
+        message_id: &str,
+        data: &[u8],
+        host_id: Option<&str>,
+    ) -> Result<Option<LifecycleEvent>> {
+        match message_id {
+            CLOCK_MESSAGE_ID => {
+                // Only the host's clock counts, and only if we're following it
+                if self.enabled && host_id == Some(sender) {
+                    self.host_clock = Some(serde_json::from_slice(data)?);
+                }
+                Ok(None)
+            }
+            PROPOSE_MESSAGE_ID => {
+                let msg: ProposeMessage = serde_json::from_slice(data)?;
+                if msg.id.is_empty() || msg.id.len() > MAX_TRANSITION_ID_LEN {
+                    return Err(anyhow::anyhow!("Transition IDs must be 1 to 63 bytes"));
+                }
+
+                if !self.insert_transition(&msg.id, msg.kind, sender) {
+                    return Err(anyhow::anyhow!("Too many transitions waiting on votes"));
+                }
+                Ok(Some(LifecycleEvent::TimeTransitionProposed(msg.id)))
+            }
+            VOTE_MESSAGE_ID => {
+                let msg: VoteMessage = serde_json::from_slice(data)?;
+                if let Some(transition) = self.transitions.get_mut(&msg.id) {
+                    transition.votes.insert(sender.to_string(), msg.accept);
+                }
+                Ok(None)
+            }
+            RESULT_MESSAGE_ID => {
+                let msg: ResultMessage = serde_json::from_slice(data)?;
+                if host_id != Some(sender) {
+                    return Err(anyhow::anyhow!("Only the host resolves transitions"));
+                }
+
+                Ok(self.resolve(&msg.id, msg.committed))
+            }
+            _ => Err(anyhow::anyhow!("Unknown time message '{}'", message_id)),
+        }
+    }
+
+    /// Start a transition of `kind`, returning its ID and the message announcing it
+    pub fn propose(&mut self, client_id: &str, kind: u32) -> Result<(String, Vec<u8>)> {
+        let id = format!("{}:{}", client_id, self.next_transition);
+        self.next_transition += 1;
+
+        let data = serde_json::to_vec(&ProposeMessage {
+            id: id.clone(),
+            kind,
+        })?;
+        if !self.insert_transition(&id, kind, client_id) {
+            return Err(anyhow::anyhow!("Too many transitions waiting on votes"));
+        }
+
+        Ok((id, data))
+    }
+
+    /// Accept or veto another member's transition, returning the vote to send
+    pub fn respond(&mut self, client_id: &str, id: &str, accept: bool) -> Result<Vec<u8>> {
+        let transition = self
+            .transitions
+            .get_mut(id)
+            .filter(|transition| transition.state == TransitionState::Voting)
+            .ok_or_else(|| anyhow::anyhow!("No transition '{}' is waiting on votes", id))?;
+
+        transition.votes.insert(client_id.to_string(), accept);
+        Ok(serde_json::to_vec(&VoteMessage {
+            id: id.to_string(),
+            accept,
+        })?)
+    }
+
+    /// Bring `state` toward the host's clock, or send ours as host, and
+    /// resolve transitions whose votes are in. Returns whether `state` changed.
+    pub fn sync(
+        &mut self,
+        state: &mut TimeState,
+        client_id: &str,
+        host_id: Option<&str>,
+        members: &[String],
+    ) -> (bool, TimeOutput) {
+        let mut output = TimeOutput::default();
+        let original = *state;
+
+        // Transitions are resolved whether or not the clock is synced
+        if host_id == Some(client_id) {
+            self.resolve_votes(members, &mut output);
+        }
+
+        if !self.enabled {
+            return (false, output);
+        }
+
+        if host_id == Some(client_id) {
+            self.host_clock = None;
+            self.remaining = 0;
+
+            let due = self
+                .last_clock_sent
+                .is_none_or(|sent| sent.elapsed() >= CLOCK_INTERVAL);
+            if due {
+                match serde_json::to_vec(state) {
+                    Ok(data) => output.messages.push((CLOCK_MESSAGE_ID, data, false)),
+                    Err(e) => log::error!("Failed to encode clock: {}", e),
+                }
+                self.last_clock_sent = Some(Instant::now());
+            }
+
+            return (false, output);
+        }
+
+        if let Some(host) = self.host_clock.take() {
+            let difference = host.linear() - state.linear();
+            if difference.abs() > SNAP_THRESHOLD {
+                state.set_linear(host.linear());
+                self.remaining = 0;
+            } else {
+                self.remaining = difference;
+            }
+
+            // Inverted Song of Time slows everyone's clock at once
+            state.time_speed_offset = host.time_speed_offset;
+        }
+
+        if self.remaining != 0 {
+            let step = match self.remaining / CONVERGE_DIVISOR {
+                0 => self.remaining.signum(),
+                step => step,
+            };
+            state.set_linear(state.linear() + step);
+            self.remaining -= step;
+        }
+
+        (*state != original, output)
+    }
+
+    // Returns false if there's no room for another transition
+    fn insert_transition(&mut self, id: &str, kind: u32, proposer: &str) -> bool {
+        // Make room by dropping the oldest resolved transition
+        if self.transitions.len() >= MAX_TRANSITIONS {
+            let oldest = self
+                .transitions
+                .iter()
+                .filter(|(_, transition)| transition.state != TransitionState::Voting)
+                .min_by_key(|(_, transition)| transition.proposed_at)
+                .map(|(id, _)| id.clone());
+
+            match oldest {
+                Some(oldest) => {
+                    self.transitions.remove(&oldest);
+                }
+                None => return false,
+            }
+        }
+
+        self.transitions.insert(
+            id.to_string(),
+            Transition {
+                kind,
+                proposer: proposer.to_string(),
+                proposed_at: Instant::now(),
+                votes: HashMap::new(),
+                state: TransitionState::Voting,
+            },
+        );
+        true
+    }
+
+    fn resolve(&mut self, id: &str, committed: bool) -> Option<LifecycleEvent> {
+        let transition = self
+            .transitions
+            .get_mut(id)
+            .filter(|transition| transition.state == TransitionState::Voting)?;
+
+        if committed {
+            transition.state = TransitionState::Committed;
+            Some(LifecycleEvent::TimeTransitionCommitted(id.to_string()))
+        } else {
+            transition.state = TransitionState::Cancelled;
+            Some(LifecycleEvent::TimeTransitionCancelled(id.to_string()))
+        }
+    }
+
+    // A single veto cancels a transition. It commits once every other
+    // current member accepts, or when the rest run out of time to answer.
+    fn resolve_votes(&mut self, members: &[String], output: &mut TimeOutput) {
+        let decided: Vec<(String, bool)> = self
+            .transitions
+            .iter()
+            .filter(|(_, transition)| transition.state == TransitionState::Voting)
+            .filter_map(|(id, transition)| {
+                let voters = members
+                    .iter()
+                    .filter(|member| **member != transition.proposer);
+                let mut votes = voters.map(|member| transition.votes.get(member).copied());
+
+                let vetoed = votes.clone().any(|vote| vote == Some(false));
+                let all_in = votes.all(|vote| vote.is_some());
+
+                if vetoed {
+                    Some((id.clone(), false))
+                } else if all_in || transition.proposed_at.elapsed() >= VOTE_TIMEOUT {
+                    Some((id.clone(), true))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        for (id, committed) in decided {
+            match serde_json::to_vec(&ResultMessage {
+                id: id.clone(),
+                committed,
+            }) {
+                Ok(data) => output.messages.push((RESULT_MESSAGE_ID, data, true)),
+                Err(e) => log::error!("Failed to encode transition result: {}", e),
+            }
+
+            if let Some(event) = self.resolve(&id, committed) {
+                output.events.push(event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(day: i32, time: u32) -> TimeState {
+        TimeState {
+            day,
+            time,
+            time_speed_offset: 0,
+        }
+    }
+
+    fn members() -> Vec<String> {
+        ["a", "b", "c"].iter().map(|id| id.to_string()).collect()
+    }
+
+    fn vote(sync: &mut TimeSync, voter: &str, id: &str, accept: bool) {
+        let data = serde_json::to_vec(&VoteMessage {
+            id: id.to_string(),
+            accept,
+        })
+        .unwrap();
+        sync.receive(voter, VOTE_MESSAGE_ID, &data, Some("a"))
+            .unwrap();
+    }
+
+    // Results the host sent, as (transition ID, committed)
+    fn results(output: &TimeOutput) -> Vec<(String, bool)> {
+        output
+            .messages
+            .iter()
+            .filter(|(message_id, _, _)| *message_id == RESULT_MESSAGE_ID)
+            .map(|(_, data, _)| {
+                let msg: ResultMessage = serde_json::from_slice(data).unwrap();
+                (msg.id, msg.committed)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn days_turn_over_at_dawn() {
+        // 5:59 still belongs to the day that started the morning before
+        let mut state = clock(1, DAY_START as u32 - 1);
+        state.set_linear(state.linear() + 1);
+        assert_eq!(state, clock(2, DAY_START as u32));
+
+        // Midnight wraps the time of day but not the day
+        let mut state = clock(1, 0xFFFF);
+        state.set_linear(state.linear() + 1);
+        assert_eq!(state, clock(1, 0));
+
+        for state in [clock(0, 0x4000), clock(1, 0x2000), clock(3, 0xC000)] {
+            let mut copy = clock(0, 0);
+            copy.set_linear(state.linear());
+            assert_eq!(copy, state);
+        }
+        assert!(clock(1, 0x3FFF).linear() > clock(1, 0x4000).linear());
+    }
+
+    #[test]
+    fn far_clocks_snap_and_near_ones_converge() {
+        let mut sync = TimeSync::new();
+        sync.set_enabled(true);
+        let receive_clock = |sync: &mut TimeSync, sender: &str, host: TimeState| {
+            let data = serde_json::to_vec(&host).unwrap();
+            sync.receive(sender, CLOCK_MESSAGE_ID, &data, Some("a"))
+                .unwrap();
+        };
+
+        // Only the host's clock counts
+        let mut state = clock(1, 0x8000);
+        receive_clock(&mut sync, "c", clock(2, 0x8000));
+        assert!(!sync.sync(&mut state, "b", Some("a"), &members()).0);
+
+        let mut host = clock(2, 0x8000);
+        host.time_speed_offset = -2;
+        receive_clock(&mut sync, "a", host);
+        assert!(sync.sync(&mut state, "b", Some("a"), &members()).0);
+        assert_eq!(state, host);
+
+        // A small difference is made up over several frames
+        let host = clock(2, 0x8000 + 80);
+        receive_clock(&mut sync, "a", host);
+        sync.sync(&mut state, "b", Some("a"), &members());
+        assert_eq!(state.time, 0x8000 + 10);
+
+        let mut frames = 1;
+        while state.linear() != host.linear() {
+            sync.sync(&mut state, "b", Some("a"), &members());
+            frames += 1;
+            assert!(frames < 100, "clock never caught up");
+        }
+        assert!(frames > 1);
+    }
+
+    #[test]
+    fn one_veto_cancels_a_transition() {
+        let mut sync = TimeSync::new();
+        let propose = serde_json::to_vec(&ProposeMessage {
+            id: "b:1".to_string(),
+            kind: 1,
+        })
+        .unwrap();
+        let event = sync
+            .receive("b", PROPOSE_MESSAGE_ID, &propose, Some("a"))
+            .unwrap();
+        assert!(matches!(event, Some(LifecycleEvent::TimeTransitionProposed(id)) if id == "b:1"));
+
+        // The host's acceptance alone isn't enough
+        sync.respond("a", "b:1", true).unwrap();
+        let (_, output) = sync.sync(&mut clock(1, 0), "a", Some("a"), &members());
+        assert!(results(&output).is_empty());
+
+        vote(&mut sync, "c", "b:1", false);
+        let (_, output) = sync.sync(&mut clock(1, 0), "a", Some("a"), &members());
+        assert_eq!(results(&output), vec![("b:1".to_string(), false)]);
+        assert!(matches!(
+            output.events.as_slice(),
+            [LifecycleEvent::TimeTransitionCancelled(id)] if id == "b:1"
+        ));
+
+        // Resolved transitions aren't voted on or resolved again
+        assert!(sync.respond("a", "b:1", true).is_err());
+        let (_, output) = sync.sync(&mut clock(1, 0), "a", Some("a"), &members());
+        assert!(results(&output).is_empty());
+    }
+
+    #[test]
+    fn transitions_commit_once_everyone_accepts_or_time_runs_out() {
+        let mut sync = TimeSync::new();
+        let (all_in, _) = sync.propose("a", 1).unwrap();
+        let (timed_out, _) = sync.propose("a", 2).unwrap();
+
+        vote(&mut sync, "b", &all_in, true);
+        vote(&mut sync, "c", &all_in, true);
+        vote(&mut sync, "b", &timed_out, true);
+        let (_, output) = sync.sync(&mut clock(1, 0), "a", Some("a"), &members());
+        assert_eq!(results(&output), vec![(all_in.clone(), true)]);
+
+        // Members that never answer count as accepting
+        let transition = sync.transitions.get_mut(&timed_out).unwrap();
+        transition.proposed_at = Instant::now().checked_sub(VOTE_TIMEOUT).unwrap();
+        let (_, output) = sync.sync(&mut clock(1, 0), "a", Some("a"), &members());
+        assert_eq!(results(&output), vec![(timed_out.clone(), true)]);
+        assert_eq!(sync.transition_kind(&timed_out), Some(2));
+
+        // Only the host resolves
+        let (late, _) = sync.propose("b", 3).unwrap();
+        vote(&mut sync, "c", &late, false);
+        let (_, output) = sync.sync(&mut clock(1, 0), "b", Some("a"), &members());
+        assert!(results(&output).is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::Result;
+use futures_util::{SinkExt, StreamExt};
+use std::fmt;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+/// Maximum number of frames waiting to be written before sends report backpressure
+const OUTBOUND_QUEUE_CAPACITY: usize = 256;
+
+/// A frame received from the server
+pub enum InboundFrame {
+    Text(String),
+    Binary(Vec<u8>),
+    /// The connection dropped without us asking for it
+    Closed,
+}
+
+/// Why a frame could not be queued for sending
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError {
+    /// The outbound queue is full; the socket isn't keeping up
+    QueueFull,
+    /// There is no open connection to send on
+    NotConnected,
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::QueueFull => write!(f, "Outbound queue is full"),
+            SendError::NotConnected => write!(f, "Transport is not connected"),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+/// Thin WebSocket transport. Inbound frames are handed to a handler from a
+/// reader task; outbound frames are queued and written by a writer task, so
+/// sending never blocks the caller.
+pub struct Transport {
+    outbound: Option<mpsc::Sender<Message>>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl Transport {
+    pub fn new() -> Self {
+        Self {
+            outbound: None,
+            reader: None,
+        }
+    }
+
+    /// Connect to `url` and spawn the reader and writer tasks.
+    /// Must be called from within a tokio runtime.
+    pub async fn connect<F>(url: &str, handler: F) -> Result<Self>
+    where
+        F: Fn(InboundFrame) + Send + 'static,
+    {
+        let (ws_stream, _) = connect_async(url).await?;
+        let (mut sink, mut stream) = ws_stream.split();
+
+        let reader = tokio::spawn(async move {
+            while let Some(result) = stream.next().await {
+                match result {
+                    Ok(Message::Text(text)) => handler(InboundFrame::Text(text)),
+                    Ok(Message::Binary(bytes)) => handler(InboundFrame::Binary(bytes)),
+                    Ok(Message::Close(_)) => break,
+                    Ok(_) => {}
+                    Err(e) => {
+                        log::error!("Error receiving message: {}", e);
+                        break;
+                    }
+                }
+            }
+            log::info!("Connection reader finished");
+            handler(InboundFrame::Closed);
+        });
+
+        // The writer owns the sink and exits once every sender is dropped
+        let (outbound, mut queue) = mpsc::channel::<Message>(OUTBOUND_QUEUE_CAPACITY);
+        tokio::spawn(async move {
+            while let Some(message) = queue.recv().await {
+                if let Err(e) = sink.send(message).await {
+                    log::error!("Failed to send message: {}", e);
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+            log::info!("Connection writer finished");
+        });
+
+        Ok(Self {
+            outbound: Some(outbound),
+            reader: Some(reader),
+        })
+    }
+
+    pub fn send_text(&self, text: &str) -> Result<(), SendError> {
+        self.send(Message::Text(text.to_string()))
+    }
+
+    pub fn send_binary(&self, bytes: Vec<u8>) -> Result<(), SendError> {
+        self.send(Message::Binary(bytes))
+    }
+
+    fn send(&self, message: Message) -> Result<(), SendError> {
+        let outbound = self.outbound.as_ref().ok_or(SendError::NotConnected)?;
+
+        outbound.try_send(message).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => SendError::QueueFull,
+            mpsc::error::TrySendError::Closed(_) => SendError::NotConnected,
+        })
+    }
+
+    /// Queue a close frame and tear down both tasks. A frame the reader is
+    /// already handing over, including its `Closed`, can still reach the
+    /// handler afterwards, so handlers must be able to tell old sockets apart.
+    pub fn disconnect(&mut self) {
+        if let Some(reader) = self.reader.take() {
+            reader.abort();
+        }
+
+        if let Some(outbound) = self.outbound.take() {
+            let _ = outbound.try_send(Message::Close(None));
+        }
+    }
+}
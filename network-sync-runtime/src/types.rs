@@ -14,10 +14,307 @@ pub struct ActorData {
     pub current_shield: i8,
 }
 
+// (name, offset, size) of every field of a struct, read off the struct itself.
+// The destructuring fails to compile if a field is added or removed without
+// listing it here.
+macro_rules! struct_layout {
+    ($ty:ident { $($field:ident),* $(,)? }) => {{
+        let _ = |value: &$ty| {
+            let $ty { $($field: _),* } = value;
+        };
+        [$((
+            stringify!($field),
+            std::mem::offset_of!($ty, $field),
+            field_size(|value: &$ty| &value.$field),
+        )),*]
+    }};
+}
+
+fn field_size<T, F>(_: fn(&T) -> &F) -> usize {
+    std::mem::size_of::<F>()
+}
+
+impl ActorData {
+    /// FNV-1a hash of the struct's size and each field's name, offset and
+    /// size, sent to the server in `hello` so mismatched runtimes refuse
+    /// each other
+    pub fn layout_hash() -> u32 {
+        let layout = struct_layout!(ActorData {
+            world_position,
+            shape_rotation,
+            upper_limb_rot,
+            joint_table,
+            current_mask,
+            current_shield,
+        });
+
+        let mut bytes = (std::mem::size_of::<ActorData>() as u32)
+            .to_le_bytes()
+            .to_vec();
+        for (name, offset, size) in layout {
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+            bytes.extend_from_slice(&(size as u32).to_le_bytes());
+        }
+
+        bytes.iter().fold(0x811c9dc5, |hash, byte| {
+            (hash ^ *byte as u32).wrapping_mul(0x01000193)
+        })
+    }
+}
+
+/// A sample of a remote actor and when it arrived
+#[derive(Debug, Clone)]
+pub struct ActorSnapshot {
+    pub data: ActorData,
+    pub received_at: std::time::Instant,
+}
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct RemoteActorData {
     pub id: String,
+    pub owner_id: String,
     pub data: ActorData,
     pub last_update: std::time::Instant,
+    /// Frame number of `data`, the newest we ack to the sender
+    pub sync_seq: u32,
+    /// Recent frames by number, which the sender's deltas build on
+    pub frames: crate::delta::FrameHistory,
+    /// Recent samples, oldest first, for interpolation
+    pub history: std::collections::VecDeque<ActorSnapshot>,
+}
+
+/// Latest state of a remote actor synced through a schema
+#[derive(Debug, Clone)]
+pub struct RemoteActorState {
+    pub id: String,
+    pub owner_id: String,
+    pub schema: String,
+    pub data: Vec<u8>,
+    pub last_update: std::time::Instant,
+}
+
+/// What gets sent for one of our actors
+#[derive(Debug, Clone)]
+pub enum ActorPayload {
+    /// The built in player shaped data, interpolated and delta compressed
+    Player(ActorData),
+    /// Fields read through a registered schema
+    Schema { schema: String, data: Vec<u8> },
+}
+
+/// Where a player is in the game
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, N64MemoryIO)]
+pub struct PlayerPresence {
+    pub scene: u32,
+    /// -1 between rooms
+    pub room: i32,
+    pub entrance: u32,
+}
+
+/// Connection state reported to mods
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Disconnected = 0,
+    /// Socket is open but the server hasn't assigned us a client id yet
+    Connecting = 1,
+    Connected = 2,
+    InSession = 3,
+    Reconnecting = 4,
+}
+
+/// Lifecycle events queued for mods to poll
+#[derive(Debug, Clone)]
+pub enum LifecycleEvent {
+    /// The server assigned us a client id
+    Connected(String),
+    /// The connection dropped
+    Disconnected,
+    /// The server confirmed we joined a session
+    Joined(String),
+    MemberJoined(String),
+    MemberLeft(String),
+    /// The server refused our request to join a session
+    JoinRejected(String),
+    /// A session listing we requested arrived
+    SessionListReceived,
+    /// Another member, or a replay on joining, changed a session state key
+    SessionStateChanged(String),
+    /// Another member moved to a different scene, room or entrance
+    PresenceChanged(String),
+    /// Another member proposed a time transition, waiting on our vote
+    TimeTransitionProposed(String),
+    /// The host committed a time transition; everyone applies it
+    TimeTransitionCommitted(String),
+    /// A member vetoed a time transition
+    TimeTransitionCancelled(String),
+    /// Reconnecting after a drop was given up on
+    ReconnectFailed,
+    /// The host banned us from the session we were in
+    RemovedFromSession(String),
+}
+
+impl LifecycleEvent {
+    /// Event type code handed to the guest; 0 means no event
+    pub fn code(&self) -> u32 {
+        match self {
+            LifecycleEvent::Connected(_) => 1,
+            LifecycleEvent::Disconnected => 2,
+            LifecycleEvent::Joined(_) => 3,
+            LifecycleEvent::MemberJoined(_) => 4,
+            LifecycleEvent::MemberLeft(_) => 5,
+            LifecycleEvent::JoinRejected(_) => 6,
+            LifecycleEvent::SessionListReceived => 7,
+            LifecycleEvent::SessionStateChanged(_) => 8,
+            LifecycleEvent::PresenceChanged(_) => 9,
+            LifecycleEvent::TimeTransitionProposed(_) => 10,
+            LifecycleEvent::TimeTransitionCommitted(_) => 11,
+            LifecycleEvent::TimeTransitionCancelled(_) => 12,
+            LifecycleEvent::ReconnectFailed => 13,
+            LifecycleEvent::RemovedFromSession(_) => 14,
+        }
+    }
+
+    /// The client or session id the event is about, if any
+    pub fn subject(&self) -> &str {
+        match self {
+            LifecycleEvent::Connected(id)
+            | LifecycleEvent::Joined(id)
+            | LifecycleEvent::MemberJoined(id)
+            | LifecycleEvent::MemberLeft(id)
+            | LifecycleEvent::JoinRejected(id)
+            | LifecycleEvent::SessionStateChanged(id)
+            | LifecycleEvent::PresenceChanged(id)
+            | LifecycleEvent::TimeTransitionProposed(id)
+            | LifecycleEvent::TimeTransitionCommitted(id)
+            | LifecycleEvent::TimeTransitionCancelled(id)
+            | LifecycleEvent::RemovedFromSession(id) => id,
+            LifecycleEvent::Disconnected
+            | LifecycleEvent::SessionListReceived
+            | LifecycleEvent::ReconnectFailed => "",
+        }
+    }
+}
+
+/// Outcome of a join request, as reported by the server's `join_session_result`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinResult {
+    Ok,
+    /// No reply arrived before the caller stopped waiting
+    TimedOut,
+    SessionFull,
+    WrongPassword,
+    Banned,
+    /// The session was created for a different game version
+    VersionMismatch,
+    InvalidSession,
+    /// A refusal reason this build doesn't know about
+    Other,
+    /// The session was created by a runtime with a different `ActorData` layout
+    LayoutMismatch,
+}
+
+impl JoinResult {
+    pub fn from_reason(reason: &str) -> Self {
+        match reason {
+            "ok" => JoinResult::Ok,
+            "session_full" => JoinResult::SessionFull,
+            "wrong_password" => JoinResult::WrongPassword,
+            "banned" => JoinResult::Banned,
+            "version_mismatch" => JoinResult::VersionMismatch,
+            "layout_mismatch" => JoinResult::LayoutMismatch,
+            "invalid_session" => JoinResult::InvalidSession,
+            _ => JoinResult::Other,
+        }
+    }
+
+    /// Result code handed to the guest; continues on from the send status codes
+    pub fn code(&self) -> i32 {
+        match self {
+            JoinResult::Ok => 3,
+            JoinResult::TimedOut => 4,
+            JoinResult::SessionFull => 5,
+            JoinResult::WrongPassword => 6,
+            JoinResult::Banned => 7,
+            JoinResult::VersionMismatch => 8,
+            JoinResult::InvalidSession => 9,
+            JoinResult::Other => 10,
+            JoinResult::LayoutMismatch => 12,
+        }
+    }
+}
+
+/// Outcome of connecting, once the server has answered our `hello`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectResult {
+    Connected,
+    /// No socket, or no welcome before the caller stopped waiting
+    Failed,
+    /// The server refused this build, for the reason it gave
+    Incompatible(String),
+}
+
+impl ConnectResult {
+    /// Result code handed to the guest; fits between the join results
+    pub fn code(&self) -> i32 {
+        match self {
+            ConnectResult::Failed => 0,
+            ConnectResult::Connected => 1,
+            ConnectResult::Incompatible(_) => 11,
+        }
+    }
+}
+
+/// Settings sent along with a join request. The server only applies the
+/// player limit when the join creates the session.
+#[derive(Debug, Clone, Default)]
+pub struct JoinOptions {
+    pub password: Option<String>,
+    pub max_players: Option<u32>,
+}
+
+/// Details sent with every join request, used if the join creates the session
+#[derive(Debug, Clone, Default)]
+pub struct SessionDetails {
+    pub display_name: Option<String>,
+    /// Sessions refuse joiners whose version differs from their creator's
+    pub game_version: Option<String>,
+    /// Keep created sessions out of session listings
+    pub unlisted: bool,
+}
+
+/// A public session, as reported by the server's `session_list`
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionListing {
+    pub session_id: String,
+    pub display_name: String,
+    pub members: u32,
+    pub max_players: Option<u32>,
+    pub game_version: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actor_data_layout_is_pinned() {
+        // ActorSyncData in actor_sync.c and the binary codec both depend on
+        // this layout. If this fails, the change is incompatible with older
+        // runtimes: update the expected values once both of those match.
+        assert_eq!(std::mem::size_of::<ActorData>(), 172);
+        assert_eq!(std::mem::offset_of!(ActorData, joint_table), 24);
+        assert_eq!(ActorData::layout_hash(), 0x1FF87F51);
+    }
+
+    #[test]
+    fn join_refusals_keep_their_own_codes() {
+        assert_eq!(JoinResult::from_reason("version_mismatch").code(), 8);
+        assert_eq!(JoinResult::from_reason("layout_mismatch").code(), 12);
+        assert_eq!(JoinResult::from_reason("banned").code(), 7);
+        assert_eq!(JoinResult::from_reason("something_new"), JoinResult::Other);
+    }
 }
@@ -2,6 +2,25 @@ use n64_recomp::RecompContext;
 use std::panic;
 
 use crate::network::{NetworkSyncModule, NETWORK_PLAY};
+use crate::transport::SendError;
+
+/// Status codes returned to the guest by exports that queue outbound frames
+pub const SEND_FAILED: i32 = 0;
+pub const SEND_QUEUED: i32 = 1;
+pub const SEND_QUEUE_FULL: i32 = 2;
+
+/// Whether an error means the outbound queue is full rather than a hard failure
+pub fn is_queue_full(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<SendError>(),
+        Some(SendError::QueueFull)
+    )
+}
+
+/// An owner ID argument, where an empty string means any owner
+pub fn owner_arg(owner_id: &str) -> Option<&str> {
+    (!owner_id.is_empty()).then_some(owner_id)
+}
 
 /// Helper function to safely execute code that might panic
 pub fn execute_safely<F>(ctx: *mut RecompContext, func_name: &str, f: F)
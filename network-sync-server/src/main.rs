@@ -1,20 +1,32 @@
+mod protocol;
+
 use clap::Parser;
 use env_logger::Builder;
 use futures_util::{SinkExt, StreamExt};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     net::SocketAddr,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
 use tokio::{
     net::{TcpListener, TcpStream},
-    sync::broadcast,
+    sync::{broadcast, oneshot},
 };
 use tokio_tungstenite::{accept_async, tungstenite::protocol::Message};
 use uuid::Uuid;
 
+use protocol::{
+    DecodeResult, Frame, RelayMessage, CODEC_BINARY, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+    SUPPORTED_CODECS, SUPPORTED_FEATURES,
+};
+
 // Command line arguments
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
@@ -22,6 +34,14 @@ struct Args {
     /// Port to listen on
     #[clap(short, long, default_value = "8080")]
     port: u16,
+
+    /// Seconds a dropped client's session slot is held open for it to resume
+    #[clap(long, default_value = "15")]
+    resume_grace_secs: u64,
+
+    /// Largest frame, and registered message payload, accepted from a client
+    #[clap(long, default_value = "16384")]
+    max_message_bytes: usize,
 }
 
 // Message types for the protocol
@@ -29,6 +49,52 @@ struct Args {
 struct ClientMessage {
     pub event_type: String,
     pub session_id: Option<String>,
+    pub codec: Option<String>,
+    pub resume_token: Option<String>,
+    // Only used by the first joiner, when the session is created
+    pub max_players: Option<usize>,
+    pub password: Option<String>,
+    pub display_name: Option<String>,
+    pub game_version: Option<String>,
+    pub unlisted: Option<bool>,
+    // Sent in `hello`, before the client joins anything
+    pub protocol_version: Option<u32>,
+    pub runtime_version: Option<String>,
+    pub layout_hash: Option<u32>,
+    // Sent in `set_session_state`
+    pub key: Option<String>,
+    pub value: Option<Vec<u8>>,
+    // Sent in `set_presence`
+    pub scene: Option<u32>,
+    pub room: Option<i32>,
+    pub entrance: Option<u32>,
+    pub adjacent_scenes: Option<Vec<u32>>,
+    // Sent in `ban_member`
+    pub member_id: Option<String>,
+}
+
+// Session settings carried by a join request
+struct SessionSettings {
+    max_players: Option<usize>,
+    password: Option<String>,
+    display_name: Option<String>,
+    game_version: Option<String>,
+    unlisted: bool,
+}
+
+impl SessionSettings {
+    // Empty strings and a limit of 0 mean the setting wasn't given
+    fn from_message(msg: &ClientMessage) -> Self {
+        let non_empty = |value: &Option<String>| value.clone().filter(|v| !v.is_empty());
+
+        Self {
+            max_players: msg.max_players.filter(|&max| max > 0),
+            password: non_empty(&msg.password),
+            display_name: non_empty(&msg.display_name),
+            game_version: non_empty(&msg.game_version),
+            unlisted: msg.unlisted.unwrap_or(false),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,12 +104,227 @@ struct ServerMessage {
     data: serde_json::Value,
 }
 
+// Session IDs must fit the mod's 64 byte string buffers
+const MAX_SESSION_ID_LEN: usize = 63;
+
+// Session state keys must fit the mod's 64 byte string buffers
+const MAX_STATE_KEY_LEN: usize = 63;
+
+// Largest value, and most keys, a session's state can hold
+const MAX_STATE_VALUE_BYTES: usize = 1024;
+const MAX_STATE_KEYS: usize = 1024;
+
+// Most scenes a presence can name as next to its own
+const MAX_ADJACENT_SCENES: usize = 32;
+
+// How long a refused client's last message gets to go out before its socket is dropped
+const HANG_UP_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Where a member is in the game, as it last told us
+#[derive(Debug, Clone)]
+struct Presence {
+    scene: u32,
+    room: i32,
+    entrance: u32,
+    // Scenes close enough that actors there still matter, like the other
+    // side of a loading zone
+    adjacent_scenes: Vec<u32>,
+}
+
+impl Presence {
+    fn from_message(msg: &ClientMessage) -> Result<Self, &'static str> {
+        let scene = msg.scene.ok_or("missing scene")?;
+        let adjacent_scenes = msg.adjacent_scenes.clone().unwrap_or_default();
+        if adjacent_scenes.len() > MAX_ADJACENT_SCENES {
+            return Err("too many adjacent scenes");
+        }
+
+        Ok(Self {
+            scene,
+            room: msg.room.unwrap_or(-1),
+            entrance: msg.entrance.unwrap_or(0),
+            adjacent_scenes,
+        })
+    }
+
+    // Same scene, or one of them names the other's as adjacent
+    fn can_see(&self, other: &Presence) -> bool {
+        self.scene == other.scene
+            || self.adjacent_scenes.contains(&other.scene)
+            || other.adjacent_scenes.contains(&self.scene)
+    }
+}
+
+// Why a join request was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JoinError {
+    InvalidSession,
+    SessionFull,
+    WrongPassword,
+    Banned,
+    VersionMismatch,
+    LayoutMismatch,
+}
+
+impl JoinError {
+    // Reason string sent in `join_session_result`
+    fn reason(&self) -> &'static str {
+        match self {
+            JoinError::InvalidSession => "invalid_session",
+            JoinError::SessionFull => "session_full",
+            JoinError::WrongPassword => "wrong_password",
+            JoinError::Banned => "banned",
+            JoinError::VersionMismatch => "version_mismatch",
+            JoinError::LayoutMismatch => "layout_mismatch",
+        }
+    }
+}
+
+// A game session, with the settings its first joiner created it with
+struct Session {
+    members: Vec<String>,
+    // Unlimited when unset
+    max_players: Option<usize>,
+    // Salted SHA-256 of the session password, if it has one
+    password_hash: Option<String>,
+    password_salt: String,
+    // Connection that created the session, handed on when it leaves
+    host_id: String,
+    created_at: Instant,
+    display_name: String,
+    // Joiners reporting a different version are refused
+    game_version: Option<String>,
+    // Hidden from session listings
+    unlisted: bool,
+    // Actor data layout of the creator's runtime, unset if it never said
+    // hello; joiners must match it
+    layout_hash: Option<u32>,
+    // Latest complete frame of each member's actors, by owner and actor ID
+    actor_frames: HashMap<(String, String), Frame>,
+    // Mod defined state, kept for as long as the session lives
+    state: BTreeMap<String, Vec<u8>>,
+    // Where each member that published a presence is
+    presence: HashMap<String, Presence>,
+    // Connections the host banned, refused if they try to join again
+    banned: HashSet<String>,
+}
+
+impl Session {
+    fn new(
+        host_id: &str,
+        session_id: &str,
+        settings: &SessionSettings,
+        layout_hash: Option<u32>,
+    ) -> Self {
+        let password_salt = Uuid::new_v4().to_string();
+        let password_hash = settings
+            .password
+            .as_ref()
+            .map(|password| hash_password(&password_salt, password));
+
+        Self {
+            members: Vec::new(),
+            max_players: settings.max_players,
+            password_hash,
+            password_salt,
+            host_id: host_id.to_string(),
+            created_at: Instant::now(),
+            display_name: settings
+                .display_name
+                .clone()
+                .unwrap_or_else(|| session_id.to_string()),
+            game_version: settings.game_version.clone(),
+            unlisted: settings.unlisted,
+            layout_hash,
+            actor_frames: HashMap::new(),
+            state: BTreeMap::new(),
+            presence: HashMap::new(),
+            banned: HashSet::new(),
+        }
+    }
+
+    fn accepts_password(&self, password: Option<&str>) -> bool {
+        match &self.password_hash {
+            Some(hash) => password
+                .is_some_and(|password| hash_password(&self.password_salt, password) == *hash),
+            None => true,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.max_players
+            .is_some_and(|max_players| self.members.len() >= max_players)
+    }
+
+    fn accepts_version(&self, game_version: Option<&str>) -> bool {
+        self.game_version.is_none() || self.game_version.as_deref() == game_version
+    }
+
+    // Runtimes that never said hello only play with each other
+    fn accepts_layout(&self, layout_hash: Option<u32>) -> bool {
+        self.layout_hash == layout_hash
+    }
+
+    fn set_state(&mut self, key: &str, value: Vec<u8>) -> Result<(), &'static str> {
+        if key.is_empty() || key.len() > MAX_STATE_KEY_LEN {
+            return Err("key must be 1 to 63 bytes");
+        }
+
+        if value.is_empty() || value.len() > MAX_STATE_VALUE_BYTES {
+            return Err("value must be 1 to 1024 bytes");
+        }
+
+        if !self.state.contains_key(key) && self.state.len() >= MAX_STATE_KEYS {
+            return Err("session state is full");
+        }
+
+        self.state.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    // Whether actor traffic between two members is worth relaying. Members
+    // that never published a presence see everyone.
+    fn can_see(&self, a: &str, b: &str) -> bool {
+        match (self.presence.get(a), self.presence.get(b)) {
+            (Some(a), Some(b)) => a.can_see(b),
+            _ => true,
+        }
+    }
+
+    // Entry in a `session_list` reply
+    fn listing(&self, session_id: &str) -> serde_json::Value {
+        serde_json::json!({
+            "session_id": session_id,
+            "display_name": self.display_name,
+            "members": self.members.len(),
+            "max_players": self.max_players,
+            "game_version": self.game_version,
+            "has_password": self.password_hash.is_some(),
+        })
+    }
+}
+
+fn hash_password(salt: &str, password: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(salt.as_bytes())
+        .chain_update(password.as_bytes())
+        .finalize();
+
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
 // Server state
 struct ServerState {
     // Map from connection ID to session ID
     connections: HashMap<String, Option<String>>,
-    // Map from session ID to set of connection IDs
-    sessions: HashMap<String, Vec<String>>,
+    // Map from session ID to the session
+    sessions: HashMap<String, Session>,
+    // Map from connection ID to the socket currently serving it
+    sockets: HashMap<String, String>,
+    // Map from resume token to connection ID
+    resume_tokens: HashMap<String, String>,
+    // Map from connection ID to the actor data layout its hello reported
+    layout_hashes: HashMap<String, u32>,
 }
 
 impl ServerState {
@@ -51,35 +332,132 @@ impl ServerState {
         Self {
             connections: HashMap::new(),
             sessions: HashMap::new(),
+            sockets: HashMap::new(),
+            resume_tokens: HashMap::new(),
+            layout_hashes: HashMap::new(),
         }
     }
 
-    fn register_connection(&mut self, id: &str) {
+    // Registers a fresh connection served by the socket of the same ID and
+    // returns the token the client can later resume it with
+    fn register_connection(&mut self, id: &str) -> String {
         info!("Registering connection: {}", id);
         self.connections.insert(id.to_string(), None);
+        self.sockets.insert(id.to_string(), id.to_string());
+
+        let resume_token = Uuid::new_v4().to_string();
+        self.resume_tokens
+            .insert(resume_token.clone(), id.to_string());
+        resume_token
     }
 
     fn remove_connection(&mut self, id: &str) {
         self.leave_session(id);
         self.connections.remove(id);
+        self.sockets.remove(id);
+        self.layout_hashes.remove(id);
+        self.resume_tokens
+            .retain(|_, connection_id| connection_id != id);
     }
 
-    fn join_session(&mut self, connection_id: &str, session_id: &str) -> Vec<String> {
-        // Update connection's session
-        self.connections
-            .insert(connection_id.to_string(), Some(session_id.to_string()));
+    fn set_layout_hash(&mut self, connection_id: &str, layout_hash: u32) {
+        self.layout_hashes
+            .insert(connection_id.to_string(), layout_hash);
+    }
+
+    // Hands an existing connection over to a new socket, dropping the fresh
+    // connection that socket was given. Returns the resumed connection ID and
+    // its session.
+    fn resume_connection(
+        &mut self,
+        resume_token: &str,
+        socket_id: &str,
+    ) -> Option<(String, Option<String>)> {
+        let connection_id = self.resume_tokens.get(resume_token)?.clone();
+        let session_id = self.connections.get(&connection_id)?.clone();
+
+        if connection_id != socket_id {
+            self.remove_connection(socket_id);
+        }
+        self.sockets
+            .insert(connection_id.clone(), socket_id.to_string());
+
+        Some((connection_id, session_id))
+    }
+
+    fn is_served_by(&self, connection_id: &str, socket_id: &str) -> bool {
+        self.sockets.get(connection_id).map(String::as_str) == Some(socket_id)
+    }
+
+    // The settings only take effect when this join creates the session; later
+    // joiners must match its password and version and fit its player limit.
+    // A connection already in another session leaves it once the join is
+    // accepted; returns the new session's members and the session left, if any.
+    fn join_session(
+        &mut self,
+        connection_id: &str,
+        session_id: &str,
+        settings: &SessionSettings,
+    ) -> Result<(Vec<String>, Option<String>), JoinError> {
+        if session_id.is_empty() || session_id.len() > MAX_SESSION_ID_LEN {
+            return Err(JoinError::InvalidSession);
+        }
+
+        // Joining a session we're already in is a no-op
+        if self.is_in_session(connection_id, session_id) {
+            return Ok((self.get_session_members(session_id), None));
+        }
 
-        // Add to session
-        let session_members = self
+        let layout_hash = self.layout_hashes.get(connection_id).copied();
+        let session = self
             .sessions
             .entry(session_id.to_string())
-            .or_insert_with(Vec::new);
+            .or_insert_with(|| {
+                info!(
+                    "Player {} created session {} (max players: {:?}, password: {}, unlisted: {})",
+                    connection_id,
+                    session_id,
+                    settings.max_players,
+                    settings.password.is_some(),
+                    settings.unlisted
+                );
+                Session::new(connection_id, session_id, settings, layout_hash)
+            });
+
+        if !session.accepts_version(settings.game_version.as_deref()) {
+            return Err(JoinError::VersionMismatch);
+        }
+
+        if !session.accepts_layout(layout_hash) {
+            return Err(JoinError::LayoutMismatch);
+        }
+
+        if session.banned.contains(connection_id) {
+            return Err(JoinError::Banned);
+        }
 
-        if !session_members.contains(&connection_id.to_string()) {
-            session_members.push(connection_id.to_string());
+        if !session.accepts_password(settings.password.as_deref()) {
+            return Err(JoinError::WrongPassword);
         }
 
-        session_members.clone()
+        if session.is_full() {
+            return Err(JoinError::SessionFull);
+        }
+
+        // Don't hold a slot, the host role or a place in relays of the old one
+        let left_session = self.leave_session(connection_id);
+
+        let Some(session) = self.sessions.get_mut(session_id) else {
+            return Err(JoinError::InvalidSession);
+        };
+        session.members.push(connection_id.to_string());
+        let members = session.members.clone();
+
+        // Update connection's session
+        self.connections
+            .insert(connection_id.to_string(), Some(session_id.to_string()));
+
+        Ok((members, left_session))
     }
 
     fn leave_session(&mut self, connection_id: &str) -> Option<String> {
@@ -89,11 +467,27 @@ impl ServerState {
             self.connections.insert(connection_id.to_string(), None);
 
             // Remove from session
-            if let Some(connections) = self.sessions.get_mut(&session_id) {
-                connections.retain(|cid| cid != connection_id);
+            if let Some(session) = self.sessions.get_mut(&session_id) {
+                session.members.retain(|cid| cid != connection_id);
+                session
+                    .actor_frames
+                    .retain(|(owner_id, _), _| owner_id != connection_id);
+                session.presence.remove(connection_id);
+
                 // Clean up empty sessions
-                if connections.is_empty() {
+                if session.members.is_empty() {
+                    info!(
+                        "Session {} closed after {:?}",
+                        session_id,
+                        session.created_at.elapsed()
+                    );
                     self.sessions.remove(&session_id);
+                } else if session.host_id == connection_id {
+                    session.host_id = session.members[0].clone();
+                    info!(
+                        "Player {} is now hosting session {}",
+                        session.host_id, session_id
+                    );
                 }
             }
 
@@ -102,15 +496,145 @@ impl ServerState {
         None
     }
 
+    // Only the host may ban, and not itself. A banned member still in the
+    // session is removed from it; returns the session and whether it was.
+    fn ban_member(
+        &mut self,
+        connection_id: &str,
+        member_id: &str,
+    ) -> Result<(String, bool), &'static str> {
+        let session_id = match self.connections.get(connection_id) {
+            Some(Some(session_id)) => session_id.clone(),
+            _ => return Err("not in a session"),
+        };
+        let session = self
+            .sessions
+            .get_mut(&session_id)
+            .ok_or("not in a session")?;
+
+        if session.host_id != connection_id {
+            return Err("only the host can ban");
+        }
+
+        if member_id.is_empty() || member_id == connection_id {
+            return Err("can't ban that member");
+        }
+
+        session.banned.insert(member_id.to_string());
+        let removed = self.is_in_session(member_id, &session_id);
+        if removed {
+            self.leave_session(member_id);
+        }
+
+        Ok((session_id, removed))
+    }
+
     fn get_session_members(&self, session_id: &str) -> Vec<String> {
-        self.sessions.get(session_id).cloned().unwrap_or_default()
+        self.sessions
+            .get(session_id)
+            .map(|session| session.members.clone())
+            .unwrap_or_default()
     }
 
-    fn is_in_session(&self, connection_id: &str, session_id: &str) -> bool {
-        match self.connections.get(connection_id) {
-            Some(Some(s)) if s == session_id => true,
-            _ => false,
+    // Listings of every session that isn't unlisted, oldest first
+    fn list_sessions(&self) -> Vec<serde_json::Value> {
+        let mut sessions: Vec<_> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| !session.unlisted)
+            .collect();
+        sessions.sort_by_key(|(_, session)| session.created_at);
+
+        sessions
+            .into_iter()
+            .map(|(session_id, session)| session.listing(session_id))
+            .collect()
+    }
+
+    fn current_session_mut(&mut self, connection_id: &str) -> Option<&mut Session> {
+        let session_id = self.connections.get(connection_id)?.as_ref()?;
+        self.sessions.get_mut(session_id)
+    }
+
+    // Keep the latest complete frame of every actor in `relay_msg`, for
+    // members that join later
+    fn remember_actor_frames(
+        &mut self,
+        connection_id: &str,
+        relay_msg: &RelayMessage,
+    ) -> serde_json::Result<()> {
+        if let Some(session) = self.current_session_mut(connection_id) {
+            for msg in relay_msg.entries() {
+                if let Some(key) = msg.actor_key() {
+                    session.actor_frames.insert(key, msg.to_frame()?);
+                }
+            }
         }
+
+        Ok(())
+    }
+
+    // Store a state value in the connection's session, returning the session
+    // and the members to tell about it
+    fn set_session_state(
+        &mut self,
+        connection_id: &str,
+        key: &str,
+        value: Vec<u8>,
+    ) -> Result<(String, Vec<String>), &'static str> {
+        let session_id = match self.connections.get(connection_id) {
+            Some(Some(session_id)) => session_id.clone(),
+            _ => return Err("not in a session"),
+        };
+        let session = self
+            .sessions
+            .get_mut(&session_id)
+            .ok_or("not in a session")?;
+
+        session.set_state(key, value)?;
+        Ok((session_id, session.members.clone()))
+    }
+
+    // Record where a member is, returning its session, the members to tell
+    // about it, and the members it couldn't see before but can now
+    fn set_presence(
+        &mut self,
+        connection_id: &str,
+        presence: Presence,
+    ) -> Result<(String, Vec<String>, Vec<String>), &'static str> {
+        let session_id = match self.connections.get(connection_id) {
+            Some(Some(session_id)) => session_id.clone(),
+            _ => return Err("not in a session"),
+        };
+        let session = self
+            .sessions
+            .get_mut(&session_id)
+            .ok_or("not in a session")?;
+
+        let others: Vec<String> = session
+            .members
+            .iter()
+            .filter(|member| *member != connection_id)
+            .cloned()
+            .collect();
+        let hidden: Vec<String> = others
+            .iter()
+            .filter(|member| !session.can_see(connection_id, member))
+            .cloned()
+            .collect();
+
+        session.presence.insert(connection_id.to_string(), presence);
+
+        let revealed = hidden
+            .into_iter()
+            .filter(|member| session.can_see(connection_id, member))
+            .collect();
+
+        Ok((session_id, others, revealed))
+    }
+
+    fn is_in_session(&self, connection_id: &str, session_id: &str) -> bool {
+        matches!(self.connections.get(connection_id), Some(Some(s)) if s == session_id)
     }
 }
 
@@ -133,11 +657,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let listener = TcpListener::bind(&addr).await?;
     info!("Listening on: {}", addr);
 
+    let resume_grace = Duration::from_secs(args.resume_grace_secs);
+    let max_message_bytes = args.max_message_bytes;
+
     // Create shared server state
     let state = Arc::new(Mutex::new(ServerState::new()));
 
     // Create broadcast channel for server messages
-    let (tx, _) = broadcast::channel::<(String, String)>(100);
+    let (tx, _) = broadcast::channel::<(String, Frame)>(100);
 
     // Accept connections
     while let Ok((stream, addr)) = listener.accept().await {
@@ -147,29 +674,38 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let tx = tx.clone();
         let state = Arc::clone(&state);
 
-        // Generate a unique ID for this connection
-        let connection_id = Uuid::new_v4().to_string();
+        // Generate a unique ID for this socket, which is also its connection ID
+        // unless the client resumes an earlier connection
+        let socket_id = Uuid::new_v4().to_string();
 
         // Register connection
-        {
+        let resume_token = {
             let mut state = state.lock().unwrap();
-            state.register_connection(&connection_id);
-        }
+            state.register_connection(&socket_id)
+        };
 
-        // Clone state for disconnect handling
-        let disconnect_state = Arc::clone(&state);
-        let disconnect_id = connection_id.clone();
+        // The connection ID this socket currently serves
+        let current_id = Arc::new(Mutex::new(socket_id.clone()));
 
         // Spawn a task to handle this connection
         tokio::spawn(async move {
-            if let Err(e) = handle_connection(stream, connection_id.clone(), state, tx).await {
-                error!("Error handling connection {}: {}", disconnect_id, e);
+            if let Err(e) = handle_connection(
+                stream,
+                socket_id.clone(),
+                Arc::clone(&current_id),
+                resume_token,
+                max_message_bytes,
+                Arc::clone(&state),
+                tx.clone(),
+            )
+            .await
+            {
+                error!("Error handling connection {}: {}", socket_id, e);
             }
 
             // On disconnect, clean up
-            let mut state = disconnect_state.lock().unwrap();
-            state.remove_connection(&connection_id);
-            info!("Connection closed: {}", connection_id);
+            let connection_id = current_id.lock().unwrap().clone();
+            release_connection(state, tx, connection_id, socket_id, resume_grace).await;
         });
     }
 
@@ -178,10 +714,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 async fn handle_connection(
     stream: TcpStream,
-    connection_id: String,
+    socket_id: String,
+    current_id: Arc<Mutex<String>>,
+    resume_token: String,
+    max_message_bytes: usize,
     state: Arc<Mutex<ServerState>>,
-    tx: broadcast::Sender<(String, String)>,
+    tx: broadcast::Sender<(String, Frame)>,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let mut connection_id = socket_id.clone();
+
     // Accept WebSocket connection
     let ws_stream = accept_async(stream).await?;
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
@@ -190,7 +731,11 @@ async fn handle_connection(
     let welcome = ServerMessage {
         event_type: "welcome".to_string(),
         sender_id: connection_id.clone(),
-        data: serde_json::json!({}),
+        data: serde_json::json!({
+            "codecs": SUPPORTED_CODECS,
+            "features": SUPPORTED_FEATURES,
+            "resume_token": resume_token,
+        }),
     };
 
     ws_sender
@@ -200,14 +745,58 @@ async fn handle_connection(
     // Subscribe to broadcast messages
     let mut rx = tx.subscribe();
 
+    // Set once the client selects binary framing
+    let wants_binary = Arc::new(AtomicBool::new(false));
+
+    // Carries the last message for a client we're hanging up on, like a
+    // refused hello; the forward task sends it and closes the socket
+    let (hang_up_tx, mut hang_up_rx) = oneshot::channel::<Message>();
+    let mut hang_up_tx = Some(hang_up_tx);
+
     // Create task to forward broadcasts to this connection
-    let conn_id = connection_id.clone();
-    let forward_task = tokio::spawn(async move {
-        while let Ok((target, msg)) = rx.recv().await {
-            // Send if broadcast is for all or specifically for this connection
-            if target == "*" || target == conn_id {
-                if let Err(e) = ws_sender.send(Message::Text(msg)).await {
-                    error!("Failed to forward message: {}", e);
+    let forward_id = Arc::clone(&current_id);
+    let forward_binary = Arc::clone(&wants_binary);
+    let mut forward_task = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                received = rx.recv() => {
+                    let (target, frame) = match received {
+                        Ok(received) => received,
+                        // Skipped frames are gone, but later ones still arrive
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!(
+                                "Connection {} fell behind and missed {} messages",
+                                forward_id.lock().unwrap(),
+                                skipped
+                            );
+                            continue;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    };
+
+                    // Send if broadcast is for all or specifically for this connection
+                    let is_target = target == "*" || target == *forward_id.lock().unwrap();
+                    if is_target {
+                        let msg = match frame.binary {
+                            Some(bytes) if forward_binary.load(Ordering::Relaxed) => {
+                                Message::Binary(bytes)
+                            }
+                            _ => Message::Text(frame.text),
+                        };
+
+                        if let Err(e) = ws_sender.send(msg).await {
+                            error!("Failed to forward message: {}", e);
+                            break;
+                        }
+                    }
+                }
+                last = &mut hang_up_rx => {
+                    if let Ok(msg) = last {
+                        if let Err(e) = ws_sender.send(msg).await {
+                            error!("Failed to send final message: {}", e);
+                        }
+                        let _ = ws_sender.close().await;
+                    }
                     break;
                 }
             }
@@ -226,36 +815,75 @@ async fn handle_connection(
 
         debug!("Received message from {}", connection_id);
 
+        if msg.len() > max_message_bytes {
+            warn!(
+                "Dropping message from {}: frame of {} bytes exceeds {}",
+                connection_id,
+                msg.len(),
+                max_message_bytes
+            );
+            continue;
+        }
+
         if let Message::Text(text) = msg {
             // Try to parse as client message
             match serde_json::from_str::<ClientMessage>(&text) {
                 Ok(client_msg) => {
                     match client_msg.event_type.as_str() {
                         "join_session" => {
-                            if let Some(session_id) = &client_msg.session_id {
-                                let members = {
-                                    let mut state = state.lock().unwrap();
-                                    state.join_session(&connection_id, session_id)
-                                };
-
-                                // Notify all session members
-                                let session_msg = ServerMessage {
-                                    event_type: "session_members".to_string(),
-                                    sender_id: connection_id.clone(),
-                                    data: serde_json::json!({
-                                        "session_id": session_id,
-                                        "members": members,
-                                    }),
-                                };
+                            let settings = SessionSettings::from_message(&client_msg);
+                            let session_id = client_msg.session_id.unwrap_or_default();
+                            let result = {
+                                let mut state = state.lock().unwrap();
+                                state.join_session(&connection_id, &session_id, &settings)
+                            };
 
-                                let msg_str = serde_json::to_string(&session_msg)?;
+                            // Answer the joiner before telling the rest of the session
+                            send_join_result(
+                                &tx,
+                                &connection_id,
+                                &session_id,
+                                result.as_ref().map(|_| ()).map_err(|e| *e),
+                            )?;
 
-                                // Broadcast to all session members
-                                for member in members {
-                                    tx.send((member, msg_str.clone()))?;
-                                }
+                            match result {
+                                Ok((_, left_session)) => {
+                                    // Tell the session we moved out of that we're gone
+                                    if let Some(left_session) = left_session {
+                                        broadcast_session_members(
+                                            &state,
+                                            &tx,
+                                            &connection_id,
+                                            &left_session,
+                                        )?;
+
+                                        info!(
+                                            "Player {} left session {}",
+                                            connection_id, left_session
+                                        );
+                                    }
 
-                                info!("Player {} joined session {}", connection_id, session_id);
+                                    // Notify all session members
+                                    broadcast_session_members(
+                                        &state,
+                                        &tx,
+                                        &connection_id,
+                                        &session_id,
+                                    )?;
+
+                                    // Then catch the joiner up on what it missed
+                                    replay_session_state(&state, &tx, &connection_id, &session_id)?;
+
+                                    info!("Player {} joined session {}", connection_id, session_id);
+                                }
+                                Err(e) => {
+                                    info!(
+                                        "Player {} was refused session {}: {}",
+                                        connection_id,
+                                        session_id,
+                                        e.reason()
+                                    );
+                                }
                             }
                         }
 
@@ -266,104 +894,871 @@ async fn handle_connection(
                             };
 
                             if let Some(session_id) = result {
-                                let members = {
-                                    let state = state.lock().unwrap();
-                                    state.get_session_members(&session_id)
-                                };
-
                                 // Notify remaining members
-                                let leave_msg = ServerMessage {
-                                    event_type: "session_members".to_string(),
-                                    sender_id: connection_id.clone(),
-                                    data: serde_json::json!({
+                                broadcast_session_members(
+                                    &state,
+                                    &tx,
+                                    &connection_id,
+                                    &session_id,
+                                )?;
+
+                                info!("Player {} left session {}", connection_id, session_id);
+                            }
+                        }
+
+                        "ban_member" => {
+                            let member_id = client_msg.member_id.unwrap_or_default();
+                            let result = {
+                                let mut state = state.lock().unwrap();
+                                state.ban_member(&connection_id, &member_id)
+                            };
+
+                            match result {
+                                Ok((session_id, removed)) => {
+                                    if removed {
+                                        // Let the banned member know why it's out, then
+                                        // tell everyone left
+                                        let removed_msg = ServerMessage {
+                                            event_type: "removed_from_session".to_string(),
+                                            sender_id: connection_id.clone(),
+                                            data: serde_json::json!({
+                                                "session_id": session_id,
+                                                "reason": JoinError::Banned.reason(),
+                                            }),
+                                        };
+
+                                        let frame =
+                                            Frame::text(serde_json::to_string(&removed_msg)?);
+                                        tx.send((member_id.clone(), frame))?;
+
+                                        broadcast_session_members(
+                                            &state,
+                                            &tx,
+                                            &connection_id,
+                                            &session_id,
+                                        )?;
+                                    }
+
+                                    info!(
+                                        "Player {} banned {} from session {}",
+                                        connection_id, member_id, session_id
+                                    );
+                                }
+                                Err(e) => {
+                                    warn!(
+                                        "Dropping ban of {} from {}: {}",
+                                        member_id, connection_id, e
+                                    );
+                                }
+                            }
+                        }
+
+                        "list_sessions" => {
+                            let sessions = {
+                                let state = state.lock().unwrap();
+                                state.list_sessions()
+                            };
+
+                            let list_msg = ServerMessage {
+                                event_type: "session_list".to_string(),
+                                sender_id: connection_id.clone(),
+                                data: serde_json::json!({ "sessions": sessions }),
+                            };
+
+                            let frame = Frame::text(serde_json::to_string(&list_msg)?);
+                            tx.send((connection_id.clone(), frame))?;
+                        }
+
+                        "resume" => {
+                            let token = client_msg.resume_token.unwrap_or_default();
+                            let resumed = {
+                                let mut state = state.lock().unwrap();
+                                state.resume_connection(&token, &socket_id)
+                            };
+
+                            let mut resumed_session = None;
+                            let result = match resumed {
+                                Some((resumed_id, session_id)) => {
+                                    info!("Socket {} resumed connection {}", socket_id, resumed_id);
+                                    connection_id = resumed_id;
+                                    *current_id.lock().unwrap() = connection_id.clone();
+                                    resumed_session = session_id.clone();
+                                    serde_json::json!({
+                                        "resumed": true,
+                                        "resume_token": token,
                                         "session_id": session_id,
-                                        "members": members,
-                                    }),
-                                };
+                                    })
+                                }
+                                None => {
+                                    info!("Connection {} could not be resumed", connection_id);
+                                    serde_json::json!({
+                                        "resumed": false,
+                                        "resume_token": resume_token,
+                                    })
+                                }
+                            };
+
+                            let result_msg = ServerMessage {
+                                event_type: "resume_result".to_string(),
+                                sender_id: connection_id.clone(),
+                                data: result,
+                            };
+
+                            let frame = Frame::text(serde_json::to_string(&result_msg)?);
+                            tx.send((connection_id.clone(), frame))?;
+
+                            // Whatever changed while we were gone
+                            if let Some(session_id) = resumed_session {
+                                replay_session_state(&state, &tx, &connection_id, &session_id)?;
+                            }
+                        }
+
+                        "set_session_state" => {
+                            let key = client_msg.key.unwrap_or_default();
+                            let value = client_msg.value.unwrap_or_default();
+                            let result = {
+                                let mut state = state.lock().unwrap();
+                                state.set_session_state(&connection_id, &key, value.clone())
+                            };
 
-                                let msg_str = serde_json::to_string(&leave_msg)?;
+                            match result {
+                                Ok((session_id, members)) => {
+                                    let entries =
+                                        serde_json::json!([{ "key": key, "value": value }]);
+                                    let frame = session_state_frame(
+                                        &connection_id,
+                                        &session_id,
+                                        entries,
+                                        false,
+                                    )?;
 
-                                // Broadcast to remaining session members
-                                for member in members {
-                                    tx.send((member, msg_str.clone()))?;
+                                    // Everyone, the setter included, applies sets in our order
+                                    for member in members {
+                                        tx.send((member, frame.clone()))?;
+                                    }
+                                }
+                                Err(e) => {
+                                    warn!(
+                                        "Dropping session state {} from {}: {}",
+                                        key, connection_id, e
+                                    );
                                 }
+                            }
+                        }
 
-                                info!("Player {} left session {}", connection_id, session_id);
+                        "set_presence" => {
+                            let result = Presence::from_message(&client_msg).and_then(|presence| {
+                                let mut state = state.lock().unwrap();
+                                let result = state.set_presence(&connection_id, presence.clone());
+                                result.map(|result| (presence, result))
+                            });
+
+                            match result {
+                                Ok((presence, (session_id, others, revealed))) => {
+                                    let frame =
+                                        presence_frame(&connection_id, &session_id, &presence)?;
+                                    for member in others {
+                                        tx.send((member, frame.clone()))?;
+                                    }
+
+                                    // Actors that just came into view, so neither
+                                    // side waits on the other's next full frame
+                                    exchange_actor_frames(&state, &tx, &connection_id, &revealed)?;
+                                }
+                                Err(e) => {
+                                    warn!("Dropping presence from {}: {}", connection_id, e);
+                                }
                             }
                         }
 
-                        _ => {
-                            debug!("Forwarding message from {}: {}", connection_id, text);
-                            // Messages not specially handled we'll broadcast to everyone in the same session
-                            let msg_str = text.clone();
-                            let state = state.lock().unwrap();
-                            if let Some(Some(session_id)) = state.connections.get(&connection_id) {
-                                for member in state.get_session_members(session_id) {
-                                    tx.send((member, msg_str.clone()))?;
+                        "hello" => {
+                            let protocol_version = client_msg.protocol_version.unwrap_or(0);
+                            let runtime_version =
+                                client_msg.runtime_version.as_deref().unwrap_or("unknown");
+
+                            let result = check_hello(protocol_version);
+                            match result {
+                                Ok(()) => {
+                                    if let Some(layout_hash) = client_msg.layout_hash {
+                                        let mut state = state.lock().unwrap();
+                                        state.set_layout_hash(&connection_id, layout_hash);
+                                    }
+                                    info!(
+                                        "Connection {} is runtime {} (protocol {}, layout {:08x})",
+                                        connection_id,
+                                        runtime_version,
+                                        protocol_version,
+                                        client_msg.layout_hash.unwrap_or(0)
+                                    );
+                                }
+                                Err(reason) => {
+                                    info!(
+                                        "Refusing runtime {} on connection {}: {}",
+                                        runtime_version, connection_id, reason
+                                    );
+                                }
+                            }
+
+                            let accepted = result.is_ok();
+                            let result_msg = ServerMessage {
+                                event_type: "hello_result".to_string(),
+                                sender_id: connection_id.clone(),
+                                data: serde_json::json!({
+                                    "accepted": accepted,
+                                    "reason": result.err().unwrap_or("ok"),
+                                    "protocol_version": PROTOCOL_VERSION,
+                                    "min_protocol_version": MIN_PROTOCOL_VERSION,
+                                }),
+                            };
+                            let text = serde_json::to_string(&result_msg)?;
+
+                            // A refused client gets the reason, then the socket is closed
+                            if !accepted {
+                                if let Some(hang_up_tx) = hang_up_tx.take() {
+                                    let _ = hang_up_tx.send(Message::Text(text));
                                 }
+                                break;
                             }
+
+                            tx.send((connection_id.clone(), Frame::text(text)))?;
+                        }
+
+                        "select_codec" => {
+                            let binary = client_msg.codec.as_deref() == Some(CODEC_BINARY);
+                            wants_binary.store(binary, Ordering::Relaxed);
+                            info!(
+                                "Connection {} selected binary framing: {}",
+                                connection_id, binary
+                            );
+                        }
+
+                        _ => {
+                            debug!("Relaying message from {}: {}", connection_id, text);
+                            let relay_msg = RelayMessage::from_json(&text);
+                            relay_to_session(
+                                &state,
+                                &connection_id,
+                                &tx,
+                                relay_msg,
+                                max_message_bytes,
+                            )?;
                         }
                     }
                 }
                 Err(e) => {
-                    debug!("Forwarding message from {}: {}", connection_id, text);
-
-                    // Messages not specially handled we'll broadcast to everyone in the same session
-                    let msg_str = text.clone();
-                    let state = state.lock().unwrap();
-                    if let Some(Some(session_id)) = state.connections.get(&connection_id) {
-                        for member in state.get_session_members(session_id) {
-                            tx.send((member, msg_str.clone()))?;
-                        }
-                    }
+                    warn!("Dropping malformed message from {}: {}", connection_id, e);
                 }
             }
+        } else if let Message::Binary(bytes) = msg {
+            debug!("Relaying binary message from {}", connection_id);
+            let relay_msg = RelayMessage::from_binary(&bytes);
+            relay_to_session(&state, &connection_id, &tx, relay_msg, max_message_bytes)?;
         }
     }
 
-    // Cancel the forward task when the connection closes
-    forward_task.abort();
+    // Cancel the forward task when the connection closes, unless it's still
+    // seeing a hang up through
+    if hang_up_tx.is_some()
+        || tokio::time::timeout(HANG_UP_TIMEOUT, &mut forward_task)
+            .await
+            .is_err()
+    {
+        forward_task.abort();
+    }
 
-    // Notify about disconnection
-    let state_clone = Arc::clone(&state);
-    let session_id_opt = {
-        let state = state.lock().unwrap();
-        state
-            .connections
-            .get(&connection_id)
-            .and_then(|s| s.clone())
+    Ok(())
+}
+
+// Forward a decoded client message to its session as coming from that client,
+// dropping it with a logged reason if it's malformed or out of bounds
+fn relay_to_session(
+    state: &Mutex<ServerState>,
+    connection_id: &str,
+    tx: &broadcast::Sender<(String, Frame)>,
+    relay_msg: DecodeResult<RelayMessage>,
+    max_payload_bytes: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut relay_msg = match relay_msg.and_then(|msg| {
+        msg.validate(max_payload_bytes)?;
+        Ok(msg)
+    }) {
+        Ok(msg) => msg,
+        Err(e) => {
+            warn!("Dropping message from {}: {}", connection_id, e);
+            return Ok(());
+        }
+    };
+
+    // Clients don't get to choose who they speak for
+    relay_msg.set_sender(connection_id);
+    state
+        .lock()
+        .unwrap()
+        .remember_actor_frames(connection_id, &relay_msg)?;
+    let echo = relay_msg.wants_echo();
+    let targets = relay_msg.take_targets();
+
+    let frame = relay_msg.to_frame()?;
+    let out_of_view = if relay_msg.has_actor_traffic() {
+        relay_msg
+            .without_actor_traffic()
+            .map(|msg| msg.to_frame())
+            .transpose()?
+    } else {
+        Some(frame.clone())
+    };
+
+    forward_to_session(state, connection_id, tx, frame, out_of_view, echo, &targets)
+}
+
+// Messages not specially handled we'll broadcast to everyone else in the same
+// session, and back to the sender only if it asked for an echo. Messages with
+// targets only go to the named members. Members that can't see the sender's
+// scene get `out_of_view` instead, if there's anything left of it.
+fn forward_to_session(
+    state: &Mutex<ServerState>,
+    connection_id: &str,
+    tx: &broadcast::Sender<(String, Frame)>,
+    frame: Frame,
+    out_of_view: Option<Frame>,
+    echo: bool,
+    targets: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let state = state.lock().unwrap();
+    let Some(Some(session_id)) = state.connections.get(connection_id) else {
+        return Ok(());
+    };
+    let Some(session) = state.sessions.get(session_id) else {
+        return Ok(());
     };
 
-    if let Some(session_id) = session_id_opt {
-        let members = {
-            let state = state_clone.lock().unwrap();
-            state
-                .get_session_members(&session_id)
-                .into_iter()
-                .filter(|id| id != &connection_id)
-                .collect::<Vec<_>>()
+    for member in &session.members {
+        let is_recipient = if targets.is_empty() {
+            *member != connection_id || echo
+        } else {
+            targets.contains(member)
         };
 
-        if !members.is_empty() {
-            // Create disconnection message with updated member list
-            let disconnect_msg = ServerMessage {
-                event_type: "session_members".to_string(),
-                sender_id: connection_id.clone(),
-                data: serde_json::json!({
-                    "session_id": session_id,
-                    "members": members,
-                }),
-            };
-
-            let msg_str = serde_json::to_string(&disconnect_msg)?;
-
-            // Broadcast to remaining members
-            log::info!("Broadcasting disconnection message to remaining members");
-            for member in members {
-                let _ = tx.send((member, msg_str.clone()));
-            }
+        let frame = if session.can_see(connection_id, member) {
+            Some(&frame)
+        } else {
+            out_of_view.as_ref()
+        };
+
+        if let (true, Some(frame)) = (is_recipient, frame) {
+            tx.send((member.clone(), frame.clone()))?;
+        }
+    }
+
+    Ok(())
+}
+
+// Send the latest frames of actors on either side of a member and the members
+// that just came into its view
+fn exchange_actor_frames(
+    state: &Mutex<ServerState>,
+    tx: &broadcast::Sender<(String, Frame)>,
+    connection_id: &str,
+    revealed: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if revealed.is_empty() {
+        return Ok(());
+    }
+
+    let state = state.lock().unwrap();
+    let Some(Some(session_id)) = state.connections.get(connection_id) else {
+        return Ok(());
+    };
+    let Some(session) = state.sessions.get(session_id) else {
+        return Ok(());
+    };
+
+    let mut ours = Vec::new();
+    let mut theirs = Vec::new();
+    for ((owner_id, _), frame) in &session.actor_frames {
+        if owner_id == connection_id {
+            ours.push(frame);
+        } else if revealed.contains(owner_id) {
+            theirs.push(frame);
+        }
+    }
+
+    if !ours.is_empty() {
+        let frame = batch_frame(connection_id, &ours)?;
+        for member in revealed {
+            tx.send((member.clone(), frame.clone()))?;
+        }
+    }
+
+    if !theirs.is_empty() {
+        let frame = batch_frame(connection_id, &theirs)?;
+        tx.send((connection_id.to_string(), frame))?;
+    }
+
+    Ok(())
+}
+
+// Drop a closed socket's connection. Connections in a session are held for a
+// grace window first so a client that reconnects can resume without the other
+// members seeing it leave and rejoin.
+async fn release_connection(
+    state: Arc<Mutex<ServerState>>,
+    tx: broadcast::Sender<(String, Frame)>,
+    connection_id: String,
+    socket_id: String,
+    grace: Duration,
+) {
+    let in_session = {
+        let state = state.lock().unwrap();
+        if !state.is_served_by(&connection_id, &socket_id) {
+            // Already resumed by another socket
+            return;
+        }
+        matches!(state.connections.get(&connection_id), Some(Some(_)))
+    };
+
+    if in_session && !grace.is_zero() {
+        info!(
+            "Holding connection {} for {:?} to resume",
+            connection_id, grace
+        );
+        tokio::time::sleep(grace).await;
+    }
+
+    let session_id = {
+        let mut state = state.lock().unwrap();
+        if !state.is_served_by(&connection_id, &socket_id) {
+            info!("Connection {} was resumed", connection_id);
+            return;
+        }
+
+        let session_id = state.leave_session(&connection_id);
+        state.remove_connection(&connection_id);
+        session_id
+    };
+
+    info!("Connection closed: {}", connection_id);
+
+    if let Some(session_id) = session_id {
+        // Broadcast to remaining members, if the session outlived us
+        info!("Broadcasting disconnection message to remaining members");
+        if let Err(e) = broadcast_session_members(&state, &tx, &connection_id, &session_id) {
+            error!(
+                "Failed to broadcast disconnection of {}: {}",
+                connection_id, e
+            );
+        }
+    }
+}
+
+// Send the current member list of a session to each of its members
+fn broadcast_session_members(
+    state: &Mutex<ServerState>,
+    tx: &broadcast::Sender<(String, Frame)>,
+    sender_id: &str,
+    session_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (members, host_id) = {
+        let state = state.lock().unwrap();
+        match state.sessions.get(session_id) {
+            Some(session) => (session.members.clone(), session.host_id.clone()),
+            None => return Ok(()),
         }
+    };
+
+    let session_msg = ServerMessage {
+        event_type: "session_members".to_string(),
+        sender_id: sender_id.to_string(),
+        data: serde_json::json!({
+            "session_id": session_id,
+            "members": members,
+            "host_id": host_id,
+        }),
+    };
+
+    let frame = Frame::text(serde_json::to_string(&session_msg)?);
+
+    for member in members {
+        tx.send((member, frame.clone()))?;
+    }
+
+    Ok(())
+}
+
+// Send a new member the session's state, where everyone is, and the latest
+// frame of every other member's actors it can see, so it doesn't wait on them
+// to change before seeing them
+fn replay_session_state(
+    state: &Mutex<ServerState>,
+    tx: &broadcast::Sender<(String, Frame)>,
+    connection_id: &str,
+    session_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (entries, presence, frames) = {
+        let state = state.lock().unwrap();
+        let Some(session) = state.sessions.get(session_id) else {
+            return Ok(());
+        };
+
+        let entries: Vec<_> = session
+            .state
+            .iter()
+            .map(|(key, value)| serde_json::json!({ "key": key, "value": value }))
+            .collect();
+        let presence = session
+            .presence
+            .iter()
+            .filter(|(member, _)| *member != connection_id)
+            .map(|(member, presence)| presence_frame(member, session_id, presence))
+            .collect::<serde_json::Result<Vec<_>>>()?;
+        let frames: Vec<_> = session
+            .actor_frames
+            .iter()
+            .filter(|((owner_id, _), _)| {
+                owner_id != connection_id && session.can_see(connection_id, owner_id)
+            })
+            .map(|(_, frame)| frame.clone())
+            .collect();
+        (entries, presence, frames)
+    };
+
+    let mut replay = Vec::with_capacity(1 + presence.len() + frames.len());
+    if !entries.is_empty() {
+        replay.push(session_state_frame(
+            connection_id,
+            session_id,
+            entries.into(),
+            true,
+        )?);
+    }
+    replay.extend(presence);
+    replay.extend(frames);
+
+    if !replay.is_empty() {
+        let frame = batch_frame(connection_id, &replay.iter().collect::<Vec<_>>())?;
+        tx.send((connection_id.to_string(), frame))?;
+    }
+
+    Ok(())
+}
+
+// Frames for one connection wrapped in a single JSON batch, so catching a
+// client up is one entry in the broadcast channel rather than one per frame.
+// Every runtime that passes the hello check unpacks batches.
+fn batch_frame(sender_id: &str, frames: &[&Frame]) -> serde_json::Result<Frame> {
+    let messages = frames
+        .iter()
+        .map(|frame| serde_json::from_str(&frame.text))
+        .collect::<serde_json::Result<Vec<serde_json::Value>>>()?;
+
+    let batch = serde_json::json!({
+        "event_type": "batch",
+        "sender_id": sender_id,
+        "messages": messages,
+    });
+
+    Ok(Frame::text(serde_json::to_string(&batch)?))
+}
+
+// `replay` marks the whole state sent on joining, as opposed to a live change
+fn session_state_frame(
+    sender_id: &str,
+    session_id: &str,
+    entries: serde_json::Value,
+    replay: bool,
+) -> serde_json::Result<Frame> {
+    let state_msg = ServerMessage {
+        event_type: "session_state".to_string(),
+        sender_id: sender_id.to_string(),
+        data: serde_json::json!({
+            "session_id": session_id,
+            "entries": entries,
+            "replay": replay,
+        }),
+    };
+
+    Ok(Frame::text(serde_json::to_string(&state_msg)?))
+}
+
+// Where `sender_id` is, for the rest of its session
+fn presence_frame(
+    sender_id: &str,
+    session_id: &str,
+    presence: &Presence,
+) -> serde_json::Result<Frame> {
+    let presence_msg = ServerMessage {
+        event_type: "presence".to_string(),
+        sender_id: sender_id.to_string(),
+        data: serde_json::json!({
+            "session_id": session_id,
+            "scene": presence.scene,
+            "room": presence.room,
+            "entrance": presence.entrance,
+        }),
+    };
+
+    Ok(Frame::text(serde_json::to_string(&presence_msg)?))
+}
+
+// Whether we can talk to a client speaking `protocol_version`, or why not
+fn check_hello(protocol_version: u32) -> Result<(), &'static str> {
+    if protocol_version < MIN_PROTOCOL_VERSION {
+        Err("client_too_old")
+    } else if protocol_version > PROTOCOL_VERSION {
+        Err("server_too_old")
+    } else {
+        Ok(())
     }
+}
+
+// Reply to a join request with "ok" or the reason it was refused
+fn send_join_result(
+    tx: &broadcast::Sender<(String, Frame)>,
+    connection_id: &str,
+    session_id: &str,
+    result: Result<(), JoinError>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let result_msg = ServerMessage {
+        event_type: "join_session_result".to_string(),
+        sender_id: connection_id.to_string(),
+        data: serde_json::json!({
+            "session_id": session_id,
+            "result": match result {
+                Ok(()) => "ok",
+                Err(e) => e.reason(),
+            },
+        }),
+    };
+
+    let frame = Frame::text(serde_json::to_string(&result_msg)?);
+    tx.send((connection_id.to_string(), frame))?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(max_players: Option<usize>) -> SessionSettings {
+        SessionSettings {
+            max_players,
+            password: None,
+            display_name: None,
+            game_version: None,
+            unlisted: false,
+        }
+    }
+
+    #[test]
+    fn joining_another_session_leaves_the_first() {
+        let mut state = ServerState::new();
+        state.register_connection("a");
+        state.register_connection("b");
+
+        state.join_session("a", "one", &settings(Some(2))).unwrap();
+        state.join_session("b", "one", &settings(None)).unwrap();
+
+        let (members, left) = state.join_session("a", "two", &settings(None)).unwrap();
+        assert_eq!(members, vec!["a".to_string()]);
+        assert_eq!(left.as_deref(), Some("one"));
+
+        // The slot and the host role in the first session are freed
+        let one = &state.sessions["one"];
+        assert_eq!(one.members, vec!["b".to_string()]);
+        assert_eq!(one.host_id, "b");
+    }
+
+    #[test]
+    fn refused_join_keeps_the_current_session() {
+        let mut state = ServerState::new();
+        for id in ["a", "b", "c"] {
+            state.register_connection(id);
+        }
+
+        state.join_session("a", "one", &settings(None)).unwrap();
+        state.join_session("b", "full", &settings(Some(1))).unwrap();
+
+        assert!(matches!(
+            state.join_session("a", "full", &settings(None)),
+            Err(JoinError::SessionFull)
+        ));
+        assert!(state.is_in_session("a", "one"));
+    }
+
+    #[test]
+    fn banned_member_is_removed_and_refused() {
+        let mut state = ServerState::new();
+        for id in ["a", "b", "c"] {
+            state.register_connection(id);
+        }
+
+        state.join_session("a", "one", &settings(None)).unwrap();
+        state.join_session("b", "one", &settings(None)).unwrap();
+        state.join_session("c", "one", &settings(None)).unwrap();
+
+        // Only the host bans, and never itself
+        assert!(state.ban_member("b", "c").is_err());
+        assert!(state.ban_member("a", "a").is_err());
+
+        assert_eq!(state.ban_member("a", "b"), Ok(("one".to_string(), true)));
+        assert_eq!(state.get_session_members("one"), vec!["a", "c"]);
+        assert!(matches!(
+            state.join_session("b", "one", &settings(None)),
+            Err(JoinError::Banned)
+        ));
+
+        // Banning ahead of time keeps a connection from ever joining
+        state.register_connection("d");
+        assert_eq!(state.ban_member("a", "d"), Ok(("one".to_string(), false)));
+        assert!(matches!(
+            state.join_session("d", "one", &settings(None)),
+            Err(JoinError::Banned)
+        ));
+    }
+
+    #[tokio::test]
+    async fn refused_hello_closes_the_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(ServerState::new()));
+        let (tx, _) = broadcast::channel::<(String, Frame)>(16);
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let resume_token = state.lock().unwrap().register_connection("a");
+            let current_id = Arc::new(Mutex::new("a".to_string()));
+            let _ = handle_connection(
+                stream,
+                "a".to_string(),
+                current_id,
+                resume_token,
+                1 << 16,
+                state,
+                tx,
+            )
+            .await;
+        });
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr))
+            .await
+            .unwrap();
+        let welcome = client.next().await.unwrap().unwrap();
+        assert!(welcome.to_text().unwrap().contains("welcome"));
+
+        let hello = serde_json::json!({
+            "event_type": "hello",
+            "protocol_version": MIN_PROTOCOL_VERSION - 1,
+        });
+        client.send(Message::Text(hello.to_string())).await.unwrap();
+
+        let result = client.next().await.unwrap().unwrap();
+        let result: serde_json::Value = serde_json::from_str(result.to_text().unwrap()).unwrap();
+        assert_eq!(result["event_type"], "hello_result");
+        assert_eq!(result["data"]["accepted"], false);
+        assert_eq!(result["data"]["reason"], "client_too_old");
+
+        // The server hangs up rather than ignoring the client from here on
+        let closed = tokio::time::timeout(Duration::from_secs(5), client.next())
+            .await
+            .unwrap();
+        assert!(matches!(
+            closed,
+            None | Some(Ok(Message::Close(_))) | Some(Err(_))
+        ));
+    }
+
+    #[test]
+    fn member_lists_name_the_host() {
+        let state = Mutex::new(ServerState::new());
+        let (tx, mut rx) = broadcast::channel::<(String, Frame)>(16);
+        {
+            let mut state = state.lock().unwrap();
+            state.register_connection("a");
+            state.register_connection("b");
+            state.join_session("a", "one", &settings(None)).unwrap();
+            state.join_session("b", "one", &settings(None)).unwrap();
+            state.leave_session("a");
+        }
+
+        broadcast_session_members(&state, &tx, "a", "one").unwrap();
+
+        let (target, frame) = rx.try_recv().unwrap();
+        let msg: serde_json::Value = serde_json::from_str(&frame.text).unwrap();
+        assert_eq!(target, "b");
+        assert_eq!(msg["data"]["members"], serde_json::json!(["b"]));
+        assert_eq!(msg["data"]["host_id"], "b");
+    }
+
+    #[tokio::test]
+    async fn joiner_gets_replay_larger_than_the_channel() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(ServerState::new()));
+        // As small as the real channel, so one frame per actor would lag it
+        let (tx, _) = broadcast::channel::<(String, Frame)>(100);
+
+        {
+            let mut state = state.lock().unwrap();
+            state.register_connection("a");
+            state.join_session("a", "one", &settings(None)).unwrap();
+            let session = state.sessions.get_mut("one").unwrap();
+            for i in 0..150 {
+                let msg = RelayMessage::ActorState(protocol::ActorStateMessage {
+                    event_type: "actor_state".to_string(),
+                    sender_id: "a".to_string(),
+                    actor_id: format!("actor{}", i),
+                    schema: "door".to_string(),
+                    data: vec![i as u8],
+                });
+                session.actor_frames.insert(
+                    ("a".to_string(), format!("actor{}", i)),
+                    msg.to_frame().unwrap(),
+                );
+            }
+        }
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let resume_token = state.lock().unwrap().register_connection("b");
+            let current_id = Arc::new(Mutex::new("b".to_string()));
+            let _ = handle_connection(
+                stream,
+                "b".to_string(),
+                current_id,
+                resume_token,
+                1 << 16,
+                state,
+                tx,
+            )
+            .await;
+        });
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr))
+            .await
+            .unwrap();
+        let join = serde_json::json!({ "event_type": "join_session", "session_id": "one" });
+        client.send(Message::Text(join.to_string())).await.unwrap();
+
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), client.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let msg: serde_json::Value = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+            if msg["event_type"] != "batch" {
+                continue;
+            }
+
+            let actors = msg["messages"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|entry| entry["event_type"] == "actor_state")
+                .count();
+            assert_eq!(actors, 150);
+            break;
+        }
+    }
+}
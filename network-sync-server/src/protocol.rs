@@ -0,0 +1,887 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// Codecs advertised to clients in the `welcome` message
+pub const SUPPORTED_CODECS: [&str; 2] = ["json", "binary"];
+pub const CODEC_BINARY: &str = "binary";
+
+/// Optional message kinds we relay, advertised in the `welcome` message
+pub const SUPPORTED_FEATURES: [&str; 4] = ["actor_delta", "batch", "hello", "presence"];
+
+/// Protocol version spoken by this server, and the oldest it still accepts
+/// in a client's `hello`
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+// Actor, message and client IDs must fit the mod's 64 byte string buffers
+const MAX_ID_LEN: usize = 63;
+
+// Most recipients a single registered message can name
+const MAX_TARGETS: usize = 32;
+
+// Largest actor delta, every field changed
+const MAX_ACTOR_DELTA_BYTES: usize = 12 + 26 * 6 + 2;
+
+// Schema names must fit the mod's 32 byte buffers
+const MAX_SCHEMA_NAME_LEN: usize = 31;
+
+// Largest state a sync schema can describe
+const MAX_ACTOR_STATE_BYTES: usize = 1024;
+
+// Most messages a single batch can carry
+const MAX_BATCH_ENTRIES: usize = 64;
+
+// Binary layout: tag u8, then fields in declaration order. Strings are u16
+// length prefixed, byte blobs u32 length prefixed, all integers little endian.
+// Actor syncs end with a u32 frame number, absent from older peers.
+// Registered messages end with an echo flag byte, a u16 counted list of
+// target client IDs and a u32 reliable sequence number, all absent from
+// older peers. Batches are a u16 counted list of complete frames, each u32
+// length prefixed.
+// Must stay in sync with network-sync-runtime/src/messages.rs; both sides
+// are tested against the frames in fixtures/binary.
+const BINARY_TAG_ACTOR_SYNC: u8 = 1;
+const BINARY_TAG_REGISTERED_MESSAGE: u8 = 2;
+const BINARY_TAG_MESSAGE_ACK: u8 = 3;
+const BINARY_TAG_ACTOR_DELTA: u8 = 4;
+const BINARY_TAG_BATCH: u8 = 5;
+const BINARY_TAG_ACTOR_STATE: u8 = 6;
+
+pub type DecodeResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+// Mirrors of the runtime's relayed message types, so we can transcode between
+// JSON and binary clients in the same session
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Vec3f {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Vec3s {
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActorData {
+    pub world_position: Vec3f,
+    pub shape_rotation: Vec3s,
+    pub upper_limb_rot: Vec3s,
+    pub joint_table: [Vec3s; 24],
+    pub current_mask: i8,
+    pub current_shield: i8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActorSyncMessage {
+    pub event_type: String,
+    pub sender_id: String,
+    pub actor_id: String,
+    pub data: ActorData,
+    // Frame number later deltas are based on, 0 from older clients
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub seq: u32,
+}
+
+// Changed fields of an actor since frame `baseline`. Only the sending and
+// receiving clients interpret the payload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActorDeltaMessage {
+    pub event_type: String,
+    pub sender_id: String,
+    pub actor_id: String,
+    pub seq: u32,
+    pub baseline: u32,
+    pub mask: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisteredMessage {
+    pub event_type: String,
+    pub sender_id: String,
+    pub message_id: String,
+    pub data: Vec<u8>,
+    // The sender wants its own message back
+    #[serde(default)]
+    pub echo: bool,
+    // Client IDs to deliver to, instead of the whole session
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<String>,
+    // Reliable sequence number, 0 for unreliable messages
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub seq: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageAckMessage {
+    pub event_type: String,
+    pub sender_id: String,
+    // Sender of the acknowledged message, the only one the ack goes to
+    pub target: String,
+    pub seq: u32,
+}
+
+// Actor fields read through a mod defined schema. Only clients that
+// registered the same schema interpret the payload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActorStateMessage {
+    pub event_type: String,
+    pub sender_id: String,
+    pub actor_id: String,
+    pub schema: String,
+    pub data: Vec<u8>,
+}
+
+// Messages one client sent in the same tick, relayed as a single frame
+#[derive(Debug, Clone)]
+pub struct BatchMessage {
+    pub sender_id: String,
+    pub messages: Vec<RelayMessage>,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+/// Messages the server relays between session members
+#[derive(Debug, Clone)]
+pub enum RelayMessage {
+    ActorSync(ActorSyncMessage),
+    ActorDelta(ActorDeltaMessage),
+    ActorState(ActorStateMessage),
+    Registered(RegisteredMessage),
+    Ack(MessageAckMessage),
+    Batch(BatchMessage),
+}
+
+impl RelayMessage {
+    /// Parse a JSON frame, failing if it isn't a well formed relayed message
+    pub fn from_json(text: &str) -> DecodeResult<Self> {
+        Self::from_value(serde_json::from_str(text)?)
+    }
+
+    fn from_value(mut value: serde_json::Value) -> DecodeResult<Self> {
+        let event_type = value
+            .get("event_type")
+            .and_then(|v| v.as_str())
+            .ok_or("missing event_type")?;
+
+        let message = match event_type {
+            "actor_sync" => RelayMessage::ActorSync(serde_json::from_value(value)?),
+            "actor_delta" => RelayMessage::ActorDelta(serde_json::from_value(value)?),
+            "actor_state" => RelayMessage::ActorState(serde_json::from_value(value)?),
+            "registered_message" => RelayMessage::Registered(serde_json::from_value(value)?),
+            "message_ack" => RelayMessage::Ack(serde_json::from_value(value)?),
+            "batch" => {
+                let sender_id = value
+                    .get("sender_id")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string();
+
+                let serde_json::Value::Array(entries) = value["messages"].take() else {
+                    return Err("batch without a messages list".into());
+                };
+
+                let messages = entries
+                    .into_iter()
+                    .map(Self::from_value)
+                    .collect::<DecodeResult<Vec<_>>>()?;
+
+                RelayMessage::Batch(BatchMessage {
+                    sender_id,
+                    messages,
+                })
+            }
+            event_type => return Err(format!("unknown event type '{}'", event_type).into()),
+        };
+
+        Ok(message)
+    }
+
+    pub fn from_binary(bytes: &[u8]) -> DecodeResult<Self> {
+        let mut reader = BinaryReader { buf: bytes };
+
+        let message = match reader.u8()? {
+            BINARY_TAG_ACTOR_SYNC => RelayMessage::ActorSync(ActorSyncMessage {
+                event_type: "actor_sync".to_string(),
+                sender_id: reader.string()?,
+                actor_id: reader.string()?,
+                data: reader.actor_data()?,
+                seq: reader.trailing_u32()?,
+            }),
+            BINARY_TAG_ACTOR_DELTA => RelayMessage::ActorDelta(ActorDeltaMessage {
+                event_type: "actor_delta".to_string(),
+                sender_id: reader.string()?,
+                actor_id: reader.string()?,
+                seq: reader.u32()?,
+                baseline: reader.u32()?,
+                mask: reader.u32()?,
+                data: reader.bytes()?,
+            }),
+            BINARY_TAG_REGISTERED_MESSAGE => RelayMessage::Registered(RegisteredMessage {
+                event_type: "registered_message".to_string(),
+                sender_id: reader.string()?,
+                message_id: reader.string()?,
+                data: reader.bytes()?,
+                echo: reader.trailing_flag()?,
+                targets: reader.trailing_strings()?,
+                seq: reader.trailing_u32()?,
+            }),
+            BINARY_TAG_MESSAGE_ACK => RelayMessage::Ack(MessageAckMessage {
+                event_type: "message_ack".to_string(),
+                sender_id: reader.string()?,
+                target: reader.string()?,
+                seq: reader.u32()?,
+            }),
+            BINARY_TAG_ACTOR_STATE => RelayMessage::ActorState(ActorStateMessage {
+                event_type: "actor_state".to_string(),
+                sender_id: reader.string()?,
+                actor_id: reader.string()?,
+                schema: reader.string()?,
+                data: reader.bytes()?,
+            }),
+            BINARY_TAG_BATCH => {
+                let sender_id = reader.string()?;
+                let count = reader.u16()?;
+
+                let messages = (0..count)
+                    .map(|_| Self::from_binary(&reader.bytes()?))
+                    .collect::<DecodeResult<Vec<_>>>()?;
+
+                RelayMessage::Batch(BatchMessage {
+                    sender_id,
+                    messages,
+                })
+            }
+            tag => return Err(format!("unknown binary message tag {}", tag).into()),
+        };
+
+        Ok(message)
+    }
+
+    /// Stamp the message with the connection that actually sent it
+    pub fn set_sender(&mut self, sender_id: &str) {
+        match self {
+            RelayMessage::ActorSync(msg) => msg.sender_id = sender_id.to_string(),
+            RelayMessage::ActorDelta(msg) => msg.sender_id = sender_id.to_string(),
+            RelayMessage::ActorState(msg) => msg.sender_id = sender_id.to_string(),
+            RelayMessage::Registered(msg) => msg.sender_id = sender_id.to_string(),
+            RelayMessage::Ack(msg) => msg.sender_id = sender_id.to_string(),
+            RelayMessage::Batch(batch) => {
+                batch.sender_id = sender_id.to_string();
+                for msg in &mut batch.messages {
+                    msg.set_sender(sender_id);
+                }
+            }
+        }
+    }
+
+    /// The messages this one is made of: a batch's entries, or itself
+    pub fn entries(&self) -> &[RelayMessage] {
+        match self {
+            RelayMessage::Batch(batch) => &batch.messages,
+            message => std::slice::from_ref(message),
+        }
+    }
+
+    /// Owner and ID of the actor whose complete state this message carries.
+    /// Deltas only make sense to peers that saw the frames before them.
+    pub fn actor_key(&self) -> Option<(String, String)> {
+        match self {
+            RelayMessage::ActorSync(msg) => Some((msg.sender_id.clone(), msg.actor_id.clone())),
+            RelayMessage::ActorState(msg) => Some((msg.sender_id.clone(), msg.actor_id.clone())),
+            _ => None,
+        }
+    }
+
+    /// Whether this carries actor updates, which only go to members that can
+    /// see the sender's scene
+    pub fn has_actor_traffic(&self) -> bool {
+        self.entries().iter().any(|msg| {
+            matches!(
+                msg,
+                RelayMessage::ActorSync(_)
+                    | RelayMessage::ActorDelta(_)
+                    | RelayMessage::ActorState(_)
+            )
+        })
+    }
+
+    /// This message without its actor updates, or None if nothing is left
+    pub fn without_actor_traffic(&self) -> Option<RelayMessage> {
+        match self {
+            RelayMessage::Batch(batch) => {
+                let messages: Vec<_> = batch
+                    .messages
+                    .iter()
+                    .filter(|msg| !msg.has_actor_traffic())
+                    .cloned()
+                    .collect();
+
+                (!messages.is_empty()).then(|| {
+                    RelayMessage::Batch(BatchMessage {
+                        sender_id: batch.sender_id.clone(),
+                        messages,
+                    })
+                })
+            }
+            msg if msg.has_actor_traffic() => None,
+            msg => Some(msg.clone()),
+        }
+    }
+
+    /// Whether the sender should get its own message back
+    pub fn wants_echo(&self) -> bool {
+        matches!(self, RelayMessage::Registered(msg) if msg.echo)
+    }
+
+    /// Remove and return the recipients the sender picked, if any
+    pub fn take_targets(&mut self) -> Vec<String> {
+        match self {
+            RelayMessage::Registered(msg) => std::mem::take(&mut msg.targets),
+            RelayMessage::Ack(msg) => vec![msg.target.clone()],
+            RelayMessage::ActorSync(_)
+            | RelayMessage::ActorDelta(_)
+            | RelayMessage::ActorState(_)
+            | RelayMessage::Batch(_) => Vec::new(),
+        }
+    }
+
+    /// Check the fields clients control against what the mod can handle
+    pub fn validate(&self, max_payload_bytes: usize) -> DecodeResult<()> {
+        let (kind, id) = match self {
+            RelayMessage::Batch(batch) => return Self::validate_batch(batch, max_payload_bytes),
+            RelayMessage::ActorSync(msg) => ("actor", &msg.actor_id),
+            RelayMessage::ActorDelta(msg) => ("actor", &msg.actor_id),
+            RelayMessage::ActorState(msg) => ("actor", &msg.actor_id),
+            RelayMessage::Registered(msg) => ("message", &msg.message_id),
+            RelayMessage::Ack(msg) => ("target", &msg.target),
+        };
+
+        if id.is_empty() || id.len() > MAX_ID_LEN {
+            return Err(format!("{} id must be 1 to {} bytes", kind, MAX_ID_LEN).into());
+        }
+
+        if let RelayMessage::ActorDelta(msg) = self {
+            if msg.data.len() > MAX_ACTOR_DELTA_BYTES {
+                return Err(format!(
+                    "actor delta of {} bytes exceeds {}",
+                    msg.data.len(),
+                    MAX_ACTOR_DELTA_BYTES
+                )
+                .into());
+            }
+        }
+
+        if let RelayMessage::ActorState(msg) = self {
+            if msg.schema.is_empty() || msg.schema.len() > MAX_SCHEMA_NAME_LEN {
+                return Err(
+                    format!("schema name must be 1 to {} bytes", MAX_SCHEMA_NAME_LEN).into(),
+                );
+            }
+
+            if msg.data.len() > MAX_ACTOR_STATE_BYTES {
+                return Err(format!(
+                    "actor state of {} bytes exceeds {}",
+                    msg.data.len(),
+                    MAX_ACTOR_STATE_BYTES
+                )
+                .into());
+            }
+        }
+
+        if let RelayMessage::Registered(msg) = self {
+            if msg.data.len() > max_payload_bytes {
+                return Err(format!(
+                    "message payload of {} bytes exceeds {}",
+                    msg.data.len(),
+                    max_payload_bytes
+                )
+                .into());
+            }
+
+            if msg.targets.len() > MAX_TARGETS {
+                return Err(format!(
+                    "message names {} targets, more than {}",
+                    msg.targets.len(),
+                    MAX_TARGETS
+                )
+                .into());
+            }
+
+            if msg
+                .targets
+                .iter()
+                .any(|target| target.is_empty() || target.len() > MAX_ID_LEN)
+            {
+                return Err(format!("target ids must be 1 to {} bytes", MAX_ID_LEN).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    // A batch goes to the whole session as a unit, so it may only carry
+    // messages that would have gone to the whole session anyway
+    fn validate_batch(batch: &BatchMessage, max_payload_bytes: usize) -> DecodeResult<()> {
+        if batch.messages.len() > MAX_BATCH_ENTRIES {
+            return Err(format!(
+                "batch of {} messages exceeds {}",
+                batch.messages.len(),
+                MAX_BATCH_ENTRIES
+            )
+            .into());
+        }
+
+        for msg in &batch.messages {
+            let broadcast = match msg {
+                RelayMessage::ActorSync(_)
+                | RelayMessage::ActorDelta(_)
+                | RelayMessage::ActorState(_) => true,
+                RelayMessage::Registered(msg) => {
+                    !msg.echo && msg.targets.is_empty() && msg.seq == 0
+                }
+                RelayMessage::Ack(_) | RelayMessage::Batch(_) => false,
+            };
+
+            if !broadcast {
+                return Err(
+                    "batches can't carry targeted, echoed, reliable or nested messages".into(),
+                );
+            }
+
+            msg.validate(max_payload_bytes)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn to_frame(&self) -> serde_json::Result<Frame> {
+        Ok(Frame {
+            text: self.to_json()?,
+            binary: Some(self.to_binary()),
+        })
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(&self.to_value()?)
+    }
+
+    fn to_value(&self) -> serde_json::Result<serde_json::Value> {
+        match self {
+            RelayMessage::ActorSync(msg) => serde_json::to_value(msg),
+            RelayMessage::ActorDelta(msg) => serde_json::to_value(msg),
+            RelayMessage::ActorState(msg) => serde_json::to_value(msg),
+            RelayMessage::Registered(msg) => serde_json::to_value(msg),
+            RelayMessage::Ack(msg) => serde_json::to_value(msg),
+            RelayMessage::Batch(batch) => Ok(serde_json::json!({
+                "event_type": "batch",
+                "sender_id": batch.sender_id,
+                "messages": batch
+                    .messages
+                    .iter()
+                    .map(Self::to_value)
+                    .collect::<serde_json::Result<Vec<_>>>()?,
+            })),
+        }
+    }
+
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+
+        match self {
+            RelayMessage::ActorSync(msg) => {
+                buf.push(BINARY_TAG_ACTOR_SYNC);
+                write_string(&mut buf, &msg.sender_id);
+                write_string(&mut buf, &msg.actor_id);
+                write_actor_data(&mut buf, &msg.data);
+                buf.extend_from_slice(&msg.seq.to_le_bytes());
+            }
+            RelayMessage::ActorDelta(msg) => {
+                buf.push(BINARY_TAG_ACTOR_DELTA);
+                write_string(&mut buf, &msg.sender_id);
+                write_string(&mut buf, &msg.actor_id);
+                buf.extend_from_slice(&msg.seq.to_le_bytes());
+                buf.extend_from_slice(&msg.baseline.to_le_bytes());
+                buf.extend_from_slice(&msg.mask.to_le_bytes());
+                buf.extend_from_slice(&(msg.data.len() as u32).to_le_bytes());
+                buf.extend_from_slice(&msg.data);
+            }
+            RelayMessage::ActorState(msg) => {
+                buf.push(BINARY_TAG_ACTOR_STATE);
+                write_string(&mut buf, &msg.sender_id);
+                write_string(&mut buf, &msg.actor_id);
+                write_string(&mut buf, &msg.schema);
+                buf.extend_from_slice(&(msg.data.len() as u32).to_le_bytes());
+                buf.extend_from_slice(&msg.data);
+            }
+            RelayMessage::Registered(msg) => {
+                buf.push(BINARY_TAG_REGISTERED_MESSAGE);
+                write_string(&mut buf, &msg.sender_id);
+                write_string(&mut buf, &msg.message_id);
+                buf.extend_from_slice(&(msg.data.len() as u32).to_le_bytes());
+                buf.extend_from_slice(&msg.data);
+                buf.push(msg.echo as u8);
+                buf.extend_from_slice(&(msg.targets.len() as u16).to_le_bytes());
+                for target in &msg.targets {
+                    write_string(&mut buf, target);
+                }
+                buf.extend_from_slice(&msg.seq.to_le_bytes());
+            }
+            RelayMessage::Ack(msg) => {
+                buf.push(BINARY_TAG_MESSAGE_ACK);
+                write_string(&mut buf, &msg.sender_id);
+                write_string(&mut buf, &msg.target);
+                buf.extend_from_slice(&msg.seq.to_le_bytes());
+            }
+            RelayMessage::Batch(batch) => {
+                buf.push(BINARY_TAG_BATCH);
+                write_string(&mut buf, &batch.sender_id);
+                buf.extend_from_slice(&(batch.messages.len() as u16).to_le_bytes());
+                for msg in &batch.messages {
+                    let frame = msg.to_binary();
+                    buf.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+                    buf.extend_from_slice(&frame);
+                }
+            }
+        }
+
+        buf
+    }
+}
+
+/// An outbound frame, with a binary encoding when the payload has one
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub text: String,
+    pub binary: Option<Vec<u8>>,
+}
+
+impl Frame {
+    pub fn text(text: String) -> Self {
+        Self { text, binary: None }
+    }
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn write_vec3s(buf: &mut Vec<u8>, value: &Vec3s) {
+    for component in [value.x, value.y, value.z] {
+        buf.extend_from_slice(&component.to_le_bytes());
+    }
+}
+
+fn write_actor_data(buf: &mut Vec<u8>, data: &ActorData) {
+    let position = &data.world_position;
+    for component in [position.x, position.y, position.z] {
+        buf.extend_from_slice(&component.to_le_bytes());
+    }
+    write_vec3s(buf, &data.shape_rotation);
+    write_vec3s(buf, &data.upper_limb_rot);
+    for joint in &data.joint_table {
+        write_vec3s(buf, joint);
+    }
+    buf.push(data.current_mask as u8);
+    buf.push(data.current_shield as u8);
+}
+
+struct BinaryReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> BinaryReader<'a> {
+    fn take(&mut self, len: usize) -> DecodeResult<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(format!(
+                "binary message truncated: wanted {} bytes, {} left",
+                len,
+                self.buf.len()
+            )
+            .into());
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> DecodeResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> DecodeResult<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> DecodeResult<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn i16(&mut self) -> DecodeResult<i16> {
+        Ok(i16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn f32(&mut self) -> DecodeResult<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn string(&mut self) -> DecodeResult<String> {
+        let len = u16::from_le_bytes(self.take(2)?.try_into()?) as usize;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+
+    fn bytes(&mut self) -> DecodeResult<Vec<u8>> {
+        let len = u32::from_le_bytes(self.take(4)?.try_into()?) as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    // A flag byte that may be missing from the end of the frame
+    fn trailing_flag(&mut self) -> DecodeResult<bool> {
+        if self.buf.is_empty() {
+            return Ok(false);
+        }
+        Ok(self.u8()? != 0)
+    }
+
+    // A u32 that may be missing from the end of the frame
+    fn trailing_u32(&mut self) -> DecodeResult<u32> {
+        if self.buf.is_empty() {
+            return Ok(0);
+        }
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    // A u16 counted string list that may be missing from the end of the frame
+    fn trailing_strings(&mut self) -> DecodeResult<Vec<String>> {
+        if self.buf.is_empty() {
+            return Ok(Vec::new());
+        }
+        let count = u16::from_le_bytes(self.take(2)?.try_into()?);
+        (0..count).map(|_| self.string()).collect()
+    }
+
+    fn vec3s(&mut self) -> DecodeResult<Vec3s> {
+        Ok(Vec3s {
+            x: self.i16()?,
+            y: self.i16()?,
+            z: self.i16()?,
+        })
+    }
+
+    fn actor_data(&mut self) -> DecodeResult<ActorData> {
+        let world_position = Vec3f {
+            x: self.f32()?,
+            y: self.f32()?,
+            z: self.f32()?,
+        };
+        let shape_rotation = self.vec3s()?;
+        let upper_limb_rot = self.vec3s()?;
+
+        let joints = (0..24)
+            .map(|_| self.vec3s())
+            .collect::<DecodeResult<Vec<_>>>()?;
+        let joint_table = joints
+            .try_into()
+            .map_err(|_| "invalid joint table length")?;
+
+        Ok(ActorData {
+            world_position,
+            shape_rotation,
+            upper_limb_rot,
+            joint_table,
+            current_mask: self.u8()? as i8,
+            current_shield: self.u8()? as i8,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Binary frames both the server and the runtime must agree on, kept as
+    // hex in fixtures/binary and checked against the runtime's codec as well
+    macro_rules! fixture {
+        ($name:literal) => {
+            parse_hex(include_str!(concat!(
+                "../../fixtures/binary/",
+                $name,
+                ".hex"
+            )))
+        };
+    }
+
+    fn parse_hex(text: &str) -> Vec<u8> {
+        let digits: Vec<u8> = text.bytes().filter(|b| b.is_ascii_hexdigit()).collect();
+        digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect()
+    }
+
+    fn actor_data() -> ActorData {
+        let joint = |i: i16| Vec3s {
+            x: i,
+            y: -i,
+            z: i16::MAX - i,
+        };
+
+        ActorData {
+            world_position: Vec3f {
+                x: 1.5,
+                y: -2.0,
+                z: 1024.25,
+            },
+            shape_rotation: Vec3s { x: 1, y: 2, z: 3 },
+            upper_limb_rot: Vec3s {
+                x: -4,
+                y: i16::MIN,
+                z: 6,
+            },
+            joint_table: std::array::from_fn(|i| joint(i as i16)),
+            current_mask: 3,
+            current_shield: -1,
+        }
+    }
+
+    fn actor_sync() -> RelayMessage {
+        RelayMessage::ActorSync(ActorSyncMessage {
+            event_type: "actor_sync".to_string(),
+            sender_id: "p1".to_string(),
+            actor_id: "player".to_string(),
+            data: actor_data(),
+            seq: 7,
+        })
+    }
+
+    fn actor_delta() -> RelayMessage {
+        RelayMessage::ActorDelta(ActorDeltaMessage {
+            event_type: "actor_delta".to_string(),
+            sender_id: "p1".to_string(),
+            actor_id: "player".to_string(),
+            seq: 8,
+            baseline: 7,
+            mask: 0b101,
+            data: vec![1, 2, 3, 4],
+        })
+    }
+
+    fn actor_state() -> RelayMessage {
+        RelayMessage::ActorState(ActorStateMessage {
+            event_type: "actor_state".to_string(),
+            sender_id: "p1".to_string(),
+            actor_id: "door".to_string(),
+            schema: "door_state".to_string(),
+            data: vec![9, 8, 7],
+        })
+    }
+
+    fn registered_message() -> RelayMessage {
+        RelayMessage::Registered(RegisteredMessage {
+            event_type: "registered_message".to_string(),
+            sender_id: "p1".to_string(),
+            message_id: "chat".to_string(),
+            data: b"hi".to_vec(),
+            echo: true,
+            targets: vec!["p2".to_string(), "p3".to_string()],
+            seq: 5,
+        })
+    }
+
+    fn message_ack() -> RelayMessage {
+        RelayMessage::Ack(MessageAckMessage {
+            event_type: "message_ack".to_string(),
+            sender_id: "p2".to_string(),
+            target: "p1".to_string(),
+            seq: 5,
+        })
+    }
+
+    fn batch() -> RelayMessage {
+        RelayMessage::Batch(BatchMessage {
+            sender_id: "p1".to_string(),
+            messages: vec![
+                actor_delta(),
+                RelayMessage::Registered(RegisteredMessage {
+                    event_type: "registered_message".to_string(),
+                    sender_id: "p1".to_string(),
+                    message_id: "chat".to_string(),
+                    data: b"hi".to_vec(),
+                    echo: false,
+                    targets: Vec::new(),
+                    seq: 0,
+                }),
+            ],
+        })
+    }
+
+    // Encoding must produce the fixture, and decoding it must give back a
+    // message that encodes the same
+    fn assert_round_trip(msg: RelayMessage, fixture: Vec<u8>) {
+        assert_eq!(msg.to_binary(), fixture);
+        assert_eq!(
+            RelayMessage::from_binary(&fixture).unwrap().to_binary(),
+            fixture
+        );
+    }
+
+    #[test]
+    fn binary_frames_match_fixtures() {
+        assert_round_trip(actor_sync(), fixture!("actor_sync"));
+        assert_round_trip(actor_delta(), fixture!("actor_delta"));
+        assert_round_trip(actor_state(), fixture!("actor_state"));
+        assert_round_trip(registered_message(), fixture!("registered_message"));
+        assert_round_trip(message_ack(), fixture!("message_ack"));
+        assert_round_trip(batch(), fixture!("batch"));
+    }
+
+    #[test]
+    fn binary_decode_keeps_fields() {
+        let RelayMessage::ActorSync(msg) =
+            RelayMessage::from_binary(&fixture!("actor_sync")).unwrap()
+        else {
+            panic!("expected an actor sync");
+        };
+        assert_eq!(msg.actor_id, "player");
+        assert_eq!(msg.seq, 7);
+        assert_eq!(msg.data.world_position.z, 1024.25);
+        assert_eq!(msg.data.upper_limb_rot.y, i16::MIN);
+        assert_eq!(msg.data.joint_table[23].z, i16::MAX - 23);
+        assert_eq!(msg.data.current_shield, -1);
+
+        let RelayMessage::Batch(batch) = RelayMessage::from_binary(&fixture!("batch")).unwrap()
+        else {
+            panic!("expected a batch");
+        };
+        assert_eq!(batch.messages.len(), 2);
+        assert!(matches!(batch.messages[0], RelayMessage::ActorDelta(_)));
+    }
+
+    #[test]
+    fn binary_decode_defaults_trailing_fields() {
+        let RelayMessage::Registered(msg) =
+            RelayMessage::from_binary(&fixture!("registered_message_v1")).unwrap()
+        else {
+            panic!("expected a registered message");
+        };
+        assert_eq!(msg.data, b"hi");
+        assert!(!msg.echo);
+        assert!(msg.targets.is_empty());
+        assert_eq!(msg.seq, 0);
+    }
+
+    #[test]
+    fn binary_decode_rejects_truncated_frames() {
+        let fixture = fixture!("actor_delta");
+        assert!(RelayMessage::from_binary(&fixture[..fixture.len() - 1]).is_err());
+        assert!(RelayMessage::from_binary(&[0xFF]).is_err());
+    }
+}
@@ -19,10 +19,16 @@ static u8 gSyncedActorCategories[MAX_ACTOR_CATEGORIES] = {0};  // Bitset for cat
 typedef struct {
     // UUID string for this actor
     char actor_id[64];
+    // Client ID of the remote actor's owner, empty to match the actor ID against any owner
+    char owner_id[64];
     // Flag indicating if actor is being synced
     u8 is_synced;
     // Flag indicating whether we are in charge of pushing its data to the server
     u8 is_owned_locally;
+    // Sync schema describing which fields to sync, empty for the built in player data
+    char schema[32];
+    // NS_SEND_* result of the last update we sent for this actor
+    u8 last_send_status;
 } NetworkExtendedActorData;
 
 static NetworkExtendedActorData* GetActorNetworkData(Actor* actor) {
@@ -83,6 +89,10 @@ const char* ActorSyncGetNetworkId(Actor *actor) {
 }
 
 void ActorSyncRegister(Actor* actor, const char* playerId, int isOwnedLocally) {
+    ActorSyncRegisterWithSchema(actor, playerId, isOwnedLocally, NULL);
+}
+
+void ActorSyncRegisterWithSchema(Actor* actor, const char* playerId, int isOwnedLocally, const char* schema) {
     if (actor == NULL) {
         recomp_printf("Cannot sync NULL actor\n");
         return;
@@ -100,6 +110,17 @@ void ActorSyncRegister(Actor* actor, const char* playerId, int isOwnedLocally) {
 
     netData->is_synced = 1;
     netData->is_owned_locally = isOwnedLocally;
+    netData->owner_id[0] = '\0';
+    netData->last_send_status = NS_SEND_QUEUED;
+
+    if (schema != NULL && strlen(schema) < sizeof(netData->schema)) {
+        strcpy(netData->schema, schema);
+    } else {
+        if (schema != NULL) {
+            recomp_printf("Schema name too long, syncing actor %u as player data\n", actor->id);
+        }
+        netData->schema[0] = '\0';
+    }
 
     if (actor->category < MAX_ACTOR_CATEGORIES) {
         gSyncedActorCategories[actor->category] = 1;
@@ -126,6 +147,12 @@ void ActorSyncUpdate(PlayState* play, Actor* actor) {
         return;
     }
 
+    // The runtime reads the schema's fields straight from the actor
+    if (netData->schema[0] != '\0') {
+        netData->last_send_status = NetworkSyncEmitActorState(netData->actor_id, netData->schema, actor);
+        return;
+    }
+
     ActorSyncData* syncData = recomp_alloc(sizeof(ActorSyncData) + sizeof(Vec3s) * 23);
     Math_Vec3s_Copy(&syncData->shapeRotation, &actor->shape.rot);
     Math_Vec3f_Copy(&syncData->worldPosition, &actor->world.pos);
@@ -142,14 +169,42 @@ void ActorSyncUpdate(PlayState* play, Actor* actor) {
         Math_Vec3s_Copy(&syncData->upperLimbRot, &player->upperLimbRot);
     }
 
-    NetworkSyncEmitActorData(syncData);
+    // A full queue drops this frame only; the next update carries newer data anyway
+    netData->last_send_status = NetworkSyncEmitActorData(netData->actor_id, syncData);
     recomp_free(syncData);
 }
 
+void ActorSyncSetOwner(Actor* actor, const char* ownerId) {
+    NetworkExtendedActorData* netData = GetActorNetworkData(actor);
+
+    if (netData == NULL || !netData->is_synced) {
+        recomp_printf("Cannot set the owner of an actor that isn't synced\n");
+        return;
+    }
+
+    if (ownerId == NULL || strlen(ownerId) >= sizeof(netData->owner_id)) {
+        netData->owner_id[0] = '\0';
+        return;
+    }
+
+    strcpy(netData->owner_id, ownerId);
+}
+
+u8 ActorSyncGetSendStatus(Actor* actor) {
+    NetworkExtendedActorData* netData = GetActorNetworkData(actor);
+
+    if (netData == NULL || !netData->is_synced || !netData->is_owned_locally) {
+        return NS_SEND_FAILED;
+    }
+
+    return netData->last_send_status;
+}
+
 void ActorSyncProcessRemoteData(PlayState* play) {
     ActorSyncData remote_data;
+    char owners_buffer[MAX_SYNCED_ACTORS * 64];
     char ids_buffer[MAX_SYNCED_ACTORS * 64];
-    u32 player_count = NetworkSyncGetRemoteActorIDs(MAX_SYNCED_ACTORS, ids_buffer, 64);
+    u32 player_count = NetworkSyncGetRemoteActorIDs(MAX_SYNCED_ACTORS, owners_buffer, ids_buffer, 64);
 
     for (u32 i = 0; i < MAX_ACTOR_CATEGORIES; i++) {
         if (gSyncedActorCategories[i] == 0) {
@@ -168,28 +223,44 @@ void ActorSyncProcessRemoteData(PlayState* play) {
                     continue;
                 }
 
+                // Schema synced actors get their fields written straight back
+                if (net_data->schema[0] != '\0') {
+                    NetworkSyncGetRemoteActorState(net_data->owner_id, net_data->actor_id, net_data->schema, actor);
+                    actor = next_actor;
+                    continue;
+                }
+
                 for (u32 j = 0; j < player_count; j++) {
+                    const char* owner_id = &owners_buffer[j * 64];
                     const char* actor_id = &ids_buffer[j * 64];
 
-                    if (strcmp(net_data->actor_id, actor_id) == 0) {
-                        if (NetworkSyncGetRemoteActorData(actor_id, &remote_data)) {
-                            Math_Vec3s_Copy(&actor->shape.rot, &remote_data.shapeRotation);
-                            Math_Vec3f_Copy(&actor->world.pos, &remote_data.worldPosition);
+                    if (strcmp(net_data->actor_id, actor_id) != 0) {
+                        continue;
+                    }
+
+                    // Actors without an owner take the runtime's pick among owners
+                    if (net_data->owner_id[0] != '\0' && strcmp(net_data->owner_id, owner_id) != 0) {
+                        continue;
+                    }
 
-                            if (actor->category == ACTORCAT_PLAYER) {
-                                Player* player = (Player*)actor;
-                                player->currentMask = remote_data.currentMask;
-                                player->currentShield = remote_data.currentShield;
+                    // Smoothed between network updates; see NS_SetInterpolationDelay
+                    if (NetworkSyncGetRemoteActorDataInterpolated(net_data->owner_id, actor_id, &remote_data)) {
+                        Math_Vec3s_Copy(&actor->shape.rot, &remote_data.shapeRotation);
+                        Math_Vec3f_Copy(&actor->world.pos, &remote_data.worldPosition);
 
-                                for (int k = 0; k < 24; k++) {
-                                    Math_Vec3s_Copy(&player->skelAnime.jointTable[k], &remote_data.jointTable[k]);
-                                }
+                        if (actor->category == ACTORCAT_PLAYER) {
+                            Player* player = (Player*)actor;
+                            player->currentMask = remote_data.currentMask;
+                            player->currentShield = remote_data.currentShield;
 
-                                Math_Vec3s_Copy(&player->upperLimbRot, &remote_data.upperLimbRot);
+                            for (int k = 0; k < 24; k++) {
+                                Math_Vec3s_Copy(&player->skelAnime.jointTable[k], &remote_data.jointTable[k]);
                             }
 
-                            break;
+                            Math_Vec3s_Copy(&player->upperLimbRot, &remote_data.upperLimbRot);
                         }
+
+                        break;
                     }
                 }
             }
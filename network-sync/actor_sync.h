@@ -8,6 +8,9 @@
 void ActorSyncInit();
 const char* ActorSyncGetNetworkId(Actor *actor);
 void ActorSyncRegister(Actor* actor, const char* playerId, int isOwnedLocally);
+void ActorSyncRegisterWithSchema(Actor* actor, const char* playerId, int isOwnedLocally, const char* schema);
+void ActorSyncSetOwner(Actor* actor, const char* ownerId);
+u8 ActorSyncGetSendStatus(Actor* actor);
 
 // MARK: - Internal API (used by callbacks)
 void ActorSyncUpdate(PlayState* play, Actor* actor);
@@ -4,6 +4,10 @@
 #include "global.h"
 #include "actor_sync.h"
 #include "message_system.h"
+#include "flag_sync.h"
+#include "inventory_sync.h"
+#include "presence.h"
+#include "time_sync.h"
 
 // MARK: - Game Event Callbacks
 
@@ -15,9 +19,21 @@ void on_actor_update(PlayState* play, Actor* actor) {
 
 RECOMP_CALLBACK("*", recomp_on_play_main)
 void on_play_main(PlayState* play) {
+    // Tell the session where we are
+    PresenceUpdate(play);
+
     // Process remote actor data
     ActorSyncProcessRemoteData(play);
 
     // Process pending messages
     MessageSystemProcessPending();
+
+    // Exchange shared flag changes
+    FlagSyncUpdate();
+
+    // Exchange inventory changes
+    InventorySyncUpdate();
+
+    // Follow the host's clock and resolve time transitions
+    TimeSyncUpdate();
 }
@@ -3,6 +3,8 @@
 #include "network_core.h"
 #include "actor_sync.h"
 #include "message_system.h"
+#include "flag_sync.h"
+#include "presence.h"
 
 // MARK: - Core Network API
 
@@ -15,14 +17,82 @@ RECOMP_EXPORT u8 NS_Connect(const char* host) {
     return NetworkSyncConnect(host);
 }
 
+RECOMP_EXPORT u8 NS_GetConnectRejectReason(char* buffer, u32 bufferSize) {
+    return NetworkSyncGetConnectRejectReason(buffer, bufferSize);
+}
+
+RECOMP_EXPORT u8 NS_SetReconnectPolicy(u32 maxAttempts, u32 initialDelayMs, u32 maxDelayMs) {
+    return NetworkSyncSetReconnectPolicy(maxAttempts, initialDelayMs, maxDelayMs);
+}
+
+RECOMP_EXPORT u32 NS_GetConnectionState() {
+    return NetworkSyncGetConnectionState();
+}
+
+RECOMP_EXPORT u32 NS_GetDroppedMessageCount() {
+    return NetworkSyncGetDroppedMessageCount();
+}
+
+RECOMP_EXPORT u32 NS_PollEvent(char* idBuffer, u32 idBufferSize) {
+    return NetworkSyncPollEvent(idBuffer, idBufferSize);
+}
+
 RECOMP_EXPORT u8 NS_JoinSession(const char* session) {
-    return NetworkSyncJoinSession(session);
+    return NetworkSyncJoinSession(session, "", 0, 0);
+}
+
+RECOMP_EXPORT u8 NS_JoinSessionAndWait(const char* session, u32 timeoutMs) {
+    return NetworkSyncJoinSession(session, "", 0, timeoutMs);
+}
+
+RECOMP_EXPORT u8 NS_JoinSessionWithOptions(const char* session, const char* password, u32 maxPlayers, u32 timeoutMs) {
+    return NetworkSyncJoinSession(session, password, maxPlayers, timeoutMs);
 }
 
 RECOMP_EXPORT u8 NS_LeaveSession() {
     return NetworkSyncLeaveSession();
 }
 
+RECOMP_EXPORT u8 NS_BanMember(const char* memberId) {
+    return NetworkSyncBanMember(memberId);
+}
+
+RECOMP_EXPORT u8 NS_SetSessionDetails(const char* displayName, const char* gameVersion, u8 unlisted) {
+    return NetworkSyncSetSessionDetails(displayName, gameVersion, unlisted);
+}
+
+RECOMP_EXPORT u8 NS_RequestSessionList() {
+    return NetworkSyncRequestSessionList();
+}
+
+RECOMP_EXPORT u32 NS_ListSessions(u32 maxSessions, char* idsBuffer, u32 idBufferSize) {
+    return NetworkSyncListSessions(maxSessions, idsBuffer, idBufferSize);
+}
+
+RECOMP_EXPORT u8 NS_GetSessionDisplayName(const char* session, char* buffer, u32 bufferSize) {
+    return NetworkSyncGetSessionDisplayName(session, buffer, bufferSize);
+}
+
+RECOMP_EXPORT u8 NS_GetSessionGameVersion(const char* session, char* buffer, u32 bufferSize) {
+    return NetworkSyncGetSessionGameVersion(session, buffer, bufferSize);
+}
+
+RECOMP_EXPORT u32 NS_GetSessionMemberCount(const char* session) {
+    return NetworkSyncGetSessionMemberCount(session);
+}
+
+RECOMP_EXPORT u32 NS_GetSessionCapacity(const char* session) {
+    return NetworkSyncGetSessionCapacity(session);
+}
+
+RECOMP_EXPORT u8 NS_SetSessionState(const char* key, void* data, u32 size) {
+    return NetworkSyncSetSessionState(key, size, data);
+}
+
+RECOMP_EXPORT u32 NS_GetSessionState(const char* key, void* buffer, u32 bufferSize) {
+    return NetworkSyncGetSessionState(key, buffer, bufferSize);
+}
+
 // MARK: - Actor Sync API
 
 RECOMP_EXPORT const char* NS_GetActorNetworkId(Actor *actor) {
@@ -34,11 +104,139 @@ RECOMP_EXPORT void NS_SyncActor(Actor* actor, const char* playerId, int isOwnedL
 }
 
 RECOMP_EXPORT u32 NS_GetRemoteActorIDs(u32 maxPlayers, char* idsBuffer, u32 idBufferSize) {
-    return NetworkSyncGetRemoteActorIDs(maxPlayers, idsBuffer, idBufferSize);
+    return NetworkSyncGetRemoteActorIDs(maxPlayers, NULL, idsBuffer, idBufferSize);
+}
+
+RECOMP_EXPORT u32 NS_GetRemoteActors(u32 maxActors, char* ownerIdsBuffer, char* idsBuffer, u32 idBufferSize) {
+    return NetworkSyncGetRemoteActorIDs(maxActors, ownerIdsBuffer, idsBuffer, idBufferSize);
+}
+
+RECOMP_EXPORT void NS_SetActorOwner(Actor* actor, const char* ownerID) {
+    ActorSyncSetOwner(actor, ownerID);
+}
+
+RECOMP_EXPORT void NS_SyncActorWithSchema(Actor* actor, const char* playerId, int isOwnedLocally, const char* schema) {
+    ActorSyncRegisterWithSchema(actor, playerId, isOwnedLocally, schema);
+}
+
+RECOMP_EXPORT u8 NS_GetActorSendStatus(Actor* actor) {
+    return ActorSyncGetSendStatus(actor);
+}
+
+RECOMP_EXPORT u8 NS_RegisterSyncSchema(const char* schema) {
+    return NetworkSyncRegisterSchema(schema);
+}
+
+RECOMP_EXPORT u8 NS_AddSchemaField(const char* schema, u32 offset, u32 fieldType, u32 count) {
+    return NetworkSyncAddSchemaField(schema, offset, fieldType, count);
+}
+
+RECOMP_EXPORT u8 NS_SetSendRate(u32 hz) {
+    return NetworkSyncSetSendRate(hz);
+}
+
+RECOMP_EXPORT u8 NS_SetActorSendRate(const char *actorID, u32 hz) {
+    return NetworkSyncSetActorSendRate(actorID, hz);
 }
 
 RECOMP_EXPORT u32 NS_GetRemoteActorData(const char *playerID, void* dataBuffer) {
-    return NetworkSyncGetRemoteActorData(playerID, dataBuffer);
+    return NetworkSyncGetRemoteActorData("", playerID, dataBuffer);
+}
+
+RECOMP_EXPORT u32 NS_GetRemoteActorDataFrom(const char *ownerID, const char *actorID, void* dataBuffer) {
+    return NetworkSyncGetRemoteActorData(ownerID, actorID, dataBuffer);
+}
+
+RECOMP_EXPORT u32 NS_GetRemoteActorDataInterpolated(const char *playerID, void* dataBuffer) {
+    return NetworkSyncGetRemoteActorDataInterpolated("", playerID, dataBuffer);
+}
+
+RECOMP_EXPORT u32 NS_GetRemoteActorDataInterpolatedFrom(const char *ownerID, const char *actorID, void* dataBuffer) {
+    return NetworkSyncGetRemoteActorDataInterpolated(ownerID, actorID, dataBuffer);
+}
+
+RECOMP_EXPORT u8 NS_SetInterpolationDelay(u32 delayMs) {
+    return NetworkSyncSetInterpolationDelay(delayMs);
+}
+
+RECOMP_EXPORT u8 NS_SetMaxExtrapolation(u32 maxMs) {
+    return NetworkSyncSetMaxExtrapolation(maxMs);
+}
+
+RECOMP_EXPORT u8 NS_SetStalenessPolicy(u32 hideAfterMs, u32 removeAfterMs) {
+    return NetworkSyncSetStalenessPolicy(hideAfterMs, removeAfterMs);
+}
+
+RECOMP_EXPORT u32 NS_GetRemoteActorAge(const char *playerID) {
+    return NetworkSyncGetRemoteActorAge("", playerID);
+}
+
+RECOMP_EXPORT u32 NS_GetRemoteActorAgeFrom(const char *ownerID, const char *actorID) {
+    return NetworkSyncGetRemoteActorAge(ownerID, actorID);
+}
+
+RECOMP_EXPORT u8 NS_IsRemoteActorStale(const char *playerID) {
+    return NetworkSyncIsRemoteActorStale("", playerID);
+}
+
+RECOMP_EXPORT u8 NS_IsRemoteActorStaleFrom(const char *ownerID, const char *actorID) {
+    return NetworkSyncIsRemoteActorStale(ownerID, actorID);
+}
+
+// MARK: - Presence API
+
+RECOMP_EXPORT u8 NS_SetAdjacentScenes(u32 scene, const u16* scenes, u32 count) {
+    return NetworkSyncSetAdjacentScenes(scene, scenes, count);
+}
+
+RECOMP_EXPORT u8 NS_GetPlayerPresence(const char* playerId, PlayerPresence* presence) {
+    return NetworkSyncGetPlayerPresence(playerId, presence);
+}
+
+// MARK: - Flag Sync API
+
+RECOMP_EXPORT u8 NS_ShareFlags(const char* name, void* addr, u32 size, u32 policy) {
+    return NetworkSyncShareFlags(name, addr, size, policy);
+}
+
+RECOMP_EXPORT u8 NS_UnshareFlags(const char* name) {
+    return NetworkSyncUnshareFlags(name);
+}
+
+RECOMP_EXPORT u8 NS_ShareWeekEventFlags(u32 policy) {
+    return FlagSyncShareWeekEvents(policy);
+}
+
+RECOMP_EXPORT u8 NS_ShareSceneFlags(u32 policy) {
+    return FlagSyncShareSceneFlags(policy);
+}
+
+RECOMP_EXPORT u8 NS_ShareOwlStatues(u32 policy) {
+    return FlagSyncShareOwlStatues(policy);
+}
+
+// MARK: - Inventory Sync API
+
+RECOMP_EXPORT u8 NS_SetInventoryMode(u32 category, u32 mode) {
+    return NetworkSyncSetInventoryMode(category, mode);
+}
+
+// MARK: - Time Sync API
+
+RECOMP_EXPORT u8 NS_SetTimeSync(u8 enabled) {
+    return NetworkSyncSetTimeSync(enabled);
+}
+
+RECOMP_EXPORT u8 NS_ProposeTimeTransition(u32 kind, char* idBuffer, u32 idBufferSize) {
+    return NetworkSyncProposeTimeTransition(kind, idBuffer, idBufferSize);
+}
+
+RECOMP_EXPORT u8 NS_RespondTimeTransition(const char* id, u8 accept) {
+    return NetworkSyncRespondTimeTransition(id, accept);
+}
+
+RECOMP_EXPORT u32 NS_GetTimeTransitionKind(const char* id) {
+    return NetworkSyncGetTimeTransitionKind(id);
 }
 
 // MARK: - Message System API
@@ -47,6 +245,26 @@ RECOMP_EXPORT u8 NS_RegisterMessageHandler(const char* messageId, u32 payloadSiz
     return MessageSystemRegisterHandler(messageId, payloadSize, callback);
 }
 
+RECOMP_EXPORT u8 NS_SetMessageEcho(const char* messageId, u8 echo) {
+    return MessageSystemSetEcho(messageId, echo);
+}
+
 RECOMP_EXPORT u8 NS_EmitMessage(const char* messageId, void* data) {
     return MessageSystemEmit(messageId, data);
 }
+
+RECOMP_EXPORT u8 NS_EmitMessageTo(const char* targetClientId, const char* messageId, void* data) {
+    return MessageSystemEmitTo(&targetClientId, 1, messageId, data);
+}
+
+RECOMP_EXPORT u32 NS_EmitReliableMessage(const char* messageId, void* data) {
+    return MessageSystemEmitReliable(messageId, data);
+}
+
+RECOMP_EXPORT u32 NS_GetMessageDeliveryStatus(u32 handle) {
+    return NetworkSyncGetDeliveryStatus(handle);
+}
+
+RECOMP_EXPORT u8 NS_EmitMessageToMany(const char** targetClientIds, u32 targetCount, const char* messageId, void* data) {
+    return MessageSystemEmitTo(targetClientIds, targetCount, messageId, data);
+}
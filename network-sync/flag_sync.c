@@ -0,0 +1,25 @@
+#include "flag_sync.h"
+#include "network_core.h"
+
+// MARK: - Flag Sync Implementation
+
+u8 FlagSyncShareWeekEvents(u32 policy) {
+    return NetworkSyncShareFlags("week_events", gSaveContext.save.saveInfo.weekEventReg,
+                                 sizeof(gSaveContext.save.saveInfo.weekEventReg), policy);
+}
+
+// Chest, switch, collectible and cleared room flags of every scene. The game
+// copies the current scene's flags here when leaving it.
+u8 FlagSyncShareSceneFlags(u32 policy) {
+    return NetworkSyncShareFlags("scenes", gSaveContext.save.saveInfo.permanentSceneFlags,
+                                 sizeof(gSaveContext.save.saveInfo.permanentSceneFlags), policy);
+}
+
+u8 FlagSyncShareOwlStatues(u32 policy) {
+    return NetworkSyncShareFlags("owl_statues", &gSaveContext.save.saveInfo.playerData.owlActivationFlags,
+                                 sizeof(gSaveContext.save.saveInfo.playerData.owlActivationFlags), policy);
+}
+
+void FlagSyncUpdate() {
+    NetworkSyncSyncFlags();
+}
@@ -0,0 +1,13 @@
+#ifndef FLAG_SYNC_H
+#define FLAG_SYNC_H
+
+#include "global.h"
+
+// MARK: - Flag Sync API
+
+u8 FlagSyncShareWeekEvents(u32 policy);
+u8 FlagSyncShareSceneFlags(u32 policy);
+u8 FlagSyncShareOwlStatues(u32 policy);
+void FlagSyncUpdate();
+
+#endif // FLAG_SYNC_H
@@ -0,0 +1,35 @@
+#include "inventory_sync.h"
+#include "network_core.h"
+
+#include <string.h>
+
+static InventorySyncState gInventorySyncState;
+
+// MARK: - Inventory Sync Implementation
+
+void InventorySyncUpdate() {
+    InventorySyncState* state = &gInventorySyncState;
+
+    memcpy(state->items, gSaveContext.save.saveInfo.inventory.items, sizeof(state->items));
+    memcpy(state->masks, &gSaveContext.save.saveInfo.inventory.items[24], sizeof(state->masks));
+    state->upgrades = gSaveContext.save.saveInfo.inventory.upgrades;
+    state->questItems = gSaveContext.save.saveInfo.inventory.questItems;
+    state->rupees = gSaveContext.save.saveInfo.playerData.rupees;
+    state->healthCapacity = gSaveContext.save.saveInfo.playerData.healthCapacity;
+
+    if (!NetworkSyncSyncInventory(state)) {
+        return;
+    }
+
+    // Other players changed something
+    memcpy(gSaveContext.save.saveInfo.inventory.items, state->items, sizeof(state->items));
+    memcpy(&gSaveContext.save.saveInfo.inventory.items[24], state->masks, sizeof(state->masks));
+    gSaveContext.save.saveInfo.inventory.upgrades = state->upgrades;
+    gSaveContext.save.saveInfo.inventory.questItems = state->questItems;
+    gSaveContext.save.saveInfo.playerData.rupees = state->rupees;
+    gSaveContext.save.saveInfo.playerData.healthCapacity = state->healthCapacity;
+
+    if (gSaveContext.save.saveInfo.playerData.health > state->healthCapacity) {
+        gSaveContext.save.saveInfo.playerData.health = state->healthCapacity;
+    }
+}
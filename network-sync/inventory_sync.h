@@ -0,0 +1,21 @@
+#ifndef INVENTORY_SYNC_H
+#define INVENTORY_SYNC_H
+
+#include "global.h"
+
+// The inventory as handed to the runtime each frame. Layout must match
+// `InventoryState` in the runtime.
+typedef struct {
+    /* 0x00 */ u8 items[24];
+    /* 0x18 */ u8 masks[24];
+    /* 0x30 */ u32 upgrades;
+    /* 0x34 */ u32 questItems;
+    /* 0x38 */ s16 rupees;
+    /* 0x3A */ s16 healthCapacity;
+} InventorySyncState; // size = 0x3C
+
+// MARK: - Inventory Sync API
+
+void InventorySyncUpdate();
+
+#endif // INVENTORY_SYNC_H
@@ -8,6 +8,7 @@
 typedef struct {
     char message_id[64];          // String identifier for the message
     u32 payload_size;             // Expected payload size
+    u8 echo;                      // Also deliver our own emits back to us
     void (*callback)(void* data); // Callback function
 } MessageCallback;
 
@@ -47,29 +48,99 @@ u8 MessageSystemRegisterHandler(const char* messageId, u32 payloadSize, void* ca
     gMessageCallbacks[gMessageCallbackCount].message_id[sizeof(gMessageCallbacks[0].message_id) - 1] = '\0'; // Ensure null termination
     gMessageCallbacks[gMessageCallbackCount].payload_size = payloadSize;
     gMessageCallbacks[gMessageCallbackCount].callback = callback;
+    gMessageCallbacks[gMessageCallbackCount].echo = 0;
     gMessageCallbackCount++;
 
     recomp_printf("Registered message handler for '%s' with payload size %u\n", messageId, payloadSize);
     return 0;
 }
 
+u8 MessageSystemSetEcho(const char* messageId, u8 echo) {
+    for (u32 i = 0; i < gMessageCallbackCount; i++) {
+        if (strcmp(gMessageCallbacks[i].message_id, messageId) == 0) {
+            gMessageCallbacks[i].echo = echo;
+            return 0;
+        }
+    }
+
+    recomp_printf("Error: Cannot set echo on unregistered message type '%s'\n", messageId);
+    return 1;
+}
+
 u8 MessageSystemEmit(const char* messageId, void* data) {
     // Find the registered size for this message type
     u32 size = 0;
+    u8 echo = 0;
     for (u32 i = 0; i < gMessageCallbackCount; i++) {
         if (strcmp(gMessageCallbacks[i].message_id, messageId) == 0) {
             size = gMessageCallbacks[i].payload_size;
+            echo = gMessageCallbacks[i].echo;
             break;
         }
     }
 
     if (size == 0) {
         recomp_printf("Warning: Emitting unregistered message type '%s'\n", messageId);
-        return 1;
+        return NS_SEND_FAILED;
+    }
+
+    // Queue the message for the server to broadcast
+    return NetworkSyncEmitMessage(messageId, size, data, echo);
+}
+
+u8 MessageSystemEmitTo(const char** targetIds, u32 targetCount, const char* messageId, void* data) {
+    // Find the registered size for this message type
+    u32 size = 0;
+    for (u32 i = 0; i < gMessageCallbackCount; i++) {
+        if (strcmp(gMessageCallbacks[i].message_id, messageId) == 0) {
+            size = gMessageCallbacks[i].payload_size;
+            break;
+        }
+    }
+
+    if (size == 0) {
+        recomp_printf("Warning: Emitting unregistered message type '%s'\n", messageId);
+        return NS_SEND_FAILED;
+    }
+
+    if (targetCount == 0) {
+        recomp_printf("Warning: Emitting '%s' with no targets\n", messageId);
+        return NS_SEND_FAILED;
+    }
+
+    // The runtime takes the targets as one comma separated list
+    char* targets = recomp_alloc(targetCount * 64);
+    targets[0] = '\0';
+    for (u32 i = 0; i < targetCount; i++) {
+        if (i > 0) {
+            strcat(targets, ",");
+        }
+        strncat(targets, targetIds[i], 63);
+    }
+
+    // Queue the message for the server to deliver to the targets only
+    u8 result = NetworkSyncEmitMessageTo(targets, messageId, size, data);
+    recomp_free(targets);
+    return result;
+}
+
+u32 MessageSystemEmitReliable(const char* messageId, void* data) {
+    // Find the registered size for this message type
+    u32 size = 0;
+    for (u32 i = 0; i < gMessageCallbackCount; i++) {
+        if (strcmp(gMessageCallbacks[i].message_id, messageId) == 0) {
+            size = gMessageCallbacks[i].payload_size;
+            break;
+        }
+    }
+
+    if (size == 0) {
+        recomp_printf("Warning: Emitting unregistered message type '%s'\n", messageId);
+        return 0;
     }
 
-    // Send the message to the server for broadcasting
-    return NetworkSyncEmitMessage(messageId, size, data);
+    // Returns a delivery handle, or 0 if it couldn't be sent
+    return NetworkSyncEmitReliableMessage(messageId, size, data);
 }
 
 void MessageSystemProcessPending() {
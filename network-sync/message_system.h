@@ -6,7 +6,10 @@
 // MARK: - Message System API
 
 u8 MessageSystemRegisterHandler(const char* messageId, u32 payloadSize, void* callback);
+u8 MessageSystemSetEcho(const char* messageId, u8 echo);
 u8 MessageSystemEmit(const char* messageId, void* data);
+u8 MessageSystemEmitTo(const char** targetIds, u32 targetCount, const char* messageId, void* data);
+u32 MessageSystemEmitReliable(const char* messageId, void* data);
 void MessageSystemProcessPending();
 
 #endif // MESSAGE_SYSTEM_H 
\ No newline at end of file
@@ -4,17 +4,121 @@
 #include "modding.h"
 #include <stdint.h>
 
+// MARK: - Send Status Codes
+
+// Returned by functions that queue data for the server
+#define NS_SEND_FAILED 0
+#define NS_SEND_QUEUED 1
+#define NS_SEND_QUEUE_FULL 2
+
+// Join results, returned when waiting on the server's answer
+#define NS_JOIN_OK 3
+#define NS_JOIN_TIMED_OUT 4
+#define NS_JOIN_SESSION_FULL 5
+#define NS_JOIN_WRONG_PASSWORD 6
+#define NS_JOIN_BANNED 7
+#define NS_JOIN_VERSION_MISMATCH 8
+#define NS_JOIN_INVALID_SESSION 9
+#define NS_JOIN_REJECTED 10
+#define NS_JOIN_LAYOUT_MISMATCH 12
+
+// Returned by NS_Connect when the server refuses this runtime's build
+#define NS_CONNECT_INCOMPATIBLE 11
+
+// Delivery status of a reliable message
+#define NS_DELIVERY_UNKNOWN 0
+#define NS_DELIVERY_PENDING 1
+#define NS_DELIVERY_DELIVERED 2
+#define NS_DELIVERY_FAILED 3
+
+// Field types for sync schemas
+#define NS_FIELD_U8 1
+#define NS_FIELD_S8 2
+#define NS_FIELD_U16 3
+#define NS_FIELD_S16 4
+#define NS_FIELD_U32 5
+#define NS_FIELD_S32 6
+#define NS_FIELD_F32 7
+#define NS_FIELD_VEC3F 8
+#define NS_FIELD_VEC3S 9
+
+// Merge policies for shared flag regions
+#define NS_MERGE_OR 1
+#define NS_MERGE_LAST_WRITER_WINS 2
+#define NS_MERGE_HOST 3
+
+// Inventory categories that can be synced separately
+#define NS_INVENTORY_ITEMS 1
+#define NS_INVENTORY_MASKS 2
+#define NS_INVENTORY_UPGRADES 3
+#define NS_INVENTORY_QUEST 4
+#define NS_INVENTORY_RUPEES 5
+#define NS_INVENTORY_HEARTS 6
+
+// How an inventory category is synced
+#define NS_INVENTORY_PRIVATE 0
+#define NS_INVENTORY_MIRRORED 1
+#define NS_INVENTORY_SHARED 2
+
+// Time transitions every member votes on; mods may use other kinds too
+#define NS_TIME_SONG_OF_TIME 1
+#define NS_TIME_INVERTED_SONG_OF_TIME 2
+#define NS_TIME_SONG_OF_DOUBLE_TIME 3
+
 // MARK: - Network Core Imports
 
 RECOMP_IMPORT(".", void NetworkSyncInit());
 RECOMP_IMPORT(".", u8 NetworkSyncConnect(const char* host));
-RECOMP_IMPORT(".", u8 NetworkSyncJoinSession(const char* session));
+RECOMP_IMPORT(".", u8 NetworkSyncGetConnectRejectReason(char* buffer, u32 bufferSize));
+RECOMP_IMPORT(".", u8 NetworkSyncSetReconnectPolicy(u32 maxAttempts, u32 initialDelayMs, u32 maxDelayMs));
+RECOMP_IMPORT(".", u32 NetworkSyncGetConnectionState());
+RECOMP_IMPORT(".", u32 NetworkSyncGetDroppedMessageCount());
+RECOMP_IMPORT(".", u32 NetworkSyncPollEvent(char* idBuffer, u32 idBufferSize));
+RECOMP_IMPORT(".", u8 NetworkSyncJoinSession(const char* session, const char* password, u32 maxPlayers, u32 timeoutMs));
 RECOMP_IMPORT(".", u8 NetworkSyncLeaveSession());
+RECOMP_IMPORT(".", u8 NetworkSyncBanMember(const char* memberId));
+RECOMP_IMPORT(".", u8 NetworkSyncSetSessionDetails(const char* displayName, const char* gameVersion, u32 unlisted));
+RECOMP_IMPORT(".", u8 NetworkSyncRequestSessionList());
+RECOMP_IMPORT(".", u32 NetworkSyncListSessions(u32 maxSessions, char* idsBuffer, u32 idBufferSize));
+RECOMP_IMPORT(".", u8 NetworkSyncGetSessionDisplayName(const char* session, char* buffer, u32 bufferSize));
+RECOMP_IMPORT(".", u8 NetworkSyncGetSessionGameVersion(const char* session, char* buffer, u32 bufferSize));
+RECOMP_IMPORT(".", u32 NetworkSyncGetSessionMemberCount(const char* session));
+RECOMP_IMPORT(".", u32 NetworkSyncGetSessionCapacity(const char* session));
+RECOMP_IMPORT(".", u8 NetworkSyncSetSessionState(const char* key, u32 size, void* data));
+RECOMP_IMPORT(".", u32 NetworkSyncGetSessionState(const char* key, void* buffer, u32 bufferSize));
 RECOMP_IMPORT(".", u8 NetworkSyncGetClientId(char* buffer, u32 bufferSize));
-RECOMP_IMPORT(".", void NetworkSyncEmitActorData(void* data));
-RECOMP_IMPORT(".", u32 NetworkSyncGetRemoteActorIDs(u32 maxPlayers, char* idsBuffer, u32 idBufferSize));
-RECOMP_IMPORT(".", u32 NetworkSyncGetRemoteActorData(const char* actor_id, void* dataBuffer));
-RECOMP_IMPORT(".", u8 NetworkSyncEmitMessage(const char* messageId, u32 size, void* data));
+RECOMP_IMPORT(".", u8 NetworkSyncEmitActorData(const char* actorId, void* data));
+RECOMP_IMPORT(".", u32 NetworkSyncGetRemoteActorIDs(u32 maxPlayers, char* ownerIdsBuffer, char* idsBuffer, u32 idBufferSize));
+RECOMP_IMPORT(".", u8 NetworkSyncRegisterSchema(const char* schema));
+RECOMP_IMPORT(".", u8 NetworkSyncAddSchemaField(const char* schema, u32 offset, u32 fieldType, u32 count));
+RECOMP_IMPORT(".", u8 NetworkSyncEmitActorState(const char* actorId, const char* schema, void* actor));
+RECOMP_IMPORT(".", u8 NetworkSyncGetRemoteActorState(const char* ownerId, const char* actorId, const char* schema, void* actor));
+RECOMP_IMPORT(".", u8 NetworkSyncSetSendRate(u32 hz));
+RECOMP_IMPORT(".", u8 NetworkSyncSetActorSendRate(const char* actorId, u32 hz));
+RECOMP_IMPORT(".", u32 NetworkSyncGetRemoteActorData(const char* owner_id, const char* actor_id, void* dataBuffer));
+RECOMP_IMPORT(".", u32 NetworkSyncGetRemoteActorDataInterpolated(const char* owner_id, const char* actor_id, void* dataBuffer));
+RECOMP_IMPORT(".", u8 NetworkSyncSetInterpolationDelay(u32 delayMs));
+RECOMP_IMPORT(".", u8 NetworkSyncSetMaxExtrapolation(u32 maxMs));
+RECOMP_IMPORT(".", u8 NetworkSyncSetStalenessPolicy(u32 hideAfterMs, u32 removeAfterMs));
+RECOMP_IMPORT(".", u32 NetworkSyncGetRemoteActorAge(const char* owner_id, const char* actor_id));
+RECOMP_IMPORT(".", u8 NetworkSyncIsRemoteActorStale(const char* owner_id, const char* actor_id));
+RECOMP_IMPORT(".", u8 NetworkSyncSetPresence(u32 scene, s32 room, u32 entrance));
+RECOMP_IMPORT(".", u8 NetworkSyncSetAdjacentScenes(u32 scene, const u16* scenes, u32 count));
+RECOMP_IMPORT(".", u8 NetworkSyncGetPlayerPresence(const char* playerId, void* presence));
+RECOMP_IMPORT(".", u8 NetworkSyncShareFlags(const char* name, void* addr, u32 size, u32 policy));
+RECOMP_IMPORT(".", u8 NetworkSyncUnshareFlags(const char* name));
+RECOMP_IMPORT(".", u8 NetworkSyncSyncFlags());
+RECOMP_IMPORT(".", u8 NetworkSyncSetInventoryMode(u32 category, u32 mode));
+RECOMP_IMPORT(".", u8 NetworkSyncSyncInventory(void* state));
+RECOMP_IMPORT(".", u8 NetworkSyncSetTimeSync(u32 enabled));
+RECOMP_IMPORT(".", u8 NetworkSyncSyncTime(void* state));
+RECOMP_IMPORT(".", u8 NetworkSyncProposeTimeTransition(u32 kind, char* idBuffer, u32 idBufferSize));
+RECOMP_IMPORT(".", u8 NetworkSyncRespondTimeTransition(const char* id, u32 accept));
+RECOMP_IMPORT(".", u32 NetworkSyncGetTimeTransitionKind(const char* id));
+RECOMP_IMPORT(".", u8 NetworkSyncEmitMessage(const char* messageId, u32 size, void* data, u32 echo));
+RECOMP_IMPORT(".", u8 NetworkSyncEmitMessageTo(const char* targets, const char* messageId, u32 size, void* data));
+RECOMP_IMPORT(".", u32 NetworkSyncEmitReliableMessage(const char* messageId, u32 size, void* data));
+RECOMP_IMPORT(".", u32 NetworkSyncGetDeliveryStatus(u32 handle));
 RECOMP_IMPORT(".", u32 NetworkSyncGetPendingMessageSize());
 RECOMP_IMPORT(".", u8 NetworkSyncGetMessage(void* buffer, u32 bufferSize, char* messageIdBuffer));
 
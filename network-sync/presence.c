@@ -0,0 +1,26 @@
+#include "presence.h"
+#include "network_core.h"
+
+// What we last told the runtime, so it only hears about changes
+static PlayerPresence gPublishedPresence;
+static u8 gPresencePublished = false;
+
+// MARK: - Presence Implementation
+
+void PresenceUpdate(PlayState* play) {
+    PlayerPresence presence;
+    presence.scene = play->sceneId;
+    presence.room = play->roomCtx.curRoom.num;
+    presence.entrance = gSaveContext.save.entrance;
+
+    if (gPresencePublished && presence.scene == gPublishedPresence.scene &&
+        presence.room == gPublishedPresence.room && presence.entrance == gPublishedPresence.entrance) {
+        return;
+    }
+
+    // Tried again next frame if it didn't go out
+    if (NetworkSyncSetPresence(presence.scene, presence.room, presence.entrance)) {
+        gPublishedPresence = presence;
+        gPresencePublished = true;
+    }
+}
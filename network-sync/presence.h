@@ -0,0 +1,17 @@
+#ifndef PRESENCE_H
+#define PRESENCE_H
+
+#include "global.h"
+
+// Where a player is in the game
+typedef struct {
+    /* 0x00 */ u32 scene;
+    /* 0x04 */ s32 room; // -1 between rooms
+    /* 0x08 */ u32 entrance;
+} PlayerPresence; // size = 0xC
+
+// MARK: - Internal API (used by callbacks)
+
+void PresenceUpdate(PlayState* play);
+
+#endif // PRESENCE_H
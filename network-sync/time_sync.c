@@ -0,0 +1,23 @@
+#include "time_sync.h"
+#include "network_core.h"
+
+static TimeSyncState gTimeSyncState;
+
+// MARK: - Time Sync Implementation
+
+void TimeSyncUpdate() {
+    TimeSyncState* state = &gTimeSyncState;
+
+    state->day = gSaveContext.save.day;
+    state->time = gSaveContext.save.time;
+    state->timeSpeedOffset = gSaveContext.save.timeSpeedOffset;
+
+    if (!NetworkSyncSyncTime(state)) {
+        return;
+    }
+
+    // Following the host's clock
+    gSaveContext.save.day = state->day;
+    gSaveContext.save.time = state->time;
+    gSaveContext.save.timeSpeedOffset = state->timeSpeedOffset;
+}
@@ -0,0 +1,18 @@
+#ifndef TIME_SYNC_H
+#define TIME_SYNC_H
+
+#include "global.h"
+
+// The clock as handed to the runtime each frame. Layout must match
+// `TimeState` in the runtime.
+typedef struct {
+    /* 0x00 */ s32 day;
+    /* 0x04 */ u32 time;
+    /* 0x08 */ s32 timeSpeedOffset;
+} TimeSyncState; // size = 0xC
+
+// MARK: - Internal API (used by callbacks)
+
+void TimeSyncUpdate();
+
+#endif // TIME_SYNC_H